@@ -1,3 +1,3 @@
 """Utility scripts for document importers."""
 
-__all__ = ['import_epub', 'import_pdf']
+__all__ = ['import_pdf']
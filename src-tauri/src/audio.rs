@@ -1,7 +1,7 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use serde::{Deserialize, Serialize};
-use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::fs;
+use std::path::Path;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
@@ -16,35 +16,171 @@ impl Default for AudioFormat {
     }
 }
 
-pub fn transcode_wav_to_mp3(path: &Path) -> Result<PathBuf> {
-    let wav_path = path;
-    let mp3_path = path.with_extension("mp3");
-    if wav_path == mp3_path {
-        return Ok(mp3_path);
-    }
-    let status = Command::new("ffmpeg")
-        .arg("-y")
-        .arg("-i")
-        .arg(&wav_path)
-        .arg(&mp3_path)
-        .status()
-        .context("failed to spawn ffmpeg")?;
-    if !status.success() {
-        anyhow::bail!("ffmpeg exited with status {status}");
-    }
-    Ok(mp3_path)
-}
-
-pub fn generate_output_path(base: &Path, title: &str, format: AudioFormat) -> PathBuf {
-    let sanitized = title
-        .chars()
-        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, ' ' | '_' | '-'))
-        .collect::<String>()
-        .trim()
-        .replace(' ', "_");
-    let extension = match format {
-        AudioFormat::Wav => "wav",
-        AudioFormat::Mp3 => "mp3",
-    };
-    base.join(format!("{sanitized}.{extension}"))
+impl AudioFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            AudioFormat::Wav => "wav",
+            AudioFormat::Mp3 => "mp3",
+        }
+    }
+}
+
+/// 16-bit PCM audio, the only sample format Piper produces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WavAudio {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub samples: Vec<i16>,
+}
+
+impl WavAudio {
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        Self {
+            sample_rate,
+            channels,
+            samples: Vec::new(),
+        }
+    }
+
+    pub fn duration_ms(&self) -> u64 {
+        let frames = self.samples.len() as u64 / u64::from(self.channels.max(1));
+        frames * 1000 / u64::from(self.sample_rate.max(1))
+    }
+
+    /// Start and end (in ms) of the part of the clip louder than background
+    /// noise, used to keep estimated word timings out of pauses.
+    pub fn voiced_range_ms(&self) -> (u64, u64) {
+        const THRESHOLD: i16 = 500;
+        let channels = usize::from(self.channels.max(1));
+        let rate = u64::from(self.sample_rate.max(1));
+        let first = self.samples.iter().position(|s| s.saturating_abs() > THRESHOLD);
+        let last = self.samples.iter().rposition(|s| s.saturating_abs() > THRESHOLD);
+        match (first, last) {
+            (Some(first), Some(last)) => (
+                (first / channels) as u64 * 1000 / rate,
+                (last / channels + 1) as u64 * 1000 / rate,
+            ),
+            _ => (0, self.duration_ms()),
+        }
+    }
+
+    fn samples_for_ms(&self, ms: u64) -> usize {
+        let frames = ms * u64::from(self.sample_rate) / 1000;
+        frames as usize * usize::from(self.channels.max(1))
+    }
+
+    /// The part of the clip between `start_ms` and `end_ms`.
+    pub fn slice_ms(&self, start_ms: u64, end_ms: u64) -> WavAudio {
+        let start = self.samples_for_ms(start_ms).min(self.samples.len());
+        let end = self.samples_for_ms(end_ms).clamp(start, self.samples.len());
+        WavAudio {
+            samples: self.samples[start..end].to_vec(),
+            ..*self
+        }
+    }
+
+    pub fn push_silence_ms(&mut self, ms: u64) {
+        let len = self.samples.len() + self.samples_for_ms(ms);
+        self.samples.resize(len, 0);
+    }
+
+    pub fn append(&mut self, other: &WavAudio) -> Result<()> {
+        if other.sample_rate != self.sample_rate || other.channels != self.channels {
+            bail!(
+                "cannot join {} Hz/{} ch audio onto {} Hz/{} ch audio",
+                other.sample_rate,
+                other.channels,
+                self.sample_rate,
+                self.channels
+            );
+        }
+        self.samples.extend_from_slice(&other.samples);
+        Ok(())
+    }
+}
+
+pub fn read_wav(path: &Path) -> Result<WavAudio> {
+    let bytes = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    parse_wav(&bytes).with_context(|| format!("invalid WAV file {}", path.display()))
+}
+
+pub fn parse_wav(bytes: &[u8]) -> Result<WavAudio> {
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        bail!("missing RIFF/WAVE header");
+    }
+    let mut format = None;
+    let mut offset = 12;
+    while offset + 8 <= bytes.len() {
+        let id = &bytes[offset..offset + 4];
+        let size = u32::from_le_bytes(bytes[offset + 4..offset + 8].try_into()?) as usize;
+        let body = &bytes[offset + 8..(offset + 8 + size).min(bytes.len())];
+        match id {
+            b"fmt " if body.len() >= 16 => {
+                let tag = u16::from_le_bytes([body[0], body[1]]);
+                let channels = u16::from_le_bytes([body[2], body[3]]);
+                let sample_rate = u32::from_le_bytes(body[4..8].try_into()?);
+                let bits = u16::from_le_bytes([body[14], body[15]]);
+                if !matches!(tag, 1 | 0xFFFE) || bits != 16 {
+                    bail!("unsupported WAV encoding (format {tag}, {bits} bits)");
+                }
+                format = Some((sample_rate, channels));
+            }
+            b"data" => {
+                let (sample_rate, channels) = format.context("data chunk before fmt chunk")?;
+                let samples = body
+                    .chunks_exact(2)
+                    .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+                    .collect();
+                return Ok(WavAudio {
+                    sample_rate,
+                    channels,
+                    samples,
+                });
+            }
+            _ => {}
+        }
+        offset += 8 + size + (size & 1);
+    }
+    bail!("missing data chunk")
+}
+
+pub fn encode_wav(audio: &WavAudio) -> Vec<u8> {
+    let data_len = (audio.samples.len() * 2) as u32;
+    let mut bytes = Vec::with_capacity(44 + data_len as usize);
+    bytes.extend(wav_header(audio.sample_rate, audio.channels, data_len));
+    bytes.extend(encode_pcm(audio));
+    bytes
+}
+
+/// Header for a WAV file whose length is not known yet, as sent before
+/// streamed audio. Players read the data until the stream ends.
+pub fn streaming_wav_header(sample_rate: u32, channels: u16) -> Vec<u8> {
+    wav_header(sample_rate, channels, u32::MAX - 36)
+}
+
+fn wav_header(sample_rate: u32, channels: u16, data_len: u32) -> Vec<u8> {
+    let block_align = channels * 2;
+    let mut bytes = Vec::with_capacity(44);
+    bytes.extend_from_slice(b"RIFF");
+    bytes.extend_from_slice(&(36 + data_len).to_le_bytes());
+    bytes.extend_from_slice(b"WAVEfmt ");
+    bytes.extend_from_slice(&16u32.to_le_bytes());
+    bytes.extend_from_slice(&1u16.to_le_bytes());
+    bytes.extend_from_slice(&channels.to_le_bytes());
+    bytes.extend_from_slice(&sample_rate.to_le_bytes());
+    bytes.extend_from_slice(&(sample_rate * u32::from(block_align)).to_le_bytes());
+    bytes.extend_from_slice(&block_align.to_le_bytes());
+    bytes.extend_from_slice(&16u16.to_le_bytes());
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&data_len.to_le_bytes());
+    bytes
+}
+
+/// The samples as 16-bit little-endian PCM, without a header.
+pub fn encode_pcm(audio: &WavAudio) -> Vec<u8> {
+    audio.samples.iter().flat_map(|sample| sample.to_le_bytes()).collect()
+}
+
+pub fn write_wav(path: &Path, audio: &WavAudio) -> Result<()> {
+    fs::write(path, encode_wav(audio)).with_context(|| format!("failed to write {}", path.display()))
 }
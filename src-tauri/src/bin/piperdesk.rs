@@ -0,0 +1,533 @@
+//! Headless front end to the PiperDesk synthesis core, for scripts and build
+//! servers. Text and documents go in through arguments or stdin; audio goes to
+//! files or stdout, and `--json` makes every command print machine-readable
+//! results.
+
+use anyhow::{bail, Context, Result};
+use clap::{Args, Parser, Subcommand};
+use futures::StreamExt;
+use piperdesk::audio::AudioFormat;
+use piperdesk::captions::{write_captions, CaptionOptions};
+use piperdesk::dict::{self, PronunciationDictionary};
+use piperdesk::importers;
+use piperdesk::importers::text::TextEncoding;
+use piperdesk::queue::{self, EnqueueDocumentOptions, ImportFolderOptions, NewQueueItem};
+use piperdesk::retry;
+use piperdesk::{server, wyoming};
+use piperdesk::{AppConfig, QueueItem, SharedState};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io::{IsTerminal, Read, Write};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+use uuid::Uuid;
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "piperdesk",
+    version,
+    about = "Synthesize speech with Piper without the desktop window"
+)]
+struct Cli {
+    /// Piper executable; defaults to $PIPER_BIN or `piper` on the PATH.
+    #[arg(long, global = true)]
+    piper: Option<PathBuf>,
+    /// Directory with `.onnx` voice models; defaults to $PIPER_VOICES.
+    #[arg(long, global = true)]
+    voices_dir: Option<PathBuf>,
+    /// espeak-ng executable for voices named `espeak:<language>`; defaults
+    /// to $ESPEAK_BIN or `espeak-ng` on the PATH.
+    #[arg(long, global = true)]
+    espeak: Option<PathBuf>,
+    /// Pronunciation dictionary applied to all synthesized text.
+    #[arg(long, global = true)]
+    dict: Option<PathBuf>,
+    /// Print results as JSON instead of text.
+    #[arg(long, global = true)]
+    json: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// List installed voices.
+    Voices,
+    /// Synthesize text into one audio file or stdout.
+    Synth(SynthArgs),
+    /// Extract the speakable text of a document.
+    Import {
+        path: PathBuf,
+        /// Encoding of plain-text files, e.g. `windows-1252`; detected when omitted.
+        #[arg(long, value_parser = parse_serde::<TextEncoding>)]
+        encoding: Option<TextEncoding>,
+    },
+    /// Work through a list of queue items.
+    #[command(subcommand)]
+    Queue(QueueCommand),
+    /// Import documents or folders and synthesize one file per chapter.
+    Export(ExportArgs),
+    /// Edit or apply the pronunciation dictionary.
+    #[command(subcommand)]
+    Dict(DictCommand),
+    /// Serve the OpenAI-compatible speech API on 127.0.0.1.
+    Serve {
+        #[arg(long)]
+        port: Option<u16>,
+        /// Token clients send as `Authorization: Bearer`; defaults to
+        /// $PIPERDESK_API_TOKEN or a random one.
+        #[arg(long)]
+        token: Option<String>,
+    },
+    /// Serve installed voices to Home Assistant over the Wyoming protocol.
+    Wyoming {
+        /// Address to listen on; `0.0.0.0` accepts other machines.
+        #[arg(long)]
+        host: Option<IpAddr>,
+        #[arg(long)]
+        port: Option<u16>,
+    },
+}
+
+#[derive(Debug, Args)]
+struct SynthArgs {
+    /// Text to speak; `-` or nothing reads stdin.
+    text: Option<String>,
+    /// Read the text from a file instead.
+    #[arg(long, conflicts_with = "text")]
+    file: Option<PathBuf>,
+    #[command(flatten)]
+    voice: VoiceArgs,
+    /// Output file; `-` writes the audio to stdout.
+    #[arg(short, long, default_value = "-")]
+    output: PathBuf,
+    /// Also write SRT and WebVTT captions next to the output file.
+    #[arg(long)]
+    captions: bool,
+    /// Stream headerless 16-bit mono PCM to stdout while Piper speaks,
+    /// e.g. into `aplay -r 22050 -f S16_LE`.
+    #[arg(long, conflicts_with_all = ["output", "captions", "format"])]
+    raw: bool,
+}
+
+#[derive(Debug, Args)]
+struct VoiceArgs {
+    /// Voice name; defaults to the configured voice or the document language.
+    #[arg(long)]
+    voice: Option<String>,
+    /// Piper length scale, e.g. 0.8 for faster speech.
+    #[arg(long)]
+    rate: Option<f32>,
+    #[arg(long, value_parser = parse_serde::<AudioFormat>)]
+    format: Option<AudioFormat>,
+}
+
+#[derive(Debug, Subcommand)]
+enum QueueCommand {
+    /// Synthesize a JSON array of `{title, text, voice?, rate?, format?}` items.
+    Run {
+        /// JSON file with the items; `-` or nothing reads stdin.
+        items: Option<PathBuf>,
+        /// Output directory; defaults to the configured export directory.
+        #[arg(long)]
+        dir: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Args)]
+struct ExportArgs {
+    /// Documents or folders of documents.
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
+    /// Output directory; defaults to the configured export directory.
+    #[arg(long)]
+    dir: Option<PathBuf>,
+    #[command(flatten)]
+    voice: VoiceArgs,
+    /// Glob of folder files to take, e.g. `**/*.md`; repeatable.
+    #[arg(long)]
+    include: Vec<String>,
+    /// Glob of folder files to skip; repeatable.
+    #[arg(long)]
+    exclude: Vec<String>,
+}
+
+#[derive(Debug, Subcommand)]
+enum DictCommand {
+    /// Print all entries.
+    List,
+    /// Add or replace the pronunciation of a word.
+    Add { word: String, spoken: String },
+    /// Delete the entry for a word.
+    Remove { word: String },
+    /// Print text with the dictionary applied; `-` or nothing reads stdin.
+    Apply { text: Option<String> },
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SynthResult {
+    path: Option<PathBuf>,
+    duration_ms: u64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RunReport {
+    items: Vec<QueueItem>,
+    errors: Vec<String>,
+}
+
+#[async_std::main]
+async fn main() -> ExitCode {
+    if std::env::var("RUST_LOG").is_err() {
+        std::env::set_var("RUST_LOG", "warn");
+    }
+    env_logger::init();
+
+    let cli = Cli::parse();
+    match run(cli).await {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("error: {err:#}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(cli: Cli) -> Result<ExitCode> {
+    let dict_path = cli.dict.clone().unwrap_or_else(dict::default_path);
+    let mut dict = PronunciationDictionary::load(&dict_path)?;
+    if let Command::Dict(command) = &cli.command {
+        return run_dict(&cli, command, &mut dict, &dict_path);
+    }
+
+    let mut config = AppConfig::default();
+    if let Some(piper) = &cli.piper {
+        config.piper.binary_path = piper.clone();
+    }
+    if let Some(voices_dir) = &cli.voices_dir {
+        config.piper.voices_dir = voices_dir.clone();
+    }
+    if let Some(espeak) = &cli.espeak {
+        config.espeak.binary_path = espeak.clone();
+    }
+    if let Command::Serve { port, token } = &cli.command {
+        config.server.port = port.unwrap_or(config.server.port);
+        config.server.token = token.clone().unwrap_or(config.server.token);
+        let server_config = config.server.clone();
+        let state = Arc::new(SharedState::new(config)?);
+        let serve = server::bind(state, &server_config).await?;
+        eprintln!(
+            "Listening on http://127.0.0.1:{}/v1 with token {}",
+            server_config.port, server_config.token
+        );
+        serve.await?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    if let Command::Wyoming { host, port } = &cli.command {
+        config.wyoming.host = host.unwrap_or(config.wyoming.host);
+        config.wyoming.port = port.unwrap_or(config.wyoming.port);
+        config.wyoming.dictionary = dict_path;
+        let wyoming_config = config.wyoming.clone();
+        let state = Arc::new(SharedState::new(config)?);
+        let serve = wyoming::bind(state, &wyoming_config).await?;
+        eprintln!(
+            "Wyoming server listening on tcp://{}:{}",
+            wyoming_config.host, wyoming_config.port
+        );
+        serve.await?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    let state = SharedState::new(config)?;
+
+    match &cli.command {
+        Command::Voices => {
+            let mut voices = state.tts.voices();
+            voices.sort_by(|a, b| a.name.cmp(&b.name));
+            if cli.json {
+                print_json(&voices)?;
+            } else {
+                for voice in voices {
+                    println!("{}\t{}", voice.name, voice.language.unwrap_or_default());
+                }
+            }
+        }
+        Command::Synth(args) => synth(&cli, &state, &dict, args).await?,
+        Command::Import { path, encoding } => {
+            let settings = state.config.lock().import_settings(None, *encoding);
+            let document = importers::import_file(path, &settings)
+                .await
+                .map_err(anyhow::Error::msg)?;
+            for warning in &document.warnings {
+                log::warn!("{warning}");
+            }
+            if cli.json {
+                print_json(&document)?;
+            } else {
+                println!("{}", document.full_text());
+            }
+        }
+        Command::Queue(QueueCommand::Run { items, dir }) => {
+            let content = read_input(items.as_deref())?;
+            let items: Vec<NewQueueItem> = serde_json::from_str(&content).context("invalid queue items")?;
+            let items = items
+                .into_iter()
+                .map(|item| NewQueueItem {
+                    text: dict.apply(&item.text),
+                    ..item
+                })
+                .collect();
+            queue::enqueue_text(&state, items).map_err(anyhow::Error::msg)?;
+            return run_queue(&cli, &state, dir.as_deref()).await;
+        }
+        Command::Export(args) => {
+            let settings = state.config.lock().import_settings(None, None);
+            let mut errors = 0;
+            for path in &args.paths {
+                if path.is_dir() {
+                    let options = ImportFolderOptions {
+                        include: args.include.clone(),
+                        exclude: args.exclude.clone(),
+                        voice: args.voice.voice.clone(),
+                        rate: args.voice.rate,
+                        format: args.voice.format,
+                    };
+                    let report = queue::import_folder(&state, path, &options, &settings)
+                        .await
+                        .map_err(anyhow::Error::msg)?;
+                    for file in report.iter().filter(|file| file.error.is_some()) {
+                        eprintln!("{}: {}", file.path.display(), file.error.as_deref().unwrap_or_default());
+                        errors += 1;
+                    }
+                } else {
+                    match importers::import_file(path, &settings).await {
+                        Ok(document) => {
+                            let options = EnqueueDocumentOptions {
+                                project: None,
+                                voice: args.voice.voice.clone(),
+                                rate: args.voice.rate,
+                                format: args.voice.format,
+                            };
+                            queue::enqueue_document(&state, document, options).map_err(anyhow::Error::msg)?;
+                        }
+                        Err(err) => {
+                            eprintln!("{}: {err}", path.display());
+                            errors += 1;
+                        }
+                    }
+                }
+            }
+            for item in state.queue.lock().iter_mut() {
+                item.text = dict.apply(&item.text);
+            }
+            let code = run_queue(&cli, &state, args.dir.as_deref()).await?;
+            return Ok(if errors > 0 { ExitCode::FAILURE } else { code });
+        }
+        Command::Dict(_) | Command::Serve { .. } | Command::Wyoming { .. } => unreachable!("handled above"),
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+async fn synth(cli: &Cli, state: &SharedState, dict: &PronunciationDictionary, args: &SynthArgs) -> Result<()> {
+    let text = match &args.file {
+        Some(file) => std::fs::read_to_string(file).with_context(|| format!("failed to read {}", file.display()))?,
+        None => text_or_stdin(args.text.as_deref())?,
+    };
+    let text = dict.apply(text.trim());
+    if text.is_empty() {
+        bail!("nothing to synthesize");
+    }
+    if args.raw {
+        return stream_raw(state, &text, &args.voice).await;
+    }
+    let to_stdout = args.output == Path::new("-");
+    if to_stdout && args.captions {
+        bail!("--captions needs an output file");
+    }
+    if to_stdout && std::io::stdout().is_terminal() {
+        bail!("refusing to write audio to a terminal; pass --output or redirect stdout");
+    }
+    let format = args
+        .voice
+        .format
+        .unwrap_or_else(|| match args.output.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("mp3") => AudioFormat::Mp3,
+            _ => AudioFormat::Wav,
+        });
+    let output = if to_stdout {
+        std::env::temp_dir().join(format!("piperdesk-{}.{}", Uuid::new_v4(), format.extension()))
+    } else {
+        args.output.clone()
+    };
+    let voice = args
+        .voice
+        .voice
+        .clone()
+        .unwrap_or_else(|| state.tts.default_voice().to_string());
+    let rate = args.voice.rate.unwrap_or(1.0);
+    let synthesized = queue::synthesize_to(state, &text, voice, rate, format, &output).await?;
+
+    let result = SynthResult {
+        path: (!to_stdout).then(|| output.clone()),
+        duration_ms: synthesized.timings.duration_ms,
+    };
+    if to_stdout {
+        let bytes = std::fs::read(&output);
+        let _ = std::fs::remove_file(&output);
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(&bytes?)?;
+        stdout.flush()?;
+        return Ok(());
+    }
+    if args.captions {
+        write_captions(&output, &synthesized.timings, &CaptionOptions::default())?;
+    }
+    if cli.json {
+        print_json(&result)?;
+    } else {
+        println!("{}", output.display());
+    }
+    Ok(())
+}
+
+/// Synthesizes every pending item into `dir`, waiting for automatic retries,
+/// and reports the outcome. Fails the process when any item failed.
+async fn run_queue(cli: &Cli, state: &SharedState, dir: Option<&Path>) -> Result<ExitCode> {
+    let dir = dir.map_or_else(|| state.config.lock().export_dir.clone(), Path::to_path_buf);
+    let mut report = RunReport {
+        items: Vec::new(),
+        errors: Vec::new(),
+    };
+    loop {
+        let Some(item) = queue::claim_next(state) else {
+            // Items waiting for a retry are synthesized once it is due.
+            match queue::next_retry_at(state) {
+                Some(at) => {
+                    retry::sleep_until(at).await;
+                    continue;
+                }
+                None => break,
+            }
+        };
+        let id = item.id;
+        let title = item.title.clone();
+        match queue::run_item(state, item, &dir).await {
+            Ok(done) => {
+                if !cli.json {
+                    println!("{}", done.output.as_deref().unwrap_or(&dir).display());
+                }
+                report.items.push(done);
+            }
+            Err(err) if queue::retry_scheduled(state, id).is_some() => eprintln!("{title}: {err}"),
+            Err(err) => {
+                eprintln!("{title}: {err}");
+                report.errors.push(format!("{title}: {err}"));
+                if let Some(failed) = state.queue.lock().iter().find(|item| item.id == id) {
+                    report.items.push(failed.clone());
+                }
+            }
+        }
+    }
+    if cli.json {
+        print_json(&report)?;
+    }
+    Ok(if report.errors.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+async fn stream_raw(state: &SharedState, text: &str, args: &VoiceArgs) -> Result<()> {
+    if std::io::stdout().is_terminal() {
+        bail!("refusing to write audio to a terminal; redirect stdout");
+    }
+    let voice = state.tts.resolve_voice(args.voice.as_deref())?;
+    let mut stream = state.tts.synthesize_stream(&voice, text, None, args.rate)?;
+    let mut reported = false;
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk?;
+        if !reported {
+            eprintln!("{} Hz, 16-bit mono", chunk.sample_rate);
+            reported = true;
+        }
+        let bytes: Vec<u8> = chunk.samples.iter().flat_map(|sample| sample.to_le_bytes()).collect();
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(&bytes)?;
+        stdout.flush()?;
+    }
+    Ok(())
+}
+
+fn run_dict(cli: &Cli, command: &DictCommand, dict: &mut PronunciationDictionary, path: &Path) -> Result<ExitCode> {
+    match command {
+        DictCommand::List => {
+            if cli.json {
+                print_json(&dict.entries())?;
+            } else {
+                for (word, spoken) in dict.entries() {
+                    println!("{word}\t{spoken}");
+                }
+            }
+        }
+        DictCommand::Add { word, spoken } => {
+            dict.insert(word, spoken);
+            dict.save(path)?;
+        }
+        DictCommand::Remove { word } => {
+            if !dict.remove(word) {
+                bail!("'{word}' is not in the dictionary");
+            }
+            dict.save(path)?;
+        }
+        DictCommand::Apply { text } => {
+            let text = text_or_stdin(text.as_deref())?;
+            let applied = dict.apply(&text);
+            if cli.json {
+                print_json(&applied)?;
+            } else {
+                print!("{applied}");
+            }
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Reads the file `source`, or stdin when it is `None` or `-`.
+fn read_input(source: Option<&Path>) -> Result<String> {
+    match source {
+        Some(path) if path != Path::new("-") => {
+            std::fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))
+        }
+        _ => read_stdin(),
+    }
+}
+
+/// Returns `text`, or stdin when it is `None` or `-`.
+fn text_or_stdin(text: Option<&str>) -> Result<String> {
+    match text {
+        Some(text) if text != "-" => Ok(text.to_string()),
+        _ => read_stdin(),
+    }
+}
+
+fn read_stdin() -> Result<String> {
+    let mut input = String::new();
+    std::io::stdin()
+        .read_to_string(&mut input)
+        .context("failed to read stdin")?;
+    Ok(input)
+}
+
+fn print_json<T: Serialize + ?Sized>(value: &T) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+/// Parses a command-line value with the same spelling the JSON API uses.
+fn parse_serde<T: DeserializeOwned>(value: &str) -> Result<T, String> {
+    serde_json::from_value(serde_json::Value::String(value.to_string())).map_err(|err| err.to_string())
+}
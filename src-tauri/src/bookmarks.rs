@@ -0,0 +1,310 @@
+//! Where the listener is in each queue item and project, and the named
+//! bookmarks they set. Positions are recorded by the player and saved next
+//! to the queue, see [`crate::session`].
+
+use crate::queue;
+use crate::SharedState;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use thiserror::Error;
+use uuid::Uuid;
+
+/// Where listening stopped in a queue item.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListeningState {
+    pub position_ms: u64,
+    /// Character of the item text being read at `position_ms`, when the item
+    /// has a timing map.
+    pub char_offset: Option<usize>,
+    /// Played to the end; playing the item again starts over.
+    #[serde(default)]
+    pub finished: bool,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Item and position a project was last listened to at.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResumePoint {
+    pub item_id: Uuid,
+    pub position_ms: u64,
+    pub char_offset: Option<usize>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Bookmark {
+    pub id: Uuid,
+    pub item_id: Uuid,
+    pub project: Option<String>,
+    pub name: String,
+    pub note: Option<String>,
+    pub position_ms: u64,
+    pub char_offset: Option<usize>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewBookmark {
+    pub item_id: Uuid,
+    pub position_ms: u64,
+    /// Defaults to the item title and the position, e.g. `Capítulo 3 (12:05)`.
+    pub name: Option<String>,
+    pub note: Option<String>,
+}
+
+/// Resume points per project and every bookmark, in creation order.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Listening {
+    pub projects: BTreeMap<String, ResumePoint>,
+    pub bookmarks: Vec<Bookmark>,
+}
+
+impl Listening {
+    /// Drops everything recorded for a queue item that was removed.
+    pub fn forget_item(&mut self, item_id: Uuid) {
+        self.projects.retain(|_, point| point.item_id != item_id);
+        self.bookmarks.retain(|bookmark| bookmark.item_id != item_id);
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum BookmarkError {
+    #[error("item {0} not found")]
+    ItemNotFound(Uuid),
+    #[error("bookmark {0} not found")]
+    NotFound(Uuid),
+}
+
+/// Character being read at `position_ms`, if the item has been synthesized
+/// with a timing map.
+fn char_offset(state: &SharedState, item_id: Uuid, position_ms: u64) -> Option<usize> {
+    queue::text_span(state, item_id, position_ms)
+        .ok()
+        .flatten()
+        .map(|span| span.char_start)
+}
+
+/// Remembers that `item_id` was heard up to `position_ms`, for the item and
+/// for its project.
+pub fn record_position(state: &SharedState, item_id: Uuid, position_ms: u64) {
+    let char_offset = char_offset(state, item_id, position_ms);
+    let updated_at = Utc::now();
+    let project = {
+        let mut queue = state.queue.lock();
+        let Some(item) = queue.iter_mut().find(|item| item.id == item_id) else {
+            return;
+        };
+        item.listening = Some(ListeningState {
+            position_ms,
+            char_offset,
+            finished: false,
+            updated_at,
+        });
+        item.project.clone()
+    };
+    if let Some(project) = project {
+        let point = ResumePoint {
+            item_id,
+            position_ms,
+            char_offset,
+            updated_at,
+        };
+        state.listening.lock().projects.insert(project, point);
+    }
+}
+
+/// Marks an item as heard to the end.
+pub fn mark_finished(state: &SharedState, item_id: Uuid) {
+    let mut queue = state.queue.lock();
+    if let Some(item) = queue.iter_mut().find(|item| item.id == item_id) {
+        let listening = item.listening.get_or_insert(ListeningState {
+            position_ms: 0,
+            char_offset: None,
+            finished: true,
+            updated_at: Utc::now(),
+        });
+        listening.finished = true;
+        listening.updated_at = Utc::now();
+    }
+}
+
+/// Where playing `item_id` should start: where it was left, or at the
+/// beginning if it was never played or was played to the end.
+pub fn resume_position(state: &SharedState, item_id: Uuid) -> u64 {
+    queue::get_item(state, item_id)
+        .and_then(|item| item.listening)
+        .filter(|listening| !listening.finished)
+        .map_or(0, |listening| listening.position_ms)
+}
+
+/// Where to pick `project` up again. Once the last item listened to has
+/// been finished, that is the start of the next item of the project.
+pub fn project_resume_point(state: &SharedState, project: &str) -> Option<ResumePoint> {
+    let point = state.listening.lock().projects.get(project).cloned()?;
+    let items = queue::list(state);
+    let index = items.iter().position(|item| item.id == point.item_id)?;
+    let finished = items[index]
+        .listening
+        .as_ref()
+        .is_some_and(|listening| listening.finished);
+    if !finished {
+        return Some(point);
+    }
+    let next = items[index + 1..]
+        .iter()
+        .find(|item| item.project.as_deref() == Some(project))?;
+    Some(ResumePoint {
+        item_id: next.id,
+        position_ms: 0,
+        char_offset: Some(0),
+        updated_at: point.updated_at,
+    })
+}
+
+pub fn add(state: &SharedState, bookmark: NewBookmark) -> Result<Bookmark, BookmarkError> {
+    let item = queue::get_item(state, bookmark.item_id).ok_or(BookmarkError::ItemNotFound(bookmark.item_id))?;
+    let name = bookmark
+        .name
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| format!("{} ({})", item.title, format_position(bookmark.position_ms)));
+    let created = Bookmark {
+        id: Uuid::new_v4(),
+        item_id: item.id,
+        project: item.project,
+        name,
+        note: bookmark.note.filter(|note| !note.trim().is_empty()),
+        position_ms: bookmark.position_ms,
+        char_offset: char_offset(state, item.id, bookmark.position_ms),
+        created_at: Utc::now(),
+    };
+    state.listening.lock().bookmarks.push(created.clone());
+    Ok(created)
+}
+
+/// Bookmarks of an item or a project, or all of them, in creation order.
+pub fn list(state: &SharedState, item_id: Option<Uuid>, project: Option<&str>) -> Vec<Bookmark> {
+    state
+        .listening
+        .lock()
+        .bookmarks
+        .iter()
+        .filter(|bookmark| item_id.is_none_or(|id| bookmark.item_id == id))
+        .filter(|bookmark| project.is_none_or(|project| bookmark.project.as_deref() == Some(project)))
+        .cloned()
+        .collect()
+}
+
+pub fn get(state: &SharedState, id: Uuid) -> Result<Bookmark, BookmarkError> {
+    state
+        .listening
+        .lock()
+        .bookmarks
+        .iter()
+        .find(|bookmark| bookmark.id == id)
+        .cloned()
+        .ok_or(BookmarkError::NotFound(id))
+}
+
+pub fn remove(state: &SharedState, id: Uuid) -> Result<(), BookmarkError> {
+    let mut listening = state.listening.lock();
+    let index = listening
+        .bookmarks
+        .iter()
+        .position(|bookmark| bookmark.id == id)
+        .ok_or(BookmarkError::NotFound(id))?;
+    listening.bookmarks.remove(index);
+    Ok(())
+}
+
+fn format_position(position_ms: u64) -> String {
+    let seconds = position_ms / 1000;
+    if seconds >= 3600 {
+        format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+    } else {
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::importers::{Chapter, DocumentMetadata, ImportedDocument};
+    use crate::queue::EnqueueDocumentOptions;
+    use crate::AppConfig;
+
+    fn book(state: &SharedState) -> Vec<crate::QueueItem> {
+        let chapter = |title: &str| Chapter {
+            title: Some(title.into()),
+            text: format!("{title}."),
+        };
+        let document = ImportedDocument {
+            metadata: DocumentMetadata {
+                title: Some("Libro".into()),
+                ..DocumentMetadata::default()
+            },
+            chapters: vec![chapter("Uno"), chapter("Dos")],
+            ..ImportedDocument::default()
+        };
+        queue::enqueue_document(state, document, EnqueueDocumentOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn resumes_projects_where_they_were_left() {
+        let state = SharedState::new(AppConfig::default()).unwrap();
+        let items = book(&state);
+        assert_eq!(project_resume_point(&state, "Libro"), None);
+
+        record_position(&state, items[0].id, 61_000);
+        assert_eq!(resume_position(&state, items[0].id), 61_000);
+        let point = project_resume_point(&state, "Libro").unwrap();
+        assert_eq!((point.item_id, point.position_ms), (items[0].id, 61_000));
+
+        mark_finished(&state, items[0].id);
+        assert_eq!(resume_position(&state, items[0].id), 0);
+        let point = project_resume_point(&state, "Libro").unwrap();
+        assert_eq!((point.item_id, point.position_ms), (items[1].id, 0));
+    }
+
+    #[test]
+    fn adds_lists_and_removes_bookmarks() {
+        let state = SharedState::new(AppConfig::default()).unwrap();
+        let items = book(&state);
+        let first = add(
+            &state,
+            NewBookmark {
+                item_id: items[0].id,
+                position_ms: 3_725_000,
+                name: None,
+                note: Some("cita".into()),
+            },
+        )
+        .unwrap();
+        assert_eq!(first.name, "Uno (1:02:05)");
+        assert_eq!(first.project.as_deref(), Some("Libro"));
+        let second = add(
+            &state,
+            NewBookmark {
+                item_id: items[1].id,
+                position_ms: 5_000,
+                name: Some("Aquí".into()),
+                note: Some("  ".into()),
+            },
+        )
+        .unwrap();
+        assert_eq!(second.note, None);
+
+        assert_eq!(list(&state, None, Some("Libro")).len(), 2);
+        assert_eq!(list(&state, Some(items[1].id), None), vec![second.clone()]);
+        remove(&state, first.id).unwrap();
+        assert!(matches!(remove(&state, first.id), Err(BookmarkError::NotFound(_))));
+        queue::remove_item(&state, items[1].id);
+        assert!(list(&state, None, None).is_empty());
+    }
+}
@@ -0,0 +1,355 @@
+use crate::audio::{encode_wav, parse_wav, WavAudio};
+use anyhow::{Context, Result};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use unicode_normalization::UnicodeNormalization;
+
+/// Bump when the chunk audio layout or key derivation changes.
+const CACHE_VERSION: u32 = 1;
+const INDEX_FILE: &str = "index.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CacheConfig {
+    pub enabled: bool,
+    pub directory: PathBuf,
+    pub max_bytes: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        let directory = dirs::cache_dir()
+            .unwrap_or_else(std::env::temp_dir)
+            .join("com.example.piperdesk")
+            .join("synthesis");
+        Self {
+            enabled: true,
+            directory,
+            max_bytes: 1024 * 1024 * 1024,
+        }
+    }
+}
+
+/// Everything besides the text that influences the audio Piper produces.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChunkParams<'a> {
+    pub voice: &'a str,
+    pub model_path: &'a Path,
+    pub model_size: u64,
+    pub model_modified: u64,
+    pub speaker: Option<u32>,
+    pub length_scale: Option<f32>,
+    pub sentence_silence: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheStats {
+    pub enabled: bool,
+    pub directory: PathBuf,
+    pub entries: usize,
+    pub total_bytes: u64,
+    pub max_bytes: u64,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CacheEntry {
+    size: u64,
+    last_access: u64,
+}
+
+#[derive(Debug, Default)]
+struct CacheIndex {
+    entries: HashMap<String, CacheEntry>,
+    total_bytes: u64,
+    hits: u64,
+    misses: u64,
+    dirty: bool,
+}
+
+/// Content-addressed store of synthesized sentence chunks with LRU eviction.
+///
+/// Each chunk is a WAV file named after the SHA-256 of its normalized text and
+/// [`ChunkParams`]. The index of sizes and access times lives in memory and is
+/// written back by [`SynthesisCache::flush`].
+#[derive(Debug)]
+pub struct SynthesisCache {
+    config: Mutex<CacheConfig>,
+    index: Mutex<CacheIndex>,
+}
+
+impl SynthesisCache {
+    pub fn open(config: CacheConfig) -> Self {
+        let index = load_index(&config.directory);
+        Self {
+            config: Mutex::new(config),
+            index: Mutex::new(index),
+        }
+    }
+
+    pub fn config(&self) -> CacheConfig {
+        self.config.lock().clone()
+    }
+
+    pub fn key(text: &str, params: &ChunkParams<'_>) -> String {
+        let normalized: String = text
+            .nfc()
+            .collect::<String>()
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        let mut hasher = Sha256::new();
+        hasher.update(CACHE_VERSION.to_le_bytes());
+        hasher.update(serde_json::to_vec(params).unwrap_or_default());
+        hasher.update([0]);
+        hasher.update(normalized.as_bytes());
+        hex::encode(hasher.finalize())
+    }
+
+    pub fn get(&self, key: &str) -> Option<WavAudio> {
+        let config = self.config.lock().clone();
+        if !config.enabled {
+            return None;
+        }
+        let mut index = self.index.lock();
+        if !index.entries.contains_key(key) {
+            index.misses += 1;
+            return None;
+        }
+        match fs::read(entry_path(&config.directory, key))
+            .map_err(anyhow::Error::from)
+            .and_then(|bytes| parse_wav(&bytes))
+        {
+            Ok(audio) => {
+                index.hits += 1;
+                index.dirty = true;
+                if let Some(entry) = index.entries.get_mut(key) {
+                    entry.last_access = now_millis();
+                }
+                Some(audio)
+            }
+            Err(err) => {
+                log::warn!("dropping unreadable cache entry {key}: {err}");
+                index.misses += 1;
+                remove_entry(&config.directory, &mut index, key);
+                None
+            }
+        }
+    }
+
+    pub fn insert(&self, key: &str, audio: &WavAudio) -> Result<()> {
+        let config = self.config.lock().clone();
+        if !config.enabled {
+            return Ok(());
+        }
+        fs::create_dir_all(&config.directory).context("failed to create cache directory")?;
+        let bytes = encode_wav(audio);
+        let size = bytes.len() as u64;
+        if size > config.max_bytes {
+            return Ok(());
+        }
+        fs::write(entry_path(&config.directory, key), bytes).context("failed to write cache entry")?;
+        let mut index = self.index.lock();
+        if let Some(previous) = index.entries.insert(
+            key.to_string(),
+            CacheEntry {
+                size,
+                last_access: now_millis(),
+            },
+        ) {
+            index.total_bytes -= previous.size;
+        }
+        index.total_bytes += size;
+        index.dirty = true;
+        evict(&config, &mut index);
+        Ok(())
+    }
+
+    /// Persists access times and sizes so LRU order survives restarts.
+    pub fn flush(&self) -> Result<()> {
+        let directory = self.config.lock().directory.clone();
+        let mut index = self.index.lock();
+        if !index.dirty {
+            return Ok(());
+        }
+        fs::create_dir_all(&directory).context("failed to create cache directory")?;
+        let content = serde_json::to_vec(&index.entries)?;
+        fs::write(directory.join(INDEX_FILE), content).context("failed to write cache index")?;
+        index.dirty = false;
+        Ok(())
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let config = self.config.lock().clone();
+        let index = self.index.lock();
+        CacheStats {
+            enabled: config.enabled,
+            directory: config.directory,
+            entries: index.entries.len(),
+            total_bytes: index.total_bytes,
+            max_bytes: config.max_bytes,
+            hits: index.hits,
+            misses: index.misses,
+        }
+    }
+
+    pub fn clear(&self) -> Result<()> {
+        let directory = self.config.lock().directory.clone();
+        let mut index = self.index.lock();
+        let keys: Vec<String> = index.entries.keys().cloned().collect();
+        for key in keys {
+            remove_entry(&directory, &mut index, &key);
+        }
+        let _ = fs::remove_file(directory.join(INDEX_FILE));
+        index.dirty = false;
+        Ok(())
+    }
+
+    pub fn update_config(&self, config: CacheConfig) -> Result<()> {
+        self.flush()?;
+        let mut index = self.index.lock();
+        let (hits, misses) = (index.hits, index.misses);
+        *index = load_index(&config.directory);
+        index.hits = hits;
+        index.misses = misses;
+        evict(&config, &mut index);
+        *self.config.lock() = config;
+        Ok(())
+    }
+}
+
+fn entry_path(directory: &Path, key: &str) -> PathBuf {
+    directory.join(format!("{key}.wav"))
+}
+
+fn remove_entry(directory: &Path, index: &mut CacheIndex, key: &str) {
+    if let Some(entry) = index.entries.remove(key) {
+        index.total_bytes -= entry.size;
+        index.dirty = true;
+    }
+    let _ = fs::remove_file(entry_path(directory, key));
+}
+
+fn evict(config: &CacheConfig, index: &mut CacheIndex) {
+    if index.total_bytes <= config.max_bytes {
+        return;
+    }
+    let mut by_age: Vec<(String, u64)> = index
+        .entries
+        .iter()
+        .map(|(key, entry)| (key.clone(), entry.last_access))
+        .collect();
+    by_age.sort_by_key(|(_, last_access)| *last_access);
+    for (key, _) in by_age {
+        if index.total_bytes <= config.max_bytes {
+            break;
+        }
+        remove_entry(&config.directory, index, &key);
+    }
+}
+
+/// Reads the saved index, dropping entries whose file vanished and chunk files
+/// that the index does not know about.
+fn load_index(directory: &Path) -> CacheIndex {
+    let mut entries: HashMap<String, CacheEntry> = fs::read(directory.join(INDEX_FILE))
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default();
+    entries.retain(|key, _| entry_path(directory, key).is_file());
+    if let Ok(listing) = fs::read_dir(directory) {
+        for file in listing.flatten() {
+            let path = file.path();
+            let stem = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or_default();
+            let is_chunk = stem.len() == 64 && stem.bytes().all(|byte| byte.is_ascii_hexdigit());
+            if is_chunk && path.extension().is_some_and(|ext| ext == "wav") && !entries.contains_key(stem) {
+                let _ = fs::remove_file(&path);
+            }
+        }
+    }
+    let total_bytes = entries.values().map(|entry| entry.size).sum();
+    CacheIndex {
+        entries,
+        total_bytes,
+        ..CacheIndex::default()
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn params(length_scale: Option<f32>) -> ChunkParams<'static> {
+        ChunkParams {
+            voice: "es_ES-davefx-medium",
+            model_path: Path::new("/voices/es_ES-davefx-medium.onnx"),
+            model_size: 1,
+            model_modified: 2,
+            speaker: None,
+            length_scale,
+            sentence_silence: 0.2,
+        }
+    }
+
+    fn audio(len: usize) -> WavAudio {
+        WavAudio {
+            sample_rate: 22050,
+            channels: 1,
+            samples: vec![1; len],
+        }
+    }
+
+    #[test]
+    fn keys_depend_on_normalized_text_and_params() {
+        let key = SynthesisCache::key("Hola  mundo", &params(None));
+        assert_eq!(key, SynthesisCache::key(" Hola mundo ", &params(None)));
+        assert_ne!(key, SynthesisCache::key("Hola mundo", &params(Some(1.2))));
+        assert_ne!(key, SynthesisCache::key("Hola mundo.", &params(None)));
+    }
+
+    #[test]
+    fn evicts_least_recently_used_entries() {
+        let directory = std::env::temp_dir().join(format!("piperdesk-cache-{}", Uuid::new_v4()));
+        let entry_size = encode_wav(&audio(100)).len() as u64;
+        let cache = SynthesisCache::open(CacheConfig {
+            enabled: true,
+            directory: directory.clone(),
+            max_bytes: entry_size * 2,
+        });
+        cache.insert("a", &audio(100)).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        cache.insert("b", &audio(100)).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        assert_eq!(cache.get("a"), Some(audio(100)));
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        cache.insert("c", &audio(100)).unwrap();
+
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("a").is_some());
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.total_bytes), (2, entry_size * 2));
+        assert_eq!((stats.hits, stats.misses), (2, 1));
+
+        cache.flush().unwrap();
+        let reopened = SynthesisCache::open(cache.config());
+        assert_eq!(reopened.stats().entries, 2);
+        reopened.clear().unwrap();
+        assert_eq!(reopened.stats().total_bytes, 0);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,233 @@
+use crate::timing::{TimedSegment, TimingMap};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CaptionOptions {
+    pub max_cue_duration_ms: u64,
+    pub max_line_length: usize,
+    pub max_lines: usize,
+}
+
+impl Default for CaptionOptions {
+    fn default() -> Self {
+        Self {
+            max_cue_duration_ms: 7000,
+            max_line_length: 42,
+            max_lines: 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cue {
+    pub start_ms: u64,
+    pub end_ms: u64,
+    pub lines: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CaptionFiles {
+    pub srt: PathBuf,
+    pub vtt: PathBuf,
+}
+
+/// Splits each timed segment into cues that respect the configured limits.
+///
+/// Long segments are broken at word boundaries and their time span is shared
+/// out proportionally to the number of characters in each piece.
+pub fn build_cues(timings: &TimingMap, options: &CaptionOptions) -> Vec<Cue> {
+    let max_line = options.max_line_length.max(1);
+    let mut cues = Vec::new();
+    for segment in &timings.segments {
+        let words: Vec<&str> = segment.text.split_whitespace().collect();
+        if words.is_empty() {
+            continue;
+        }
+        let mut pieces = pack_words(&words, max_line, options.max_lines.max(1));
+        let duration = segment.end_ms.saturating_sub(segment.start_ms);
+        if options.max_cue_duration_ms > 0 {
+            let wanted = duration.div_ceil(options.max_cue_duration_ms) as usize;
+            while pieces.len() < wanted.min(words.len()) {
+                pieces = split_longest(pieces);
+            }
+        }
+        push_cues(&mut cues, segment, &pieces, max_line);
+    }
+    cues
+}
+
+fn pack_words<'a>(words: &[&'a str], max_line: usize, max_lines: usize) -> Vec<Vec<&'a str>> {
+    wrap_words(words, max_line)
+        .chunks(max_lines)
+        .map(|lines| lines.concat())
+        .collect()
+}
+
+fn split_longest(mut pieces: Vec<Vec<&str>>) -> Vec<Vec<&str>> {
+    let Some((idx, _)) = pieces
+        .iter()
+        .enumerate()
+        .filter(|(_, piece)| piece.len() > 1)
+        .max_by_key(|(_, piece)| piece_chars(piece))
+    else {
+        return pieces;
+    };
+    let piece = pieces.remove(idx);
+    let (head, tail) = piece.split_at(piece.len() / 2);
+    pieces.insert(idx, tail.to_vec());
+    pieces.insert(idx, head.to_vec());
+    pieces
+}
+
+fn piece_chars(piece: &[&str]) -> usize {
+    piece.iter().map(|word| word.chars().count()).sum::<usize>() + piece.len().saturating_sub(1)
+}
+
+fn push_cues(cues: &mut Vec<Cue>, segment: &TimedSegment, pieces: &[Vec<&str>], max_line: usize) {
+    let total: usize = pieces.iter().map(|piece| piece_chars(piece)).sum();
+    let duration = segment.end_ms.saturating_sub(segment.start_ms);
+    let mut consumed = 0;
+    let mut start_ms = segment.start_ms;
+    for (idx, piece) in pieces.iter().enumerate() {
+        consumed += piece_chars(piece);
+        let end_ms = if idx + 1 == pieces.len() {
+            segment.end_ms
+        } else {
+            segment.start_ms + duration * consumed as u64 / total.max(1) as u64
+        };
+        cues.push(Cue {
+            start_ms,
+            end_ms,
+            lines: wrap_words(piece, max_line).iter().map(|line| line.join(" ")).collect(),
+        });
+        start_ms = end_ms;
+    }
+}
+
+fn wrap_words<'a>(words: &[&'a str], max_line: usize) -> Vec<Vec<&'a str>> {
+    let mut lines: Vec<Vec<&str>> = Vec::new();
+    let mut current: Vec<&str> = Vec::new();
+    for word in words {
+        if !current.is_empty() && piece_chars(&current) + 1 + word.chars().count() > max_line {
+            lines.push(std::mem::take(&mut current));
+        }
+        current.push(word);
+    }
+    if !current.is_empty() {
+        lines.push(current);
+    }
+    lines
+}
+
+pub fn to_srt(cues: &[Cue]) -> String {
+    let mut out = String::new();
+    for (idx, cue) in cues.iter().enumerate() {
+        let _ = writeln!(
+            out,
+            "{}\n{} --> {}\n{}\n",
+            idx + 1,
+            format_timestamp(cue.start_ms, ','),
+            format_timestamp(cue.end_ms, ','),
+            cue.lines.join("\n")
+        );
+    }
+    out
+}
+
+pub fn to_webvtt(cues: &[Cue]) -> String {
+    let mut out = String::from("WEBVTT\n\n");
+    for cue in cues {
+        let _ = writeln!(
+            out,
+            "{} --> {}\n{}\n",
+            format_timestamp(cue.start_ms, '.'),
+            format_timestamp(cue.end_ms, '.'),
+            cue.lines.join("\n")
+        );
+    }
+    out
+}
+
+fn format_timestamp(ms: u64, separator: char) -> String {
+    let hours = ms / 3_600_000;
+    let minutes = ms / 60_000 % 60;
+    let seconds = ms / 1000 % 60;
+    let millis = ms % 1000;
+    format!("{hours:02}:{minutes:02}:{seconds:02}{separator}{millis:03}")
+}
+
+/// Writes `<stem>.srt` and `<stem>.vtt` next to `audio`.
+pub fn write_captions(audio: &Path, timings: &TimingMap, options: &CaptionOptions) -> Result<CaptionFiles> {
+    let cues = build_cues(timings, options);
+    let files = CaptionFiles {
+        srt: audio.with_extension("srt"),
+        vtt: audio.with_extension("vtt"),
+    };
+    fs::write(&files.srt, to_srt(&cues)).context("failed to write SRT captions")?;
+    fs::write(&files.vtt, to_webvtt(&cues)).context("failed to write WebVTT captions")?;
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(text: &str, start_ms: u64, end_ms: u64) -> TimedSegment {
+        TimedSegment {
+            index: 0,
+            text: text.into(),
+            start_ms,
+            end_ms,
+            char_start: 0,
+            char_end: text.chars().count(),
+            words: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn formats_srt_and_webvtt() {
+        let timings = TimingMap {
+            duration_ms: 3_723_456,
+            segments: vec![segment("Hola mundo.", 1_500, 3_723_456)],
+        };
+        let options = CaptionOptions {
+            max_cue_duration_ms: 0,
+            ..CaptionOptions::default()
+        };
+        let cues = build_cues(&timings, &options);
+        assert_eq!(to_srt(&cues), "1\n00:00:01,500 --> 01:02:03,456\nHola mundo.\n\n");
+        assert_eq!(
+            to_webvtt(&cues),
+            "WEBVTT\n\n00:00:01.500 --> 01:02:03.456\nHola mundo.\n\n"
+        );
+    }
+
+    #[test]
+    fn splits_long_segments_and_wraps_lines() {
+        let timings = TimingMap {
+            duration_ms: 4000,
+            segments: vec![segment("uno dos tres cuatro cinco seis siete ocho", 0, 4000)],
+        };
+        let options = CaptionOptions {
+            max_cue_duration_ms: 10_000,
+            max_line_length: 10,
+            max_lines: 2,
+        };
+        let cues = build_cues(&timings, &options);
+        assert_eq!(cues.len(), 3);
+        assert_eq!(cues[0].lines, vec!["uno dos", "tres"]);
+        assert_eq!(cues[0].start_ms, 0);
+        assert_eq!(cues[2].end_ms, 4000);
+        assert!(cues.windows(2).all(|pair| pair[0].end_ms == pair[1].start_ms));
+        assert!(cues
+            .iter()
+            .flat_map(|cue| &cue.lines)
+            .all(|line| line.chars().count() <= 10));
+    }
+}
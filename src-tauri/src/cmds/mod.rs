@@ -1,216 +1,450 @@
-use crate::audio::{generate_output_path, AudioFormat};
-use crate::piper::{SynthesisRequest, VoiceInfo};
-use crate::{QueueItem, QueueStatus, SharedState};
+use async_std::task::JoinHandle;
+use base64::Engine;
+use futures::StreamExt;
+use parking_lot::Mutex;
+use piperdesk::audio::WavAudio;
+use piperdesk::bookmarks::{self, Bookmark, NewBookmark, ResumePoint};
+use piperdesk::cache::{CacheConfig, CacheStats};
+use piperdesk::dubbing::{self, DubbingOptions};
+use piperdesk::ffmpeg::{self, FfmpegConfig, FfmpegInfo};
+use piperdesk::hotfolder::{self, HotFolderConfig};
+use piperdesk::importers::markup::MarkupOptions;
+use piperdesk::importers::script::{self, ScriptConfig};
+use piperdesk::importers::subtitles::{self, SubtitleCue};
+use piperdesk::importers::text::TextEncoding;
+use piperdesk::importers::{self, ImportSettings, ImportedDocument};
+use piperdesk::naming::{claim_unique_path, release_unused_path, FilenameTemplate};
+use piperdesk::playback::sink::{AudioSink, Pacer};
+use piperdesk::playback::{PlaybackStatus, Player, PlayerCommand};
+use piperdesk::queue::{
+    self, EnqueueDocumentOptions, ExportOptions, FileImportResult, ImportFolderOptions, NewQueueItem,
+};
+use piperdesk::server::{self, ServerConfig};
+use piperdesk::timing::{timing_path_for, TextSpan, TimingMap};
+use piperdesk::tts::{BackendInfo, VoiceInfo};
+use piperdesk::wyoming::{self, WyomingConfig};
+use piperdesk::{AppConfig, QueueItem, SharedState};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use tauri::{AppHandle, Manager, Runtime, State};
 use uuid::Uuid;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct NewQueueItem {
-    pub title: String,
-    pub text: String,
-    pub voice: Option<String>,
-    pub rate: Option<f32>,
-    pub format: Option<AudioFormat>,
-}
-
-pub fn enqueue_text_impl(state: &SharedState, items: Vec<NewQueueItem>) -> Result<Vec<QueueItem>, String> {
-    let mut queue = state.queue.lock();
-    let mut created = Vec::new();
-    for item in items {
-        let id = Uuid::new_v4();
-        let queue_item = QueueItem {
-            id,
-            title: item.title.clone(),
-            text: item.text.clone(),
-            voice: item
-                .voice
-                .unwrap_or_else(|| state.piper.config().default_voice.clone()),
-            rate: item.rate.unwrap_or(1.0),
-            format: item.format.unwrap_or(AudioFormat::Wav),
-            status: QueueStatus::Pending,
-            output: None,
-        };
-        queue.push_back(queue_item.clone());
-        created.push(queue_item);
-    }
-    Ok(created)
-}
-
 #[tauri::command]
 pub fn enqueue_text(state: State<'_, SharedState>, items: Vec<NewQueueItem>) -> Result<Vec<QueueItem>, String> {
-    enqueue_text_impl(state.inner(), items)
+    queue::enqueue_text(state.inner(), items)
 }
 
-pub fn remove_from_queue_impl(state: &SharedState, id: Uuid) {
-    let mut queue = state.queue.lock();
-    if let Some(pos) = queue.iter().position(|item| item.id == id) {
-        queue.remove(pos);
-    }
+#[tauri::command]
+pub fn enqueue_document(
+    state: State<'_, SharedState>,
+    document: ImportedDocument,
+    options: Option<EnqueueDocumentOptions>,
+) -> Result<Vec<QueueItem>, String> {
+    queue::enqueue_document(state.inner(), document, options.unwrap_or_default())
 }
 
 #[tauri::command]
 pub fn remove_from_queue(state: State<'_, SharedState>, id: Uuid) -> Result<(), String> {
-    remove_from_queue_impl(state.inner(), id);
+    queue::remove_item(state.inner(), id);
     Ok(())
 }
 
-pub fn clear_queue_impl(state: &SharedState) {
-    state.queue.lock().clear();
-}
-
 #[tauri::command]
 pub fn clear_queue(state: State<'_, SharedState>) -> Result<(), String> {
-    clear_queue_impl(state.inner());
+    queue::clear(state.inner());
     Ok(())
 }
 
 #[tauri::command]
 pub fn get_queue(state: State<'_, SharedState>) -> Result<Vec<QueueItem>, String> {
-    let queue = state.queue.lock();
-    Ok(queue.iter().cloned().collect())
+    Ok(queue::list(state.inner()))
 }
 
 #[tauri::command]
 pub fn list_voices(state: State<'_, SharedState>) -> Result<Vec<VoiceInfo>, String> {
-    Ok(state.piper.voices())
+    Ok(state.tts.voices())
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct ExportOptions {
-    pub item_id: Uuid,
-    pub format: AudioFormat,
-    pub speed: Option<f32>,
-    pub voice: Option<String>,
-    pub directory: Option<PathBuf>,
+#[tauri::command]
+pub fn list_tts_backends(state: State<'_, SharedState>) -> Result<Vec<BackendInfo>, String> {
+    Ok(state.tts.backends())
 }
 
 #[tauri::command]
-pub async fn synthesize_next<R: Runtime>(app: AppHandle<R>, state: State<'_, SharedState>) -> Result<QueueItem, String> {
-    let next = {
-        let mut queue = state.queue.lock();
-        queue
-            .iter_mut()
-            .find(|item| item.status == QueueStatus::Pending)
-            .map(|item| {
-                item.status = QueueStatus::Processing;
-                item.clone()
-            })
-    };
-    let mut item = match next {
-        Some(item) => item,
-        None => return Err("No pending items".to_string()),
-    };
+pub async fn synthesize_next(state: State<'_, SharedState>) -> Result<QueueItem, String> {
+    queue::synthesize_next(state.inner()).await
+}
 
-    let export_dir = {
-        let config = state.config.lock();
-        config.export_dir.clone()
-    };
-    let format = item.format;
-    let output_path = generate_output_path(&export_dir, &item.title, format);
-    let request = SynthesisRequest {
-        text: item.text.clone(),
-        voice: Some(item.voice.clone()),
-        output: output_path.clone(),
-        speed: Some(item.rate),
-        format,
-    };
-    let result = state
-        .piper
-        .synthesize(request)
-        .await
-        .map_err(|err| err.to_string());
-
-    let mut queue = state.queue.lock();
-    if let Some(stored) = queue.iter_mut().find(|q| q.id == item.id) {
-        match result {
-            Ok(path) => {
-                stored.status = QueueStatus::Completed;
-                stored.output = Some(path.clone());
-                item.status = QueueStatus::Completed;
-                item.output = Some(path);
-                if let Err(err) = app.emit_all("queue::completed", &stored) {
-                    log::warn!("failed to emit completion event: {err}");
+#[tauri::command]
+pub fn cancel_queue_item(state: State<'_, SharedState>, id: Uuid) -> Result<QueueItem, String> {
+    queue::cancel_item(state.inner(), id).map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+pub fn retry_item(state: State<'_, SharedState>, id: Uuid) -> Result<QueueItem, String> {
+    queue::retry_item(state.inner(), id).map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+pub fn retry_failed(state: State<'_, SharedState>) -> Result<Vec<QueueItem>, String> {
+    Ok(queue::retry_failed(state.inner()))
+}
+
+#[tauri::command]
+pub fn move_queue_item(state: State<'_, SharedState>, id: Uuid, position: usize) -> Result<(), String> {
+    queue::move_item(state.inner(), id, position).map_err(|err| err.to_string())
+}
+
+/// Re-emits queue events as Tauri events of the same name, whichever front
+/// end caused them.
+pub async fn forward_queue_events<R: Runtime>(app: AppHandle<R>) {
+    let events = app.state::<SharedState>().events.subscribe();
+    while let Ok(event) = events.recv().await {
+        if let Err(err) = app.emit_all(event.name(), event.payload()) {
+            log::warn!("failed to emit {}: {err}", event.name());
+        }
+    }
+}
+
+/// Speech being streamed to the UI, so it can be stopped.
+#[derive(Default)]
+pub struct SpeechStreams(Mutex<HashMap<Uuid, JoinHandle<()>>>);
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SpeechChunk {
+    id: Uuid,
+    sample_rate: u32,
+    channels: u16,
+    /// Base64 of 16-bit little-endian samples.
+    pcm: String,
+}
+
+fn encode_pcm(audio: &WavAudio) -> String {
+    let pcm: Vec<u8> = audio.samples.iter().flat_map(|sample| sample.to_le_bytes()).collect();
+    base64::engine::general_purpose::STANDARD.encode(pcm)
+}
+
+/// Starts speaking `text` without writing a file. Audio arrives as
+/// `speech::chunk` events while Piper runs, followed by `speech::finished`
+/// or `speech::failed`.
+#[tauri::command]
+pub async fn stream_speech<R: Runtime>(
+    app: AppHandle<R>,
+    state: State<'_, SharedState>,
+    text: String,
+    voice: Option<String>,
+    speed: Option<f32>,
+) -> Result<Uuid, String> {
+    let voice = state
+        .tts
+        .resolve_voice(voice.as_deref())
+        .map_err(|err| err.to_string())?;
+    let mut stream = state
+        .tts
+        .synthesize_stream(&voice, &text, None, speed)
+        .map_err(|err| format!("{err:#}"))?;
+    let id = Uuid::new_v4();
+    let streams = app.state::<SpeechStreams>();
+    // Held until the task is registered, so it cannot unregister first.
+    let mut running = streams.0.lock();
+    let task_app = app.clone();
+    let task = async_std::task::spawn(async move {
+        while let Some(chunk) = stream.next().await {
+            let result = match chunk {
+                Ok(audio) => {
+                    let chunk = SpeechChunk {
+                        id,
+                        sample_rate: audio.sample_rate,
+                        channels: audio.channels,
+                        pcm: encode_pcm(&audio),
+                    };
+                    task_app.emit_all("speech::chunk", chunk)
                 }
-            }
-            Err(err) => {
-                stored.status = QueueStatus::Failed;
-                if let Err(event_err) = app.emit_all("queue::failed", &(stored.id, err.clone())) {
-                    log::warn!("failed to emit failure event: {event_err}");
+                Err(err) => {
+                    log::warn!("speech stream failed: {err:#}");
+                    let _ = task_app.emit_all("speech::failed", (id, format!("{err:#}")));
+                    task_app.state::<SpeechStreams>().0.lock().remove(&id);
+                    return;
                 }
-                return Err(err);
+            };
+            if let Err(err) = result {
+                log::warn!("failed to emit speech::chunk: {err}");
             }
         }
-    }
-    Ok(item)
+        let _ = task_app.emit_all("speech::finished", id);
+        task_app.state::<SpeechStreams>().0.lock().remove(&id);
+    });
+    running.insert(id, task);
+    Ok(id)
 }
 
+/// Stops a stream started by `stream_speech`; Piper is killed with it.
 #[tauri::command]
-pub async fn export_audio(state: State<'_, SharedState>, options: ExportOptions) -> Result<PathBuf, String> {
-    let item = {
-        let queue = state.queue.lock();
-        queue.iter().find(|item| item.id == options.item_id).cloned()
+pub async fn stop_speech<R: Runtime>(app: AppHandle<R>, id: Uuid) -> Result<(), String> {
+    let task = app.state::<SpeechStreams>().0.lock().remove(&id);
+    if let Some(task) = task {
+        task.cancel().await;
+    }
+    Ok(())
+}
+
+/// How far the player may run ahead of what the webview has played.
+const PLAYER_LEAD: Duration = Duration::from_millis(300);
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PlayerAudio {
+    sample_rate: u32,
+    channels: u16,
+    /// Base64 of 16-bit little-endian samples.
+    pcm: String,
+}
+
+/// Plays the player's audio through Web Audio in the webview, as
+/// `player::audio` events; `player::clear` drops what is still scheduled.
+struct WebviewSink<R: Runtime> {
+    app: AppHandle<R>,
+    pacer: Pacer,
+}
+
+impl<R: Runtime> AudioSink for WebviewSink<R> {
+    fn write(&mut self, audio: &WavAudio) -> anyhow::Result<()> {
+        self.pacer.wait(Duration::from_millis(audio.duration_ms()));
+        let chunk = PlayerAudio {
+            sample_rate: audio.sample_rate,
+            channels: audio.channels,
+            pcm: encode_pcm(audio),
+        };
+        Ok(self.app.emit_all("player::audio", chunk)?)
     }
-    .ok_or_else(|| "Item not found".to_string())?;
 
-    let directory = options.directory.unwrap_or_else(|| {
-        let config = state.config.lock();
-        config.export_dir.clone()
-    });
-    let format = options.format;
-    let output_path = generate_output_path(&directory, &item.title, format);
-    let request = SynthesisRequest {
-        text: item.text,
-        voice: options.voice.or(Some(item.voice)),
-        output: output_path.clone(),
-        speed: options.speed.or(Some(item.rate)),
-        format,
-    };
-    state
-        .piper
-        .synthesize(request)
-        .await
-        .map_err(|err| err.to_string())?;
-    if let Some(stored) = state
-        .queue
-        .lock()
-        .iter_mut()
-        .find(|existing| existing.id == options.item_id)
-    {
-        stored.output = Some(output_path.clone());
-        stored.format = format;
+    fn pending(&self) -> Duration {
+        self.pacer.pending()
     }
-    Ok(output_path)
-}
-
-#[tauri::command]
-pub async fn import_document(path: String) -> Result<String, String> {
-    let path = PathBuf::from(&path);
-    let extension = path
-        .extension()
-        .and_then(|ext| ext.to_str())
-        .unwrap_or_default()
-        .to_ascii_lowercase();
-    let script = match extension.as_str() {
-        "epub" => "scripts/import_epub.py",
-        "pdf" => "scripts/import_pdf.py",
-        "txt" => return std::fs::read_to_string(&path).map_err(|err| err.to_string()),
-        other => {
-            return Err(format!("Unsupported extension: {other}"));
+
+    fn clear(&mut self) {
+        self.pacer.reset();
+        if let Err(err) = self.app.emit_all("player::clear", ()) {
+            log::warn!("failed to emit player::clear: {err}");
         }
+    }
+}
+
+/// Starts the playback engine, forwarding its events to the webview.
+pub fn spawn_player<R: Runtime>(app: &AppHandle<R>) -> Player {
+    let sink = WebviewSink {
+        app: app.clone(),
+        pacer: Pacer::new(PLAYER_LEAD),
     };
-    let mut command = async_process::Command::new("python3");
-    command.arg(script).arg("--file").arg(&path);
-    let output = command.output().await.map_err(|err| err.to_string())?;
-    if !output.status.success() {
-        return Err(String::from_utf8_lossy(&output.stderr).to_string());
+    let events = app.clone();
+    Player::spawn(ManagedState(app.clone()), sink, move |event| {
+        if let Err(err) = events.emit_all(event.name(), event.payload()) {
+            log::warn!("failed to emit {}: {err}", event.name());
+        }
+    })
+}
+
+#[tauri::command]
+pub fn control_player(player: State<'_, Player>, command: PlayerCommand) -> Result<(), String> {
+    player.send(command).map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+pub fn get_player_status(player: State<'_, Player>) -> Result<PlaybackStatus, String> {
+    Ok(player.status())
+}
+
+#[tauri::command]
+pub fn list_bookmarks(
+    state: State<'_, SharedState>,
+    item_id: Option<Uuid>,
+    project: Option<String>,
+) -> Result<Vec<Bookmark>, String> {
+    Ok(bookmarks::list(state.inner(), item_id, project.as_deref()))
+}
+
+#[tauri::command]
+pub fn add_bookmark(state: State<'_, SharedState>, bookmark: NewBookmark) -> Result<Bookmark, String> {
+    bookmarks::add(state.inner(), bookmark).map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+pub fn remove_bookmark(state: State<'_, SharedState>, id: Uuid) -> Result<(), String> {
+    bookmarks::remove(state.inner(), id).map_err(|err| err.to_string())
+}
+
+/// Plays the bookmarked item from the bookmark.
+#[tauri::command]
+pub fn jump_to_bookmark(
+    state: State<'_, SharedState>,
+    player: State<'_, Player>,
+    id: Uuid,
+) -> Result<Bookmark, String> {
+    let bookmark = bookmarks::get(state.inner(), id).map_err(|err| err.to_string())?;
+    player
+        .send(PlayerCommand::Play {
+            item_id: Some(bookmark.item_id),
+            position_ms: Some(bookmark.position_ms),
+        })
+        .map_err(|err| err.to_string())?;
+    Ok(bookmark)
+}
+
+/// Continues listening to `project` where it was left.
+#[tauri::command]
+pub fn resume_project(
+    state: State<'_, SharedState>,
+    player: State<'_, Player>,
+    project: String,
+) -> Result<ResumePoint, String> {
+    let point = bookmarks::project_resume_point(state.inner(), &project)
+        .ok_or_else(|| format!("{project} has not been listened to yet"))?;
+    player
+        .send(PlayerCommand::Play {
+            item_id: Some(point.item_id),
+            position_ms: Some(point.position_ms),
+        })
+        .map_err(|err| err.to_string())?;
+    Ok(point)
+}
+
+#[tauri::command]
+pub async fn export_audio(state: State<'_, SharedState>, options: ExportOptions) -> Result<PathBuf, String> {
+    queue::export_item(state.inner(), options).await
+}
+
+#[tauri::command]
+pub fn get_text_span(
+    state: State<'_, SharedState>,
+    item_id: Uuid,
+    position_ms: u64,
+) -> Result<Option<TextSpan>, String> {
+    queue::text_span(state.inner(), item_id, position_ms)
+}
+
+#[tauri::command]
+pub fn get_timing_map(state: State<'_, SharedState>, item_id: Uuid) -> Result<TimingMap, String> {
+    queue::timing_map(state.inner(), item_id)
+}
+
+/// Importer settings from the current configuration, with the pypdf script
+/// bundled as a Tauri resource.
+fn import_settings<R: Runtime>(
+    app: &AppHandle<R>,
+    state: &SharedState,
+    encoding: Option<TextEncoding>,
+) -> ImportSettings {
+    let resource = app.path_resolver().resolve_resource("../scripts/import_pdf.py");
+    state.config.lock().import_settings(resource, encoding)
+}
+
+#[tauri::command]
+pub async fn import_document<R: Runtime>(
+    app: AppHandle<R>,
+    state: State<'_, SharedState>,
+    path: String,
+    encoding: Option<TextEncoding>,
+) -> Result<ImportedDocument, String> {
+    let settings = import_settings(&app, state.inner(), encoding);
+    importers::import_file(Path::new(&path), &settings).await
+}
+
+/// Imports every supported document below `path` and queues them in natural
+/// order of their paths.
+#[tauri::command]
+pub async fn import_folder<R: Runtime>(
+    app: AppHandle<R>,
+    state: State<'_, SharedState>,
+    path: PathBuf,
+    options: Option<ImportFolderOptions>,
+) -> Result<Vec<FileImportResult>, String> {
+    let settings = import_settings(&app, state.inner(), None);
+    queue::import_folder(state.inner(), &path, &options.unwrap_or_default(), &settings).await
+}
+
+/// Runs the hot folder until the application exits, emitting
+/// `hotfolder::processed` for every document taken from the inbox.
+pub async fn watch_hot_folder<R: Runtime>(app: AppHandle<R>) {
+    let state = app.state::<SharedState>();
+    let resource = app.path_resolver().resolve_resource("../scripts/import_pdf.py");
+    hotfolder::watch(state.inner(), resource, |event| {
+        if let Err(err) = app.emit_all("hotfolder::processed", event) {
+            log::warn!("failed to emit hot folder event: {err}");
+        }
+    })
+    .await;
+}
+
+#[tauri::command]
+pub fn import_subtitles(path: PathBuf) -> Result<Vec<SubtitleCue>, String> {
+    subtitles::import_subtitles(&path).map_err(|err| err.to_string())
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DubSubtitlesRequest {
+    /// SRT or WebVTT file to re-voice.
+    pub path: PathBuf,
+    pub voice: Option<String>,
+    pub rate: Option<f32>,
+    #[serde(default)]
+    pub options: DubbingOptions,
+    pub directory: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DubbingOutput {
+    pub path: PathBuf,
+    pub timings: PathBuf,
+    pub duration_ms: u64,
+    pub warnings: Vec<String>,
+}
+
+/// Re-voices a subtitle file into one WAV track aligned to the video, named
+/// after the subtitle file.
+#[tauri::command]
+pub async fn dub_subtitles(
+    state: State<'_, SharedState>,
+    request: DubSubtitlesRequest,
+) -> Result<DubbingOutput, String> {
+    let cues = subtitles::import_subtitles(&request.path).map_err(|err| err.to_string())?;
+    let voice = state
+        .tts
+        .resolve_voice(request.voice.as_deref())
+        .map_err(|err| err.to_string())?;
+    let directory = request
+        .directory
+        .unwrap_or_else(|| state.config.lock().export_dir.clone());
+    let stem = request
+        .path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "subtitles".to_string());
+    let output = claim_unique_path(&directory.join(format!("{stem}.wav"))).map_err(|err| err.to_string())?;
+    let result = async {
+        let rate = request.rate.unwrap_or(1.0);
+        let track = dubbing::dub_cues(&state.tts, &voice, rate, &cues, &request.options)
+            .await
+            .map_err(|err| err.to_string())?;
+        piperdesk::audio::write_wav(&output, &track.audio).map_err(|err| err.to_string())?;
+        let timings = timing_path_for(&output);
+        track.timings.save(&timings).map_err(|err| err.to_string())?;
+        Ok(DubbingOutput {
+            path: output.clone(),
+            timings,
+            duration_ms: track.timings.duration_ms,
+            warnings: track.warnings,
+        })
+    }
+    .await;
+    if result.is_err() {
+        release_unused_path(&output);
     }
-    String::from_utf8(output.stdout)
-        .map_err(|err| err.to_string())
-        .map(|content| content.trim().to_string())
+    result
 }
 
 #[tauri::command]
@@ -225,75 +459,198 @@ pub fn update_export_dir(state: State<'_, SharedState>, path: PathBuf) -> Result
     Ok(())
 }
 
+#[tauri::command]
+pub async fn get_ffmpeg_status(state: State<'_, SharedState>) -> Result<FfmpegInfo, String> {
+    let config = state.config.lock().ffmpeg.clone();
+    let info = ffmpeg::detect(&config).await.map_err(|err| err.to_string())?;
+    *state.ffmpeg.lock() = Some(info.clone());
+    Ok(info)
+}
+
+#[tauri::command]
+pub async fn update_ffmpeg_config(state: State<'_, SharedState>, config: FfmpegConfig) -> Result<FfmpegInfo, String> {
+    let info = ffmpeg::detect(&config).await.map_err(|err| err.to_string())?;
+    state.config.lock().ffmpeg = config;
+    *state.ffmpeg.lock() = Some(info.clone());
+    Ok(info)
+}
+
+#[tauri::command]
+pub fn update_importer_config(state: State<'_, SharedState>, config: ScriptConfig) -> Result<(), String> {
+    script::locate_python(&config).map_err(|err| err.to_string())?;
+    state.config.lock().importers = config;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn update_markup_options(state: State<'_, SharedState>, options: MarkupOptions) -> Result<(), String> {
+    state.config.lock().markup = options;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn update_hot_folder_config(state: State<'_, SharedState>, config: HotFolderConfig) -> Result<(), String> {
+    if config.enabled {
+        config.prepare().map_err(|err| format!("{err:#}"))?;
+    }
+    state.config.lock().hot_folder = config;
+    Ok(())
+}
+
+/// Running API server, replaced whenever its configuration changes.
+#[derive(Default)]
+pub struct ApiServer(Mutex<Option<JoinHandle<()>>>);
+
+/// Gives the API server access to the state Tauri manages.
+struct ManagedState<R: Runtime>(AppHandle<R>);
+
+impl<R: Runtime> Clone for ManagedState<R> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<R: Runtime> AsRef<SharedState> for ManagedState<R> {
+    fn as_ref(&self) -> &SharedState {
+        self.0.state::<SharedState>().inner()
+    }
+}
+
+/// Stops the running API server and starts it again if it is enabled.
+pub async fn restart_api_server<R: Runtime>(app: AppHandle<R>) -> Result<(), String> {
+    let previous = app.state::<ApiServer>().0.lock().take();
+    if let Some(previous) = previous {
+        previous.cancel().await;
+    }
+    let config = app.state::<SharedState>().config.lock().server.clone();
+    if !config.enabled {
+        return Ok(());
+    }
+    let serve = server::bind(ManagedState(app.clone()), &config)
+        .await
+        .map_err(|err| err.to_string())?;
+    let handle = async_std::task::spawn(async move {
+        if let Err(err) = serve.await {
+            log::error!("{err}");
+        }
+    });
+    *app.state::<ApiServer>().0.lock() = Some(handle);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn update_server_config<R: Runtime>(
+    app: AppHandle<R>,
+    state: State<'_, SharedState>,
+    config: ServerConfig,
+) -> Result<(), String> {
+    state.config.lock().server = config;
+    restart_api_server(app).await
+}
+
+/// Running Wyoming server, replaced whenever its configuration changes.
+#[derive(Default)]
+pub struct WyomingServer(Mutex<Option<JoinHandle<()>>>);
+
+/// Stops the running Wyoming server and starts it again if it is enabled.
+pub async fn restart_wyoming_server<R: Runtime>(app: AppHandle<R>) -> Result<(), String> {
+    let previous = app.state::<WyomingServer>().0.lock().take();
+    if let Some(previous) = previous {
+        previous.cancel().await;
+    }
+    let config = app.state::<SharedState>().config.lock().wyoming.clone();
+    if !config.enabled {
+        return Ok(());
+    }
+    let serve = wyoming::bind(ManagedState(app.clone()), &config)
+        .await
+        .map_err(|err| err.to_string())?;
+    let handle = async_std::task::spawn(async move {
+        if let Err(err) = serve.await {
+            log::error!("{err}");
+        }
+    });
+    *app.state::<WyomingServer>().0.lock() = Some(handle);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn update_wyoming_config<R: Runtime>(
+    app: AppHandle<R>,
+    state: State<'_, SharedState>,
+    config: WyomingConfig,
+) -> Result<(), String> {
+    state.config.lock().wyoming = config;
+    restart_wyoming_server(app).await
+}
+
+#[tauri::command]
+pub fn get_cache_stats(state: State<'_, SharedState>) -> Result<CacheStats, String> {
+    Ok(state.cache.stats())
+}
+
+#[tauri::command]
+pub fn clear_cache(state: State<'_, SharedState>) -> Result<CacheStats, String> {
+    state.cache.clear().map_err(|err| err.to_string())?;
+    Ok(state.cache.stats())
+}
+
+#[tauri::command]
+pub fn update_cache_config(state: State<'_, SharedState>, config: CacheConfig) -> Result<CacheStats, String> {
+    state.cache.update_config(config.clone()).map_err(|err| err.to_string())?;
+    state.config.lock().cache = config;
+    Ok(state.cache.stats())
+}
+
+#[tauri::command]
+pub fn update_filename_template(state: State<'_, SharedState>, template: String) -> Result<(), String> {
+    FilenameTemplate::parse(&template).map_err(|err| err.to_string())?;
+    state.config.lock().filename_template = template;
+    Ok(())
+}
+
 pub fn register_commands() -> tauri::InvokeHandler<()> {
     tauri::generate_handler![
         enqueue_text,
+        enqueue_document,
         remove_from_queue,
         clear_queue,
         get_queue,
         list_voices,
+        list_tts_backends,
         synthesize_next,
+        cancel_queue_item,
+        retry_item,
+        retry_failed,
+        move_queue_item,
         export_audio,
+        stream_speech,
+        stop_speech,
+        control_player,
+        get_player_status,
+        list_bookmarks,
+        add_bookmark,
+        remove_bookmark,
+        jump_to_bookmark,
+        resume_project,
+        get_text_span,
+        get_timing_map,
         import_document,
+        import_folder,
+        import_subtitles,
+        dub_subtitles,
         get_config,
-        update_export_dir
+        update_export_dir,
+        update_filename_template,
+        get_ffmpeg_status,
+        update_ffmpeg_config,
+        update_importer_config,
+        update_markup_options,
+        update_hot_folder_config,
+        update_server_config,
+        update_wyoming_config,
+        get_cache_stats,
+        clear_cache,
+        update_cache_config
     ]
 }
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::{AppConfig, SharedState};
-
-    fn state() -> SharedState {
-        SharedState::new(AppConfig::default()).expect("state")
-    }
-
-    #[test]
-    fn enqueue_and_remove_items() {
-        let state = state();
-        let items = enqueue_text_impl(
-            &state,
-            vec![NewQueueItem {
-                title: "Sample".into(),
-                text: "Hello".into(),
-                voice: None,
-                rate: Some(1.0),
-                format: None,
-            }],
-        )
-        .expect("enqueue");
-        assert_eq!(items.len(), 1);
-        assert_eq!(state.queue.lock().len(), 1);
-        remove_from_queue_impl(&state, items[0].id);
-        assert!(state.queue.lock().is_empty());
-    }
-
-    #[test]
-    fn clear_queue_removes_all() {
-        let state = state();
-        enqueue_text_impl(
-            &state,
-            vec![
-                NewQueueItem {
-                    title: "One".into(),
-                    text: "A".into(),
-                    voice: None,
-                    rate: None,
-                    format: None,
-                },
-                NewQueueItem {
-                    title: "Two".into(),
-                    text: "B".into(),
-                    voice: None,
-                    rate: None,
-                    format: None,
-                },
-            ],
-        )
-        .expect("enqueue");
-        assert_eq!(state.queue.lock().len(), 2);
-        clear_queue_impl(&state);
-        assert!(state.queue.lock().is_empty());
-    }
-}
@@ -1,8 +1,16 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+
+/// Dictionary shared by the command-line tool and the Wyoming server.
+pub fn default_path() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("piperdesk")
+        .join("dictionary.json")
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 #[serde(rename_all = "camelCase")]
@@ -20,7 +28,83 @@ impl PronunciationDictionary {
         Ok(Self { entries })
     }
 
+    /// Writes the entries as a JSON object sorted by word.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent).context("failed to create dictionary directory")?;
+        }
+        let content = serde_json::to_string_pretty(&self.entries())?;
+        fs::write(path, content + "\n").context("failed to write dictionary")
+    }
+
+    pub fn entries(&self) -> BTreeMap<&str, &str> {
+        self.entries
+            .iter()
+            .map(|(word, spoken)| (word.as_str(), spoken.as_str()))
+            .collect()
+    }
+
+    pub fn insert(&mut self, word: &str, spoken: &str) {
+        self.entries.insert(word.trim().to_string(), spoken.trim().to_string());
+    }
+
+    /// Returns whether `word` had an entry.
+    pub fn remove(&mut self, word: &str) -> bool {
+        self.entries.remove(word.trim()).is_some()
+    }
+
     pub fn translate<'a>(&'a self, input: &'a str) -> &'a str {
         self.entries.get(input).map(|s| s.as_str()).unwrap_or(input)
     }
+
+    /// Replaces every word of `text` that has an entry, matching the exact
+    /// spelling first and the lowercase spelling after it.
+    pub fn apply(&self, text: &str) -> String {
+        if self.entries.is_empty() {
+            return text.to_string();
+        }
+        let mut out = String::with_capacity(text.len());
+        let mut rest = text;
+        while !rest.is_empty() {
+            let word_len = rest
+                .char_indices()
+                .find(|&(_, c)| !is_word_char(c))
+                .map_or(rest.len(), |(idx, _)| idx);
+            if word_len == 0 {
+                let c = rest.chars().next().unwrap_or_default();
+                out.push(c);
+                rest = &rest[c.len_utf8()..];
+                continue;
+            }
+            let (word, tail) = rest.split_at(word_len);
+            let spoken = self
+                .entries
+                .get(word)
+                .or_else(|| self.entries.get(&word.to_lowercase()));
+            out.push_str(spoken.map_or(word, String::as_str));
+            rest = tail;
+        }
+        out
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn applies_entries_to_whole_words() {
+        let mut dict = PronunciationDictionary::default();
+        dict.insert("sql", "ese cu ele");
+        dict.insert("GUI", "gui");
+        let text = "Exporta SQL desde la GUI, no la GUIA.";
+        assert_eq!(dict.apply(text), "Exporta ese cu ele desde la gui, no la GUIA.");
+        assert!(dict.remove("sql"));
+        assert!(!dict.remove("sql"));
+        assert_eq!(dict.entries().into_iter().collect::<Vec<_>>(), vec![("GUI", "gui")]);
+    }
 }
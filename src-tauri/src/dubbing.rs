@@ -0,0 +1,199 @@
+use crate::audio::WavAudio;
+use crate::importers::subtitles::SubtitleCue;
+use crate::timing::{estimate_words, TimedSegment, TimingMap};
+use crate::tts::{TtsEngine, VoiceInfo};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum OverrunMode {
+    /// Keep the natural speed and report cues that run into the next one.
+    Warn,
+    /// Re-synthesize overrunning cues faster, up to `max_speedup`.
+    #[default]
+    SpeedUp,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DubbingOptions {
+    pub overrun: OverrunMode,
+    /// Largest speed-up factor applied to a cue, e.g. 1.5 for 50% faster.
+    pub max_speedup: f32,
+}
+
+impl Default for DubbingOptions {
+    fn default() -> Self {
+        Self {
+            overrun: OverrunMode::default(),
+            max_speedup: 1.5,
+        }
+    }
+}
+
+/// Audio track for a subtitle file, aligned to the video timeline.
+#[derive(Debug, Clone)]
+pub struct DubbedTrack {
+    pub audio: WavAudio,
+    pub timings: TimingMap,
+    pub warnings: Vec<String>,
+}
+
+/// Voices every cue and places it at its start time.
+///
+/// `rate` is Piper's length scale; overrunning cues are re-synthesized with a
+/// smaller one when `options.overrun` asks for it. Cues that still do not fit
+/// push the following cue back and are reported in the warnings.
+pub async fn dub_cues(
+    engine: &TtsEngine,
+    voice: &VoiceInfo,
+    rate: f32,
+    cues: &[SubtitleCue],
+    options: &DubbingOptions,
+) -> Result<DubbedTrack> {
+    let texts: Vec<&str> = cues.iter().map(|cue| cue.text.as_str()).collect();
+    let mut clips: Vec<WavAudio> = engine
+        .synthesize_clips(voice, &texts, None, Some(rate))
+        .await?
+        .iter()
+        .map(trim_silence)
+        .collect();
+    let mut warnings = Vec::new();
+    if options.overrun == OverrunMode::SpeedUp {
+        for (idx, cue) in cues.iter().enumerate() {
+            let slot = slot_ms(cues, idx);
+            let duration = clips[idx].duration_ms();
+            if duration <= slot || slot == 0 {
+                continue;
+            }
+            let factor = (duration as f32 / slot as f32).min(options.max_speedup.max(1.0));
+            let faster = engine
+                .synthesize_clips(voice, &[cue.text.as_str()], None, Some(rate / factor))
+                .await?;
+            clips[idx] = trim_silence(faster.first().context("no audio was synthesized")?);
+            warnings.push(format!("cue {} sped up {factor:.2}x to fit its slot", cue.index));
+        }
+    }
+    let mut track = assemble_track(cues, &clips)?;
+    warnings.append(&mut track.warnings);
+    track.warnings = warnings;
+    Ok(track)
+}
+
+/// Time available to a cue: up to the next cue's start, or its own end for
+/// the last cue.
+fn slot_ms(cues: &[SubtitleCue], idx: usize) -> u64 {
+    let cue = &cues[idx];
+    let end = cues.get(idx + 1).map_or(cue.end_ms, |next| next.start_ms);
+    end.saturating_sub(cue.start_ms)
+}
+
+/// Drops the silence Piper leaves around speech so the voice starts on cue.
+fn trim_silence(clip: &WavAudio) -> WavAudio {
+    let (start, end) = clip.voiced_range_ms();
+    clip.slice_ms(start, end)
+}
+
+/// Lays `clips` out at their cue start times, padding with silence up to the
+/// end of the last cue.
+fn assemble_track(cues: &[SubtitleCue], clips: &[WavAudio]) -> Result<DubbedTrack> {
+    let first = clips.first().context("no cues to dub")?;
+    let mut audio = WavAudio::new(first.sample_rate, first.channels);
+    let mut segments = Vec::with_capacity(cues.len());
+    let mut warnings = Vec::new();
+    let mut char_cursor = 0;
+    for (idx, (cue, clip)) in cues.iter().zip(clips).enumerate() {
+        let position = audio.duration_ms();
+        if position < cue.start_ms {
+            audio.push_silence_ms(cue.start_ms - position);
+        } else if position > cue.start_ms {
+            warnings.push(format!("cue {} starts {} ms late", cue.index, position - cue.start_ms));
+        }
+        let start_ms = audio.duration_ms();
+        audio.append(clip)?;
+        let end_ms = audio.duration_ms();
+        let overrun = clip.duration_ms().saturating_sub(slot_ms(cues, idx));
+        if overrun > 0 {
+            warnings.push(format!("cue {} overruns its slot by {overrun} ms", cue.index));
+        }
+
+        let char_start = char_cursor;
+        char_cursor += cue.text.chars().count();
+        segments.push(TimedSegment {
+            index: idx,
+            text: cue.text.clone(),
+            start_ms,
+            end_ms,
+            char_start,
+            char_end: char_cursor,
+            words: estimate_words(&cue.text, char_start, start_ms, end_ms),
+        });
+        // Cue texts are joined with a newline in the read-along text.
+        char_cursor += 1;
+    }
+    if let Some(last) = cues.last() {
+        let position = audio.duration_ms();
+        if position < last.end_ms {
+            audio.push_silence_ms(last.end_ms - position);
+        }
+    }
+    let timings = TimingMap {
+        duration_ms: audio.duration_ms(),
+        segments,
+    };
+    Ok(DubbedTrack {
+        audio,
+        timings,
+        warnings,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cue(index: usize, start_ms: u64, end_ms: u64) -> SubtitleCue {
+        SubtitleCue {
+            index,
+            start_ms,
+            end_ms,
+            text: format!("Frase {index}."),
+        }
+    }
+
+    fn clip(ms: u64) -> WavAudio {
+        let mut audio = WavAudio::new(1000, 1);
+        audio.samples = vec![1000; ms as usize];
+        audio
+    }
+
+    #[test]
+    fn places_clips_at_cue_starts() {
+        let cues = vec![cue(1, 500, 1500), cue(2, 2000, 2500), cue(3, 2600, 4000)];
+        let track = assemble_track(&cues, &[clip(800), clip(700), clip(300)]).unwrap();
+        let spans: Vec<_> = track
+            .timings
+            .segments
+            .iter()
+            .map(|segment| (segment.start_ms, segment.end_ms))
+            .collect();
+        assert_eq!(spans, vec![(500, 1300), (2000, 2700), (2700, 3000)]);
+        assert_eq!(track.audio.duration_ms(), 4000);
+        assert!(track.audio.samples[..500].iter().all(|&sample| sample == 0));
+        assert_eq!(
+            track.warnings,
+            vec!["cue 2 overruns its slot by 100 ms", "cue 3 starts 100 ms late"]
+        );
+        assert_eq!(track.timings.segments[1].char_start, "Frase 1.\n".chars().count());
+    }
+
+    #[test]
+    fn trims_silence_around_speech() {
+        let mut audio = clip(0);
+        audio.push_silence_ms(200);
+        audio.samples.extend([1000; 300]);
+        audio.push_silence_ms(100);
+        assert_eq!(trim_silence(&audio).duration_ms(), 300);
+    }
+}
@@ -0,0 +1,261 @@
+//! espeak-ng as a second synthesizer. It sounds robotic next to Piper but
+//! speaks about a hundred languages, and is fast enough to run once per
+//! clip without the synthesis cache.
+
+use crate::audio::{parse_wav, WavAudio};
+use crate::text::split_sentences;
+use crate::tts::{Capabilities, PcmStream, TtsBackend, VoiceInfo};
+use anyhow::{anyhow, Context, Result};
+use async_process::Command;
+use futures::future::BoxFuture;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+
+/// espeak-ng's default speaking rate, for a length scale of 1.
+const WORDS_PER_MINUTE: f32 = 175.0;
+const MIN_WORDS_PER_MINUTE: f32 = 80.0;
+const MAX_WORDS_PER_MINUTE: f32 = 450.0;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct EspeakConfig {
+    pub enabled: bool,
+    pub binary_path: PathBuf,
+    /// Longest a single espeak-ng run may take before it is killed.
+    pub timeout_secs: u64,
+}
+
+impl Default for EspeakConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            binary_path: PathBuf::from(std::env::var("ESPEAK_BIN").unwrap_or_else(|_| "espeak-ng".into())),
+            timeout_secs: 60,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum EspeakError {
+    #[error("failed to spawn espeak-ng: {0}")]
+    Spawn(#[source] std::io::Error),
+    #[error("espeak-ng failed: {0}")]
+    CommandFailed(String),
+    #[error("espeak-ng did not finish within {0} seconds")]
+    TimedOut(u64),
+}
+
+#[derive(Debug, Clone)]
+pub struct EspeakEngine {
+    config: Arc<EspeakConfig>,
+    voices: BTreeMap<String, VoiceInfo>,
+}
+
+impl EspeakEngine {
+    /// Lists the installed voices; without espeak-ng the backend has none.
+    pub fn new(config: EspeakConfig) -> Self {
+        let voices = match std::process::Command::new(&config.binary_path).arg("--voices").output() {
+            Ok(output) if output.status.success() => parse_voices(&String::from_utf8_lossy(&output.stdout)),
+            Ok(output) => {
+                log::warn!(
+                    "espeak-ng --voices failed: {}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                );
+                BTreeMap::new()
+            }
+            Err(err) => {
+                log::info!("espeak-ng '{}' not available: {err}", config.binary_path.display());
+                BTreeMap::new()
+            }
+        };
+        Self {
+            config: Arc::new(config),
+            voices,
+        }
+    }
+
+    async fn synthesize_clip(&self, voice: &str, text: &str, speed: Option<f32>) -> Result<WavAudio> {
+        let words_per_minute =
+            (WORDS_PER_MINUTE / speed.unwrap_or(1.0).max(0.1)).clamp(MIN_WORDS_PER_MINUTE, MAX_WORDS_PER_MINUTE);
+        let mut cmd = Command::new(&self.config.binary_path);
+        cmd.arg("-v")
+            .arg(voice)
+            .arg("-s")
+            .arg(format!("{}", words_per_minute.round()))
+            .arg("--stdout");
+        // The text goes through stdin so that it is never taken for an option.
+        cmd.stdin(async_process::Stdio::piped())
+            .stdout(async_process::Stdio::piped())
+            .stderr(async_process::Stdio::piped())
+            .kill_on_drop(true);
+        let mut child = cmd.spawn().map_err(EspeakError::Spawn)?;
+        // Written from a task while `output` reads the audio, which espeak-ng
+        // starts writing before it has read a long text to the end.
+        let writer = child.stdin.take().map(|mut stdin| {
+            let text = text.to_string();
+            async_std::task::spawn(async move {
+                use async_std::io::WriteExt;
+                stdin.write_all(text.as_bytes()).await
+            })
+        });
+        // Dropping the timed-out `output` future drops the child, and
+        // `kill_on_drop` kills it.
+        let timeout = Duration::from_secs(self.config.timeout_secs.max(1));
+        let output = async_std::future::timeout(timeout, child.output())
+            .await
+            .map_err(|_| EspeakError::TimedOut(self.config.timeout_secs))??;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(EspeakError::CommandFailed(format!("{}: {}", output.status, stderr.trim())).into());
+        }
+        if let Some(writer) = writer {
+            writer.await.context("write text to espeak-ng")?;
+        }
+        parse_wav(&output.stdout)
+    }
+}
+
+impl TtsBackend for EspeakEngine {
+    fn id(&self) -> &'static str {
+        "espeak"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        // espeak-ng reads SSML with -m, but texts arrive split into
+        // sentences, which would cut the markup apart.
+        Capabilities {
+            ssml: false,
+            speakers: false,
+            cached: false,
+        }
+    }
+
+    fn voices(&self) -> Vec<VoiceInfo> {
+        self.voices.values().cloned().collect()
+    }
+
+    fn synthesize_clips<'a>(
+        &'a self,
+        voice: &'a VoiceInfo,
+        texts: &'a [&'a str],
+        _speaker: Option<u32>,
+        speed: Option<f32>,
+    ) -> BoxFuture<'a, Result<Vec<WavAudio>>> {
+        Box::pin(async move {
+            let mut clips = Vec::with_capacity(texts.len());
+            for text in texts {
+                clips.push(self.synthesize_clip(&voice.name, text, speed).await?);
+            }
+            Ok(clips)
+        })
+    }
+
+    /// One chunk per sentence, each ready as soon as espeak-ng has spoken it.
+    fn synthesize_stream(
+        &self,
+        voice: &VoiceInfo,
+        text: &str,
+        _speaker: Option<u32>,
+        speed: Option<f32>,
+    ) -> Result<PcmStream> {
+        let sentences: Vec<String> = split_sentences(text)
+            .into_iter()
+            .map(|sentence| sentence.text)
+            .collect();
+        if sentences.is_empty() {
+            return Err(anyhow!("no text to synthesize"));
+        }
+        let engine = self.clone();
+        let voice = voice.name.clone();
+        Ok(Box::pin(futures::stream::iter(sentences).then(move |sentence| {
+            let engine = engine.clone();
+            let voice = voice.clone();
+            async move { engine.synthesize_clip(&voice, &sentence, speed).await }
+        })))
+    }
+}
+
+/// Reads the table printed by `espeak-ng --voices`:
+///
+/// ```text
+/// Pty Language       Age/Gender VoiceName          File                 Other Languages
+///  5  eu              --/M      Basque             eu
+/// ```
+fn parse_voices(listing: &str) -> BTreeMap<String, VoiceInfo> {
+    listing
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let mut columns = line.split_whitespace().skip(1);
+            let language = columns.next()?;
+            let description = columns.nth(1).map(|name| name.replace('_', " "));
+            let voice = VoiceInfo {
+                name: language.to_string(),
+                backend: "espeak".into(),
+                description,
+                language: Some(language.to_string()),
+                quality: None,
+                model_path: None,
+            };
+            Some((language.to_string(), voice))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_the_voice_table() {
+        let listing = "Pty Language       Age/Gender VoiceName          File                 Other Languages\n \
+                       5  eu              --/M      Basque             eu\n \
+                       5  es-419          --/M      Spanish_(Latin_America) roa/es-419     (es-mx 6)\n";
+        let voices = parse_voices(listing);
+        assert_eq!(voices.len(), 2);
+        assert_eq!(voices["eu"].description.as_deref(), Some("Basque"));
+        assert_eq!(voices["es-419"].description.as_deref(), Some("Spanish (Latin America)"));
+        assert_eq!(voices["es-419"].language.as_deref(), Some("es-419"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn long_texts_do_not_block_on_the_audio_pipe() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = std::env::temp_dir().join(format!("piperdesk-espeak-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&root).unwrap();
+        let mut audio = WavAudio::new(22_050, 1);
+        audio.samples = vec![7; 200_000];
+        let clip = root.join("clip.wav");
+        crate::audio::write_wav(&clip, &audio).unwrap();
+        // Like espeak-ng, writes audio before it has read all of its input.
+        let binary_path = root.join("espeak-ng");
+        let script = format!("#!/bin/sh\ncat '{}'\ncat > /dev/null\n", clip.display());
+        std::fs::write(&binary_path, script).unwrap();
+        std::fs::set_permissions(&binary_path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let engine = EspeakEngine {
+            config: Arc::new(EspeakConfig {
+                enabled: true,
+                binary_path,
+                ..EspeakConfig::default()
+            }),
+            voices: BTreeMap::new(),
+        };
+
+        let text = "palabra ".repeat(50_000);
+        let synthesized = async_std::task::block_on(async_std::future::timeout(
+            std::time::Duration::from_secs(30),
+            engine.synthesize_clip("es", &text, None),
+        ))
+        .expect("espeak-ng blocked")
+        .unwrap();
+        assert_eq!(synthesized.samples.len(), audio.samples.len());
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,217 @@
+use async_process::{Command, Stdio};
+use async_std::io::ReadExt;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+use std::time::Duration;
+use thiserror::Error;
+
+/// Oldest major release the MP3 pipeline has been tested with.
+const MIN_MAJOR_VERSION: u32 = 4;
+const STDERR_TAIL_LINES: usize = 20;
+const FALLBACK_LOCATIONS: &[&str] = &[
+    "/usr/local/bin/ffmpeg",
+    "/opt/homebrew/bin/ffmpeg",
+    "/usr/bin/ffmpeg",
+    "C:\\ffmpeg\\bin\\ffmpeg.exe",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct FfmpegConfig {
+    /// Explicit binary to use; when unset ffmpeg is looked up on `PATH` and in
+    /// common install locations.
+    pub binary_path: Option<PathBuf>,
+    pub timeout_secs: u64,
+    /// Encoder arguments placed between the input and the output file.
+    pub mp3_args: Vec<String>,
+}
+
+impl Default for FfmpegConfig {
+    fn default() -> Self {
+        Self {
+            binary_path: std::env::var_os("FFMPEG_BIN").map(PathBuf::from),
+            timeout_secs: 300,
+            mp3_args: ["-codec:a", "libmp3lame", "-q:a", "2"].map(String::from).to_vec(),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum FfmpegError {
+    #[error("ffmpeg not found ({0}); install it or set its path in the settings")]
+    NotFound(String),
+    #[error("failed to run ffmpeg at '{path}': {source}")]
+    Spawn {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("ffmpeg exited with {status}: {stderr}")]
+    Failed { status: ExitStatus, stderr: String },
+    #[error("ffmpeg did not finish within {0} seconds")]
+    TimedOut(u64),
+    #[error("unrecognised `ffmpeg -version` output: {0}")]
+    UnknownVersion(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FfmpegInfo {
+    pub path: PathBuf,
+    pub version: String,
+    pub major: Option<u32>,
+    pub supported: bool,
+}
+
+pub fn locate(config: &FfmpegConfig) -> Result<PathBuf, FfmpegError> {
+    if let Some(path) = &config.binary_path {
+        if path.is_absolute() {
+            return if path.is_file() {
+                Ok(path.clone())
+            } else {
+                Err(FfmpegError::NotFound(format!("'{}' does not exist", path.display())))
+            };
+        }
+        return which::which(path).map_err(|err| FfmpegError::NotFound(format!("'{}': {err}", path.display())));
+    }
+    if let Ok(path) = which::which("ffmpeg") {
+        return Ok(path);
+    }
+    FALLBACK_LOCATIONS
+        .iter()
+        .map(PathBuf::from)
+        .find(|path| path.is_file())
+        .ok_or_else(|| FfmpegError::NotFound("not on PATH".to_string()))
+}
+
+/// Locates ffmpeg and runs `ffmpeg -version` to make sure it actually works.
+pub async fn detect(config: &FfmpegConfig) -> Result<FfmpegInfo, FfmpegError> {
+    let path = locate(config)?;
+    let output = run(&path, &["-hide_banner".into(), "-version".into()], config.timeout_secs).await?;
+    let first_line = output.lines().next().unwrap_or_default();
+    let version = parse_version(first_line).ok_or_else(|| FfmpegError::UnknownVersion(first_line.to_string()))?;
+    let major = parse_major(&version);
+    Ok(FfmpegInfo {
+        path,
+        supported: !matches!(major, Some(major) if major < MIN_MAJOR_VERSION),
+        version,
+        major,
+    })
+}
+
+pub async fn transcode_wav_to_mp3(config: &FfmpegConfig, wav_path: &Path, mp3_path: &Path) -> Result<(), FfmpegError> {
+    let path = locate(config)?;
+    let mut args: Vec<String> = ["-nostdin", "-hide_banner", "-loglevel", "error", "-y", "-i"]
+        .map(String::from)
+        .to_vec();
+    args.push(wav_path.display().to_string());
+    args.extend(config.mp3_args.iter().cloned());
+    args.push(mp3_path.display().to_string());
+    run(&path, &args, config.timeout_secs).await.map(|_| ())
+}
+
+/// Decodes any audio ffmpeg reads, such as an exported MP3, into 16-bit PCM WAV.
+pub async fn decode_to_wav(config: &FfmpegConfig, input: &Path, wav_path: &Path) -> Result<(), FfmpegError> {
+    let path = locate(config)?;
+    let mut args: Vec<String> = ["-nostdin", "-hide_banner", "-loglevel", "error", "-y", "-i"]
+        .map(String::from)
+        .to_vec();
+    args.push(input.display().to_string());
+    args.extend(["-codec:a", "pcm_s16le", "-f", "wav"].map(String::from));
+    args.push(wav_path.display().to_string());
+    run(&path, &args, config.timeout_secs).await.map(|_| ())
+}
+
+/// Runs ffmpeg without blocking the async runtime, killing it after
+/// `timeout_secs`. Returns stdout; stderr is attached to any error.
+async fn run(path: &Path, args: &[String], timeout_secs: u64) -> Result<String, FfmpegError> {
+    let spawn_error = |source| FfmpegError::Spawn {
+        path: path.to_path_buf(),
+        source,
+    };
+    let mut child = Command::new(path)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(spawn_error)?;
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let mut stderr = child.stderr.take().expect("stderr is piped");
+    let stdout_task = async_std::task::spawn(async move {
+        let mut buf = String::new();
+        let _ = stdout.read_to_string(&mut buf).await;
+        buf
+    });
+    let stderr_task = async_std::task::spawn(async move {
+        let mut buf = String::new();
+        let _ = stderr.read_to_string(&mut buf).await;
+        buf
+    });
+
+    let timeout = Duration::from_secs(timeout_secs.max(1));
+    let status = match async_std::future::timeout(timeout, child.status()).await {
+        Ok(status) => status.map_err(spawn_error)?,
+        Err(_) => {
+            let _ = child.kill();
+            let _ = child.status().await;
+            return Err(FfmpegError::TimedOut(timeout_secs));
+        }
+    };
+    let stdout = stdout_task.await;
+    let stderr = stderr_task.await;
+    if !status.success() {
+        return Err(FfmpegError::Failed {
+            status,
+            stderr: tail(&stderr),
+        });
+    }
+    Ok(stdout)
+}
+
+fn tail(stderr: &str) -> String {
+    let lines: Vec<&str> = stderr.lines().filter(|line| !line.trim().is_empty()).collect();
+    lines[lines.len().saturating_sub(STDERR_TAIL_LINES)..].join("\n")
+}
+
+fn parse_version(line: &str) -> Option<String> {
+    let rest = line.trim().strip_prefix("ffmpeg version ")?;
+    rest.split_whitespace().next().map(str::to_string)
+}
+
+fn parse_major(version: &str) -> Option<u32> {
+    // Git snapshots report e.g. "N-111234-g..." which carries no release number.
+    if version.starts_with("N-") {
+        return None;
+    }
+    let digits: String = version
+        .trim_start_matches(|c: char| !c.is_ascii_digit())
+        .chars()
+        .take_while(char::is_ascii_digit)
+        .collect();
+    digits.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_version_lines() {
+        let version = parse_version("ffmpeg version 6.1.1-3ubuntu5 Copyright (c) 2000-2023").unwrap();
+        assert_eq!(version, "6.1.1-3ubuntu5");
+        assert_eq!(parse_major(&version), Some(6));
+        assert_eq!(parse_major("n4.4.2"), Some(4));
+        assert_eq!(parse_major("N-111234-g1234abcd"), None);
+        assert_eq!(parse_version("avconv version 9"), None);
+    }
+
+    #[test]
+    fn keeps_only_the_end_of_stderr() {
+        let stderr: String = (0..30).map(|i| format!("line {i}\n")).collect();
+        let tail = tail(&stderr);
+        assert!(tail.starts_with("line 10"));
+        assert!(tail.ends_with("line 29"));
+    }
+}
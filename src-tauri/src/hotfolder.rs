@@ -0,0 +1,327 @@
+use crate::audio::AudioFormat;
+use crate::importers::folder::natural_cmp;
+use crate::importers::{self, SUPPORTED_EXTENSIONS};
+use crate::naming::claim_unique_path;
+use crate::queue::{self, EnqueueDocumentOptions};
+use crate::retry;
+use crate::{QueueItem, SharedState};
+use anyhow::{Context, Result};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Subfolders of the inbox that processed sources are moved to.
+const DONE_DIR: &str = "done";
+const FAILED_DIR: &str = "failed";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct HotFolderConfig {
+    pub enabled: bool,
+    /// Directory watched for new documents.
+    pub inbox: Option<PathBuf>,
+    /// Where audio is written; defaults to `outbox` inside the inbox.
+    pub outbox: Option<PathBuf>,
+    /// Voice for every document; `None` picks one from the document language.
+    pub voice: Option<String>,
+    pub rate: f32,
+    pub format: AudioFormat,
+    pub poll_secs: u64,
+}
+
+impl Default for HotFolderConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            inbox: None,
+            outbox: None,
+            voice: None,
+            rate: 1.0,
+            format: AudioFormat::Wav,
+            poll_secs: 5,
+        }
+    }
+}
+
+impl HotFolderConfig {
+    pub fn outbox_dir(&self, inbox: &Path) -> PathBuf {
+        self.outbox.clone().unwrap_or_else(|| inbox.join("outbox"))
+    }
+
+    /// Creates the inbox, its `done/` and `failed/` folders and the outbox.
+    pub fn prepare(&self) -> Result<()> {
+        let Some(inbox) = &self.inbox else {
+            anyhow::bail!("no inbox directory configured");
+        };
+        for dir in [
+            inbox.clone(),
+            inbox.join(DONE_DIR),
+            inbox.join(FAILED_DIR),
+            self.outbox_dir(inbox),
+        ] {
+            fs::create_dir_all(&dir).with_context(|| format!("failed to create {}", dir.display()))?;
+        }
+        Ok(())
+    }
+}
+
+/// Reported for every document taken from the inbox.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HotFolderEvent {
+    pub source: PathBuf,
+    /// New location of the source in `done/` or `failed/`.
+    pub moved_to: Option<PathBuf>,
+    pub outputs: Vec<PathBuf>,
+    pub error: Option<String>,
+}
+
+/// Tracks inbox files between polls so documents still being copied onto a
+/// shared drive are not picked up half-written.
+#[derive(Debug, Default)]
+pub struct InboxScanner {
+    seen: HashMap<PathBuf, (u64, Option<SystemTime>)>,
+    /// Sources that could not be moved out of the inbox after processing.
+    skipped: HashSet<PathBuf>,
+}
+
+impl InboxScanner {
+    /// Never returns `path` again, so a source that cannot be filed away is
+    /// not synthesized on every poll.
+    pub fn skip(&mut self, path: &Path) {
+        self.skipped.insert(path.to_path_buf());
+    }
+
+    /// Supported files directly in `inbox` whose size and modification time
+    /// have not changed since the previous scan, in natural order.
+    pub fn ready_files(&mut self, inbox: &Path) -> Result<Vec<PathBuf>> {
+        let mut current = HashMap::new();
+        for entry in fs::read_dir(inbox).with_context(|| format!("failed to read {}", inbox.display()))? {
+            let entry = entry?;
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().into_owned();
+            // Editors and sync clients create hidden and lock files while writing.
+            if name.starts_with('.') || name.starts_with("~$") || !is_supported(&path) {
+                continue;
+            }
+            let meta = entry.metadata()?;
+            if meta.is_file() {
+                current.insert(path, (meta.len(), meta.modified().ok()));
+            }
+        }
+        let mut ready: Vec<PathBuf> = current
+            .iter()
+            .filter(|(path, state)| self.seen.get(*path) == Some(state) && !self.skipped.contains(*path))
+            .map(|(path, _)| path.clone())
+            .collect();
+        ready.sort_by(|a, b| natural_cmp(&a.to_string_lossy(), &b.to_string_lossy()));
+        self.seen = current;
+        Ok(ready)
+    }
+}
+
+fn is_supported(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| SUPPORTED_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+/// Moves `source` into `done/` or, with an error, into `failed/` next to a
+/// `.error.log` file describing what went wrong.
+pub fn file_away(inbox: &Path, source: &Path, error: Option<&str>) -> Result<PathBuf> {
+    let dir = inbox.join(if error.is_some() { FAILED_DIR } else { DONE_DIR });
+    let name = source.file_name().context("source has no file name")?;
+    let target = claim_unique_path(&dir.join(name))?;
+    if fs::rename(source, &target).is_err() {
+        // Renaming fails across volumes; copy instead.
+        fs::copy(source, &target).with_context(|| format!("failed to move {}", source.display()))?;
+        fs::remove_file(source)?;
+    }
+    if let Some(error) = error {
+        let mut log = target.clone().into_os_string();
+        log.push(".error.log");
+        let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S");
+        fs::write(&log, format!("{timestamp}\n{}\n{error}\n", source.display()))?;
+    }
+    Ok(target)
+}
+
+/// Polls the configured inbox until the application exits. Each stable
+/// document is imported, queued with the folder profile and synthesized into
+/// the outbox before the next one is taken; `on_processed` is called with the
+/// outcome.
+pub async fn watch(state: &SharedState, pdf_script: Option<PathBuf>, mut on_processed: impl FnMut(&HotFolderEvent)) {
+    let mut scanner = InboxScanner::default();
+    loop {
+        let config = state.config.lock().hot_folder.clone();
+        if let (true, Some(inbox)) = (config.enabled, config.inbox.clone()) {
+            match scanner.ready_files(&inbox) {
+                Ok(files) => {
+                    for source in files {
+                        let event = process_file(state, pdf_script.clone(), &config, &inbox, &source).await;
+                        if event.moved_to.is_none() {
+                            scanner.skip(&source);
+                        }
+                        on_processed(&event);
+                    }
+                }
+                Err(err) => log::warn!("hot folder scan failed: {err:#}"),
+            }
+        }
+        async_std::task::sleep(Duration::from_secs(config.poll_secs.max(1))).await;
+    }
+}
+
+async fn process_file(
+    state: &SharedState,
+    pdf_script: Option<PathBuf>,
+    config: &HotFolderConfig,
+    inbox: &Path,
+    source: &Path,
+) -> HotFolderEvent {
+    let outbox = config.outbox_dir(inbox);
+    log::info!("hot folder: importing {}", source.display());
+    let mut outputs = Vec::new();
+    let mut errors = Vec::new();
+    let imported = async {
+        let settings = state.config.lock().import_settings(pdf_script, None);
+        let document = importers::import_file(source, &settings).await?;
+        let options = EnqueueDocumentOptions {
+            project: None,
+            voice: config.voice.clone(),
+            rate: Some(config.rate),
+            format: Some(config.format),
+        };
+        queue::enqueue_document(state, document, options)
+    }
+    .await;
+    match imported {
+        Ok(items) => {
+            // Every chapter is synthesized even if an earlier one failed, so
+            // the outbox holds as much of the document as possible.
+            for item in items {
+                match synthesize_chapter(state, &item, &outbox).await {
+                    Ok(Some(output)) => outputs.push(output),
+                    Ok(None) => log::warn!(
+                        "hot folder: '{}' was taken or cancelled in the queue, output not in outbox",
+                        item.title
+                    ),
+                    Err(err) => {
+                        // The source is filed under failed/, so a chapter
+                        // left in the queue would only be synthesized again
+                        // into the export folder.
+                        queue::remove_item(state, item.id);
+                        errors.push(format!("{}: {err}", item.title));
+                    }
+                }
+            }
+        }
+        Err(err) => errors.push(err),
+    }
+
+    let error = (!errors.is_empty()).then(|| errors.join("\n"));
+    if let Some(error) = &error {
+        log::warn!("hot folder: {} failed: {error}", source.display());
+    }
+    let moved_to = match file_away(inbox, source, error.as_deref()) {
+        Ok(path) => Some(path),
+        Err(err) => {
+            log::error!(
+                "hot folder: could not move {} out of the inbox: {err:#}",
+                source.display()
+            );
+            None
+        }
+    };
+    HotFolderEvent {
+        source: source.to_path_buf(),
+        moved_to,
+        outputs,
+        error,
+    }
+}
+
+/// Synthesizes a queued chapter into the outbox, waiting out its automatic
+/// retries. `None` if the queue took or cancelled the chapter first.
+async fn synthesize_chapter(state: &SharedState, item: &QueueItem, outbox: &Path) -> Result<Option<PathBuf>, String> {
+    // Keeps the desktop app's retry worker from running the chapter into
+    // the export folder while this waits for the retry.
+    state.awaited_retries.lock().insert(item.id);
+    let result = loop {
+        let Some(claimed) = queue::claim_pending(state, item.id) else {
+            break Ok(None);
+        };
+        match queue::run_item(state, claimed, outbox).await {
+            Ok(done) => break Ok(done.output),
+            Err(err) => match queue::retry_scheduled(state, item.id) {
+                Some(at) => retry::sleep_until(at).await,
+                None => break Err(err),
+            },
+        }
+    };
+    state.awaited_retries.lock().remove(&item.id);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn picks_up_files_once_they_stop_changing() {
+        let inbox = std::env::temp_dir().join(format!("piperdesk-inbox-{}", Uuid::new_v4()));
+        fs::create_dir_all(inbox.join(DONE_DIR)).unwrap();
+        fs::write(inbox.join("10.txt"), "diez").unwrap();
+        fs::write(inbox.join("2.txt"), "dos").unwrap();
+        fs::write(inbox.join("~$borrador.docx"), "").unwrap();
+        fs::write(inbox.join("foto.jpg"), "").unwrap();
+
+        let mut scanner = InboxScanner::default();
+        assert!(scanner.ready_files(&inbox).unwrap().is_empty());
+        fs::write(inbox.join("10.txt"), "diez y más").unwrap();
+        assert_eq!(scanner.ready_files(&inbox).unwrap(), vec![inbox.join("2.txt")]);
+        assert_eq!(
+            scanner.ready_files(&inbox).unwrap(),
+            vec![inbox.join("2.txt"), inbox.join("10.txt")]
+        );
+        fs::remove_dir_all(&inbox).unwrap();
+    }
+
+    #[test]
+    fn skips_sources_that_could_not_be_filed_away() {
+        let inbox = std::env::temp_dir().join(format!("piperdesk-inbox-{}", Uuid::new_v4()));
+        fs::create_dir_all(&inbox).unwrap();
+        fs::write(inbox.join("atascado.txt"), "x").unwrap();
+        fs::write(inbox.join("nuevo.txt"), "y").unwrap();
+
+        let mut scanner = InboxScanner::default();
+        scanner.ready_files(&inbox).unwrap();
+        scanner.skip(&inbox.join("atascado.txt"));
+        assert_eq!(scanner.ready_files(&inbox).unwrap(), vec![inbox.join("nuevo.txt")]);
+        fs::remove_dir_all(&inbox).unwrap();
+    }
+
+    #[test]
+    fn files_away_sources_with_error_log() {
+        let inbox = std::env::temp_dir().join(format!("piperdesk-inbox-{}", Uuid::new_v4()));
+        fs::create_dir_all(&inbox).unwrap();
+        for _ in 0..2 {
+            fs::write(inbox.join("libro.epub"), "x").unwrap();
+            file_away(&inbox, &inbox.join("libro.epub"), Some("EPUB has an empty spine")).unwrap();
+        }
+        fs::write(inbox.join("notas.txt"), "x").unwrap();
+        let done = file_away(&inbox, &inbox.join("notas.txt"), None).unwrap();
+
+        assert_eq!(done, inbox.join(DONE_DIR).join("notas.txt"));
+        assert!(!inbox.join("libro.epub").exists());
+        let failed = inbox.join(FAILED_DIR);
+        assert!(failed.join("libro.epub").is_file() && failed.join("libro-2.epub").is_file());
+        let log = fs::read_to_string(failed.join("libro-2.epub.error.log")).unwrap();
+        assert!(log.ends_with("EPUB has an empty spine\n"), "{log}");
+        fs::remove_dir_all(&inbox).unwrap();
+    }
+}
@@ -0,0 +1,436 @@
+use super::package::{parse_xml, read_entry, PackageError};
+use super::xhtml::{collapse_whitespace, xhtml_to_text};
+use super::{Chapter, CoverImage, DocumentMetadata, ImportedDocument};
+use base64::Engine;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{Read, Seek};
+use std::path::Path;
+use thiserror::Error;
+use zip::ZipArchive;
+
+const CONTAINER_PATH: &str = "META-INF/container.xml";
+const CONTENT_MEDIA_TYPES: &[&str] = &["application/xhtml+xml", "text/html", "application/xml"];
+
+#[derive(Debug, Error)]
+pub enum EpubError {
+    #[error("failed to open EPUB: {0}")]
+    Open(#[from] std::io::Error),
+    #[error(transparent)]
+    Package(#[from] PackageError),
+    #[error("EPUB package document has no readable spine items")]
+    EmptySpine,
+}
+
+/// Imports an EPUB 2 or 3 book in reading order.
+///
+/// Content documents are read in OPF spine order; `linear="no"` items and the
+/// navigation document are skipped. Chapter titles come from the EPUB 3 nav
+/// document or the EPUB 2 NCX, falling back to the first heading of a file.
+/// Spine files without a title of their own are treated as continuations of
+/// the previous chapter, which is how split chapters are usually packaged.
+pub fn import_epub(path: &Path) -> Result<ImportedDocument, EpubError> {
+    read_epub(File::open(path)?)
+}
+
+pub fn read_epub<R: Read + Seek>(reader: R) -> Result<ImportedDocument, EpubError> {
+    let mut archive = ZipArchive::new(reader).map_err(PackageError::from)?;
+    let container = parse_xml(CONTAINER_PATH, &read_entry(&mut archive, CONTAINER_PATH)?)?;
+    let opf_path = container
+        .find(&|node| node.name == "rootfile")
+        .and_then(|rootfile| rootfile.attr("full-path"))
+        .map(str::to_string)
+        .ok_or_else(|| PackageError::MissingEntry("rootfile in META-INF/container.xml".to_string()))?;
+    let package = Package::parse(&opf_path, &read_entry(&mut archive, &opf_path)?)?;
+
+    let mut warnings = Vec::new();
+    let toc = match package.read_toc(&mut archive) {
+        Ok(toc) => toc,
+        Err(err) => {
+            warnings.push(format!("ignoring table of contents: {err}"));
+            HashMap::new()
+        }
+    };
+    let cover = package.cover.as_ref().and_then(|item| {
+        match archive
+            .by_name(&item.path)
+            .map_err(PackageError::from)
+            .and_then(|mut file| {
+                let mut data = Vec::new();
+                file.read_to_end(&mut data)?;
+                Ok(data)
+            }) {
+            Ok(data) => Some(CoverImage {
+                media_type: item.media_type.clone(),
+                data: base64::engine::general_purpose::STANDARD.encode(data),
+            }),
+            Err(err) => {
+                warnings.push(format!("ignoring cover image '{}': {err}", item.path));
+                None
+            }
+        }
+    });
+
+    let mut chapters: Vec<Chapter> = Vec::new();
+    let mut seen = HashSet::new();
+    for item in &package.spine {
+        if !seen.insert(item.path.as_str()) {
+            continue;
+        }
+        if item.is_nav || package.toc_pages.contains(&item.path) {
+            continue;
+        }
+        if !CONTENT_MEDIA_TYPES.contains(&item.media_type.as_str()) {
+            continue;
+        }
+        let markup = match read_entry(&mut archive, &item.path) {
+            Ok(markup) => markup,
+            Err(err) => {
+                warnings.push(format!("skipping '{}': {err}", item.path));
+                continue;
+            }
+        };
+        let converted = xhtml_to_text(&markup);
+        if converted.text.is_empty() {
+            continue;
+        }
+        let title = toc.get(&item.path).cloned().or(converted.heading);
+        match chapters.last_mut() {
+            Some(previous) if title.is_none() => {
+                previous.text.push_str("\n\n");
+                previous.text.push_str(&converted.text);
+            }
+            _ => chapters.push(Chapter {
+                title,
+                text: converted.text,
+            }),
+        }
+    }
+    if chapters.is_empty() {
+        return Err(EpubError::EmptySpine);
+    }
+
+    Ok(ImportedDocument {
+        metadata: DocumentMetadata {
+            cover,
+            ..package.metadata
+        },
+        chapters,
+        warnings,
+        ..ImportedDocument::default()
+    })
+}
+
+#[derive(Debug, Clone)]
+struct ManifestItem {
+    path: String,
+    media_type: String,
+    is_nav: bool,
+}
+
+/// The parts of the OPF package document the importer needs.
+#[derive(Debug)]
+struct Package {
+    metadata: DocumentMetadata,
+    spine: Vec<ManifestItem>,
+    nav: Option<ManifestItem>,
+    ncx: Option<ManifestItem>,
+    cover: Option<ManifestItem>,
+    /// EPUB 2 `<guide>` table-of-contents pages, which only repeat chapter titles.
+    toc_pages: HashSet<String>,
+}
+
+impl Package {
+    fn parse(opf_path: &str, content: &str) -> Result<Self, EpubError> {
+        let root = parse_xml(opf_path, content)?;
+        let base = parent_dir(opf_path);
+
+        let text_of = |name: &str| {
+            root.find(&|node| node.name == name)
+                .map(|node| collapse_whitespace(&node.text()))
+                .filter(|text| !text.is_empty())
+        };
+        let authors: Vec<String> = root
+            .find_all(&|node| node.name == "creator")
+            .into_iter()
+            .map(|node| collapse_whitespace(&node.text()))
+            .filter(|author| !author.is_empty())
+            .collect();
+        let metadata = DocumentMetadata {
+            title: text_of("title"),
+            author: (!authors.is_empty()).then(|| authors.join(", ")),
+            language: text_of("language"),
+            cover: None,
+        };
+
+        let mut manifest: HashMap<String, ManifestItem> = HashMap::new();
+        let mut nav = None;
+        let mut cover = None;
+        for node in root.find_all(&|node| node.name == "item") {
+            let (Some(id), Some(href)) = (node.attr("id"), node.attr("href")) else {
+                continue;
+            };
+            let properties: Vec<&str> = node.attr("properties").unwrap_or_default().split_whitespace().collect();
+            let item = ManifestItem {
+                path: resolve_href(&base, href),
+                media_type: node.attr("media-type").unwrap_or_default().to_ascii_lowercase(),
+                is_nav: properties.contains(&"nav"),
+            };
+            if item.is_nav {
+                nav = Some(item.clone());
+            }
+            if properties.contains(&"cover-image") {
+                cover = Some(item.clone());
+            }
+            manifest.insert(id.to_string(), item);
+        }
+        if cover.is_none() {
+            cover = root
+                .find(&|node| node.name == "meta" && node.attr("name") == Some("cover"))
+                .and_then(|meta| meta.attr("content"))
+                .and_then(|id| manifest.get(id))
+                .filter(|item| item.media_type.starts_with("image/"))
+                .cloned();
+        }
+
+        let spine_node = root.find(&|node| node.name == "spine");
+        let ncx = spine_node
+            .and_then(|spine| spine.attr("toc"))
+            .and_then(|id| manifest.get(id))
+            .or_else(|| {
+                manifest
+                    .values()
+                    .find(|item| item.media_type == "application/x-dtbncx+xml")
+            })
+            .cloned();
+        let spine = spine_node
+            .map(|spine| spine.find_all(&|node| node.name == "itemref"))
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|itemref| itemref.attr("linear") != Some("no"))
+            .filter_map(|itemref| itemref.attr("idref").and_then(|id| manifest.get(id)).cloned())
+            .collect();
+        let toc_pages = root
+            .find_all(&|node| node.name == "reference" && node.attr("type") == Some("toc"))
+            .into_iter()
+            .filter_map(|reference| reference.attr("href"))
+            .map(|href| resolve_href(&base, href))
+            .collect();
+
+        Ok(Self {
+            metadata,
+            spine,
+            nav,
+            ncx,
+            cover,
+            toc_pages,
+        })
+    }
+
+    /// Maps content document paths to the title of the first TOC entry
+    /// pointing into them.
+    fn read_toc<R: Read + Seek>(&self, archive: &mut ZipArchive<R>) -> Result<HashMap<String, String>, EpubError> {
+        let mut entries: Vec<(String, String)> = Vec::new();
+        if let Some(nav) = &self.nav {
+            let root = parse_xml(&nav.path, &read_entry(archive, &nav.path)?)?;
+            let base = parent_dir(&nav.path);
+            let toc_nav = root
+                .find(&|node| node.name == "nav" && node.attr("type").is_some_and(|kind| kind.contains("toc")))
+                .or_else(|| root.find(&|node| node.name == "nav"));
+            if let Some(toc_nav) = toc_nav {
+                for link in toc_nav.find_all(&|node| node.name == "a") {
+                    if let Some(href) = link.attr("href") {
+                        entries.push((resolve_href(&base, href), collapse_whitespace(&link.text())));
+                    }
+                }
+            }
+        }
+        if entries.is_empty() {
+            if let Some(ncx) = &self.ncx {
+                let root = parse_xml(&ncx.path, &read_entry(archive, &ncx.path)?)?;
+                let base = parent_dir(&ncx.path);
+                for point in root.find_all(&|node| node.name == "navPoint") {
+                    let label = point
+                        .child("navLabel")
+                        .and_then(|label| label.child("text"))
+                        .map(|text| collapse_whitespace(&text.text()));
+                    let src = point.child("content").and_then(|content| content.attr("src"));
+                    if let (Some(label), Some(src)) = (label, src) {
+                        entries.push((resolve_href(&base, src), label));
+                    }
+                }
+            }
+        }
+
+        let mut toc = HashMap::new();
+        for (path, title) in entries {
+            if !title.is_empty() {
+                toc.entry(path).or_insert(title);
+            }
+        }
+        Ok(toc)
+    }
+}
+
+fn parent_dir(path: &str) -> String {
+    path.rsplit_once('/')
+        .map(|(dir, _)| dir.to_string())
+        .unwrap_or_default()
+}
+
+/// Resolves a manifest or TOC `href` against the directory of the document
+/// that contains it, producing a ZIP entry name without fragment.
+fn resolve_href(base: &str, href: &str) -> String {
+    let href = href.split('#').next().unwrap_or_default();
+    let href = percent_decode(href);
+    let mut segments: Vec<&str> = if href.starts_with('/') {
+        Vec::new()
+    } else {
+        base.split('/').filter(|segment| !segment.is_empty()).collect()
+    };
+    for segment in href.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    segments.join("/")
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+            if let Some(byte) = hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+    use zip::write::FileOptions;
+
+    fn page(title: &str, body: &str) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<html xmlns="http://www.w3.org/1999/xhtml"><head><title>{title}</title></head><body>{body}</body></html>"#
+        )
+    }
+
+    fn build_epub() -> Vec<u8> {
+        let opf = r#"<?xml version="1.0" encoding="utf-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="id">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:title>El libro</dc:title>
+    <dc:creator>Ana García</dc:creator>
+    <dc:language>es</dc:language>
+    <meta name="cover" content="cover"/>
+  </metadata>
+  <manifest>
+    <item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+    <item id="cover" href="images/cover.png" media-type="image/png"/>
+    <item id="c1" href="text/cap%201.xhtml" media-type="application/xhtml+xml"/>
+    <item id="c1b" href="text/cap1b.xhtml" media-type="application/xhtml+xml"/>
+    <item id="c2" href="text/cap2.xhtml" media-type="application/xhtml+xml"/>
+    <item id="notes" href="text/notes.xhtml" media-type="application/xhtml+xml"/>
+  </manifest>
+  <spine>
+    <itemref idref="nav"/>
+    <itemref idref="c1"/>
+    <itemref idref="c1b"/>
+    <itemref idref="notes" linear="no"/>
+    <itemref idref="c2"/>
+  </spine>
+</package>"#;
+        let nav = page(
+            "Índice",
+            r#"<nav xmlns:epub="http://www.idpf.org/2007/ops" epub:type="toc"><ol>
+<li><a href="text/cap%201.xhtml#start">Uno</a></li>
+<li><a href="text/cap2.xhtml">Dos</a></li></ol></nav>"#,
+        );
+        let files = [
+            ("text/notes.xhtml", page("Notas", "<p>Nota al pie.</p>")),
+            ("text/cap2.xhtml", page("2", "<p>Segundo capítulo.</p>")),
+            ("OEBPS/nav.xhtml", nav),
+            ("text/cap1b.xhtml", page("1b", "<p>Sigue el primero.</p>")),
+            (
+                "text/cap 1.xhtml",
+                page("1", "<h2>Capítulo uno</h2><p>Primer capítulo.</p>"),
+            ),
+        ];
+
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = FileOptions::default();
+        writer.start_file("mimetype", options).unwrap();
+        writer.write_all(b"application/epub+zip").unwrap();
+        writer.start_file(CONTAINER_PATH, options).unwrap();
+        writer
+            .write_all(
+                br#"<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+<rootfiles><rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/></rootfiles></container>"#,
+            )
+            .unwrap();
+        writer.start_file("OEBPS/content.opf", options).unwrap();
+        writer.write_all(opf.as_bytes()).unwrap();
+        writer.start_file("OEBPS/images/cover.png", options).unwrap();
+        writer.write_all(b"png").unwrap();
+        for (name, content) in files {
+            let name = if name.starts_with("OEBPS/") {
+                name.to_string()
+            } else {
+                format!("OEBPS/{name}")
+            };
+            writer.start_file(name, options).unwrap();
+            writer.write_all(content.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn follows_spine_and_toc() {
+        let document = read_epub(Cursor::new(build_epub())).unwrap();
+        assert_eq!(document.metadata.title.as_deref(), Some("El libro"));
+        assert_eq!(document.metadata.author.as_deref(), Some("Ana García"));
+        assert_eq!(document.metadata.language.as_deref(), Some("es"));
+        let cover = document.metadata.cover.as_ref().unwrap();
+        assert_eq!((cover.media_type.as_str(), cover.data.as_str()), ("image/png", "cG5n"));
+        assert_eq!(
+            document.chapters,
+            vec![
+                Chapter {
+                    title: Some("Uno".into()),
+                    text: "Capítulo uno\n\nPrimer capítulo.\n\nSigue el primero.".into(),
+                },
+                Chapter {
+                    title: Some("Dos".into()),
+                    text: "Segundo capítulo.".into(),
+                },
+            ]
+        );
+        assert!(document.warnings.is_empty());
+    }
+
+    #[test]
+    fn resolves_relative_hrefs() {
+        assert_eq!(
+            resolve_href("OEBPS/text", "../images/a%20b.png#x"),
+            "OEBPS/images/a b.png"
+        );
+        assert_eq!(resolve_href("", "./chapter.xhtml"), "chapter.xhtml");
+    }
+}
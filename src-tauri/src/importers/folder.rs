@@ -0,0 +1,142 @@
+use super::SUPPORTED_EXTENSIONS;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use std::cmp::Ordering;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use walkdir::WalkDir;
+
+#[derive(Debug, Error)]
+pub enum FolderError {
+    #[error("'{0}' is not a directory")]
+    NotADirectory(PathBuf),
+    #[error("invalid pattern: {0}")]
+    Pattern(#[from] globset::Error),
+    #[error("failed to scan folder: {0}")]
+    Walk(#[from] walkdir::Error),
+}
+
+/// Finds importable documents below `root`, in natural order of their paths.
+///
+/// Patterns are matched case-insensitively against the path relative to
+/// `root`, with `/` as separator. When `include` is empty every supported
+/// file is taken; a file matching any `exclude` pattern is always skipped.
+pub fn scan_folder(root: &Path, include: &[String], exclude: &[String]) -> Result<Vec<PathBuf>, FolderError> {
+    if !root.is_dir() {
+        return Err(FolderError::NotADirectory(root.to_path_buf()));
+    }
+    let include = glob_set(include)?;
+    let exclude = glob_set(exclude)?;
+    let mut files = Vec::new();
+    for entry in WalkDir::new(root).follow_links(true) {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let supported = entry
+            .path()
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| SUPPORTED_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()));
+        let relative = entry.path().strip_prefix(root).unwrap_or(entry.path());
+        let relative = relative.to_string_lossy().replace('\\', "/");
+        let key = relative.to_lowercase();
+        let included = include.is_empty() || include.is_match(&key);
+        if supported && included && !exclude.is_match(&key) {
+            files.push((relative, entry.into_path()));
+        }
+    }
+    files.sort_by(|(a, _), (b, _)| natural_cmp(a, b));
+    Ok(files.into_iter().map(|(_, path)| path).collect())
+}
+
+fn glob_set(patterns: &[String]) -> Result<GlobSet, FolderError> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns.iter().filter(|pattern| !pattern.trim().is_empty()) {
+        builder.add(Glob::new(&pattern.trim().to_lowercase())?);
+    }
+    Ok(builder.build()?)
+}
+
+/// Orders strings the way people number files: `2.txt` before `10.txt`,
+/// ignoring case. Strings that only differ in case or zero padding fall back
+/// to plain ordering so the result is stable.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    natural_key_cmp(a, b).then_with(|| a.cmp(b))
+}
+
+fn natural_key_cmp(mut a: &str, mut b: &str) -> Ordering {
+    loop {
+        let (Some(ca), Some(cb)) = (a.chars().next(), b.chars().next()) else {
+            return a.len().cmp(&b.len());
+        };
+        let ordering = if ca.is_ascii_digit() && cb.is_ascii_digit() {
+            let (na, rest_a) = split_digits(a);
+            let (nb, rest_b) = split_digits(b);
+            a = rest_a;
+            b = rest_b;
+            let (ta, tb) = (na.trim_start_matches('0'), nb.trim_start_matches('0'));
+            ta.len().cmp(&tb.len()).then_with(|| ta.cmp(tb))
+        } else {
+            a = &a[ca.len_utf8()..];
+            b = &b[cb.len_utf8()..];
+            ca.to_lowercase().cmp(cb.to_lowercase())
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+fn split_digits(text: &str) -> (&str, &str) {
+    let end = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    text.split_at(end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn sorts_naturally() {
+        let mut names = vec!["Tema 10.pdf", "tema 2.pdf", "Tema 1b.pdf", "Anexo.txt", "Tema 02.pdf"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(
+            names,
+            vec!["Anexo.txt", "Tema 1b.pdf", "Tema 02.pdf", "tema 2.pdf", "Tema 10.pdf"]
+        );
+    }
+
+    #[test]
+    fn scans_supported_files_with_patterns() {
+        let root = std::env::temp_dir().join(format!("piperdesk-folder-{}", Uuid::new_v4()));
+        for file in [
+            "lote/10.txt",
+            "lote/2.TXT",
+            "lote/borrador-1.txt",
+            "informe.pdf",
+            "notas.xyz",
+        ] {
+            let path = root.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "texto").unwrap();
+        }
+        let relative = |include: &[&str], exclude: &[&str]| {
+            let strings = |patterns: &[&str]| patterns.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+            scan_folder(&root, &strings(include), &strings(exclude))
+                .unwrap()
+                .iter()
+                .map(|path| path.strip_prefix(&root).unwrap().to_string_lossy().replace('\\', "/"))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            relative(&[], &["**/borrador*"]),
+            vec!["informe.pdf", "lote/2.TXT", "lote/10.txt"]
+        );
+        assert_eq!(
+            relative(&["*.txt"], &[]),
+            vec!["lote/2.TXT", "lote/10.txt", "lote/borrador-1.txt"]
+        );
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,450 @@
+use super::xhtml::collapse_whitespace;
+use super::{chapters_from_blocks, Block, DocumentMetadata, ImportedDocument};
+use pulldown_cmark::{html, Options, Parser};
+use scraper::{ElementRef, Html, Node, Selector};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use thiserror::Error;
+
+/// Elements that hold page chrome or non-text content rather than prose.
+const SKIPPED_TAGS: &[&str] = &[
+    "head", "script", "style", "noscript", "template", "nav", "footer", "aside", "form", "button", "select", "iframe",
+    "svg", "canvas", "img", "picture", "video", "audio", "object",
+];
+const BLOCK_TAGS: &[&str] = &[
+    "p",
+    "div",
+    "section",
+    "article",
+    "main",
+    "header",
+    "blockquote",
+    "figure",
+    "figcaption",
+    "address",
+    "dl",
+    "dt",
+    "dd",
+    "details",
+    "summary",
+    "hr",
+    "body",
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CodeBlockMode {
+    Skip,
+    /// Replace the block with [`MarkupOptions::code_summary`].
+    #[default]
+    Summarize,
+    Read,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TableMode {
+    Skip,
+    /// One sentence per row with the cells in order.
+    #[default]
+    Rows,
+    /// Each cell prefixed with its column header, e.g. "Precio: 10".
+    HeaderValue,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct MarkupOptions {
+    pub code_blocks: CodeBlockMode,
+    /// Spoken instead of a code block; `{lines}` and `{language}` are replaced.
+    pub code_summary: String,
+    pub tables: TableMode,
+}
+
+impl Default for MarkupOptions {
+    fn default() -> Self {
+        Self {
+            code_blocks: CodeBlockMode::default(),
+            code_summary: "Bloque de código de {lines} líneas.".to_string(),
+            tables: TableMode::default(),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum MarkupError {
+    #[error("failed to read document: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("document contains no readable text")]
+    NoText,
+}
+
+pub fn import_html(path: &Path, options: &MarkupOptions) -> Result<ImportedDocument, MarkupError> {
+    let bytes = std::fs::read(path)?;
+    html_to_document(&String::from_utf8_lossy(&bytes), options)
+}
+
+pub fn import_markdown(path: &Path, options: &MarkupOptions) -> Result<ImportedDocument, MarkupError> {
+    let bytes = std::fs::read(path)?;
+    markdown_to_document(&String::from_utf8_lossy(&bytes), options)
+}
+
+/// Renders Markdown to HTML and reads that, so both formats share one set of
+/// rules. YAML front matter provides title, author and language.
+pub fn markdown_to_document(markdown: &str, options: &MarkupOptions) -> Result<ImportedDocument, MarkupError> {
+    let (front_matter, body) = split_front_matter(markdown);
+    let mut rendered = String::new();
+    let parser = Parser::new_ext(
+        body,
+        Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_FOOTNOTES,
+    );
+    html::push_html(&mut rendered, parser);
+    let mut document = html_to_document(&rendered, options)?;
+    let metadata = &mut document.metadata;
+    metadata.title = front_matter.title.or(metadata.title.take());
+    metadata.author = front_matter.author.or(metadata.author.take());
+    metadata.language = front_matter.language.or(metadata.language.take());
+    Ok(document)
+}
+
+/// Converts an HTML page to chapters of speakable paragraphs.
+///
+/// Headings split chapters, list items are numbered, links keep only their
+/// text and navigation, footers and asides are dropped. When the page has a
+/// `<main>` or `<article>` element only that is read.
+pub fn html_to_document(markup: &str, options: &MarkupOptions) -> Result<ImportedDocument, MarkupError> {
+    let page = Html::parse_document(markup);
+    let select_first = |selector: &str| {
+        let selector = Selector::parse(selector).expect("valid selector");
+        page.select(&selector).next()
+    };
+    let content = select_first("main")
+        .or_else(|| select_first("article"))
+        .or_else(|| select_first("body"))
+        .unwrap_or_else(|| page.root_element());
+
+    let mut reader = Reader {
+        options,
+        blocks: Vec::new(),
+        current: String::new(),
+        prefix: None,
+    };
+    reader.walk(content);
+    reader.flush();
+
+    let first_heading = reader.blocks.iter().find_map(|block| match block {
+        Block::Heading { text, .. } => Some(text.clone()),
+        Block::Paragraph(_) => None,
+    });
+    let metadata = DocumentMetadata {
+        title: select_first("title")
+            .map(|title| collapse_whitespace(&title.text().collect::<String>()))
+            .filter(|title| !title.is_empty())
+            .or(first_heading),
+        author: select_first("meta[name=author]")
+            .and_then(|meta| meta.value().attr("content"))
+            .map(collapse_whitespace)
+            .filter(|author| !author.is_empty()),
+        language: select_first("html")
+            .and_then(|html| html.value().attr("lang"))
+            .map(str::to_string)
+            .filter(|lang| !lang.is_empty()),
+        cover: None,
+    };
+    let chapters = chapters_from_blocks(reader.blocks);
+    if chapters.is_empty() {
+        return Err(MarkupError::NoText);
+    }
+    Ok(ImportedDocument {
+        metadata,
+        chapters,
+        ..ImportedDocument::default()
+    })
+}
+
+struct Reader<'o> {
+    options: &'o MarkupOptions,
+    blocks: Vec<Block>,
+    current: String,
+    /// Enumerator for the list item whose text is being collected.
+    prefix: Option<String>,
+}
+
+impl Reader<'_> {
+    fn flush(&mut self) {
+        let text = collapse_whitespace(&self.current);
+        self.current.clear();
+        if text.is_empty() {
+            return;
+        }
+        let text = match self.prefix.take() {
+            Some(prefix) => format!("{prefix} {text}"),
+            None => text,
+        };
+        self.blocks.push(Block::Paragraph(text));
+    }
+
+    fn walk(&mut self, element: ElementRef<'_>) {
+        for child in element.children() {
+            match child.value() {
+                Node::Text(text) => self.current.push_str(text),
+                Node::Element(_) => {
+                    if let Some(child) = ElementRef::wrap(child) {
+                        self.element(child);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn element(&mut self, element: ElementRef<'_>) {
+        let name = element.value().name();
+        if SKIPPED_TAGS.contains(&name) || is_hidden(element) {
+            return;
+        }
+        match name {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                self.flush();
+                let text = element_text(element);
+                if !text.is_empty() {
+                    let level = name[1..].parse().unwrap_or(1);
+                    self.blocks.push(Block::Heading { level, text });
+                }
+            }
+            "ul" | "ol" => {
+                self.flush();
+                let start: usize = element
+                    .value()
+                    .attr("start")
+                    .and_then(|start| start.parse().ok())
+                    .unwrap_or(1);
+                let items = element
+                    .children()
+                    .filter_map(ElementRef::wrap)
+                    .filter(|child| child.value().name() == "li");
+                for (offset, item) in items.enumerate() {
+                    self.prefix = Some(format!("{}.", start + offset));
+                    self.walk(item);
+                    self.flush();
+                    self.prefix = None;
+                }
+            }
+            "pre" => {
+                self.flush();
+                self.code_block(element);
+            }
+            "table" => {
+                self.flush();
+                self.table(element);
+            }
+            "br" => self.current.push(' '),
+            name if BLOCK_TAGS.contains(&name) || name == "li" => {
+                self.flush();
+                self.walk(element);
+                self.flush();
+            }
+            _ => self.walk(element),
+        }
+    }
+
+    fn code_block(&mut self, element: ElementRef<'_>) {
+        let code: String = element.text().collect();
+        match self.options.code_blocks {
+            CodeBlockMode::Skip => {}
+            CodeBlockMode::Read => {
+                self.current.push_str(&code);
+                self.flush();
+            }
+            CodeBlockMode::Summarize => {
+                let language = element
+                    .descendants()
+                    .filter_map(ElementRef::wrap)
+                    .flat_map(|node| node.value().classes())
+                    .find_map(|class| class.strip_prefix("language-"))
+                    .unwrap_or_default()
+                    .to_string();
+                let lines = code.trim_end().lines().count();
+                self.current.push_str(
+                    &self
+                        .options
+                        .code_summary
+                        .replace("{lines}", &lines.to_string())
+                        .replace("{language}", &language),
+                );
+                self.flush();
+            }
+        }
+    }
+
+    fn table(&mut self, element: ElementRef<'_>) {
+        if self.options.tables == TableMode::Skip {
+            return;
+        }
+        let mut rows: Vec<(bool, Vec<String>)> = Vec::new();
+        for row in element.descendants().filter_map(ElementRef::wrap) {
+            if row.value().name() != "tr" {
+                continue;
+            }
+            let cells: Vec<ElementRef<'_>> = row
+                .children()
+                .filter_map(ElementRef::wrap)
+                .filter(|cell| matches!(cell.value().name(), "td" | "th"))
+                .collect();
+            let is_header = !cells.is_empty() && cells.iter().all(|cell| cell.value().name() == "th");
+            rows.push((is_header, cells.into_iter().map(element_text).collect()));
+        }
+        let header = match rows.first() {
+            Some((true, cells)) if self.options.tables == TableMode::HeaderValue => Some(cells.clone()),
+            _ => None,
+        };
+        for (index, (_, cells)) in rows.iter().enumerate() {
+            if header.is_some() && index == 0 {
+                continue;
+            }
+            let parts: Vec<String> = cells
+                .iter()
+                .enumerate()
+                .filter(|(_, cell)| !cell.is_empty())
+                .map(
+                    |(column, cell)| match header.as_ref().and_then(|header| header.get(column)) {
+                        Some(label) if !label.is_empty() => format!("{label}: {cell}"),
+                        _ => cell.clone(),
+                    },
+                )
+                .collect();
+            if !parts.is_empty() {
+                let separator = if header.is_some() { "; " } else { ", " };
+                self.current.push_str(&parts.join(separator));
+                self.current.push('.');
+                self.flush();
+            }
+        }
+    }
+}
+
+fn is_hidden(element: ElementRef<'_>) -> bool {
+    let value = element.value();
+    value.attr("hidden").is_some()
+        || value.attr("aria-hidden") == Some("true")
+        || matches!(value.attr("role"), Some("navigation" | "banner" | "contentinfo"))
+}
+
+/// Text content of an element without skipped descendants, whitespace collapsed.
+fn element_text(element: ElementRef<'_>) -> String {
+    fn collect(element: ElementRef<'_>, out: &mut String) {
+        for child in element.children() {
+            match child.value() {
+                Node::Text(text) => out.push_str(text),
+                Node::Element(_) => {
+                    if let Some(child) = ElementRef::wrap(child) {
+                        if !SKIPPED_TAGS.contains(&child.value().name()) && !is_hidden(child) {
+                            collect(child, out);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    let mut text = String::new();
+    collect(element, &mut text);
+    collapse_whitespace(&text)
+}
+
+/// Metadata from a leading `---` YAML block; only flat `key: value` lines are
+/// understood, which covers what static site generators write.
+#[derive(Debug, Default)]
+struct FrontMatter {
+    title: Option<String>,
+    author: Option<String>,
+    language: Option<String>,
+}
+
+fn split_front_matter(markdown: &str) -> (FrontMatter, &str) {
+    let mut front_matter = FrontMatter::default();
+    let Some(rest) = markdown
+        .strip_prefix("---\n")
+        .or_else(|| markdown.strip_prefix("---\r\n"))
+    else {
+        return (front_matter, markdown);
+    };
+    let Some(end) = rest.find("\n---") else {
+        return (front_matter, markdown);
+    };
+    for line in rest[..end].lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim().trim_matches(|c| c == '"' || c == '\'').to_string();
+        if value.is_empty() {
+            continue;
+        }
+        match key.trim() {
+            "title" => front_matter.title = Some(value),
+            "author" => front_matter.author = Some(value),
+            "lang" | "language" => front_matter.language = Some(value),
+            _ => {}
+        }
+    }
+    let body = rest[end + 4..].split_once('\n').map_or("", |(_, body)| body);
+    (front_matter, body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chapters(document: &ImportedDocument) -> Vec<(Option<&str>, &str)> {
+        document
+            .chapters
+            .iter()
+            .map(|chapter| (chapter.title.as_deref(), chapter.text.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn reads_markdown_structure() {
+        let markdown =
+            "---\ntitle: \"Guía\"\nlang: es\n---\n\n# Instalación\n\nDescarga el [paquete](https://example.com).\n\n\
+                        1. Abre el instalador\n2. Pulsa *Siguiente*\n\n```sh\n./install.sh\nreboot\n```\n\n\
+                        | Sistema | Versión |\n|---|---|\n| Linux | 6 |\n\n# Uso\n\n- Importa un documento\n";
+        let options = MarkupOptions {
+            tables: TableMode::HeaderValue,
+            ..MarkupOptions::default()
+        };
+        let document = markdown_to_document(markdown, &options).unwrap();
+        assert_eq!(document.metadata.title.as_deref(), Some("Guía"));
+        assert_eq!(document.metadata.language.as_deref(), Some("es"));
+        assert_eq!(
+            chapters(&document),
+            vec![
+                (
+                    Some("Instalación"),
+                    "Instalación.\n\nDescarga el paquete.\n\n1. Abre el instalador\n\n2. Pulsa Siguiente\n\n\
+                     Bloque de código de 2 líneas.\n\nSistema: Linux; Versión: 6."
+                ),
+                (Some("Uso"), "Uso.\n\n1. Importa un documento"),
+            ]
+        );
+    }
+
+    #[test]
+    fn drops_page_chrome_from_html() {
+        let page = r#"<!doctype html><html lang="en"><head><title>News</title><meta name="author" content="Ann"></head>
+<body><nav><a href="/">Home</a></nav><main><h2>Story</h2><p>First<br>line.</p><pre><code>let x = 1;</code></pre>
+<table><tr><td>a</td><td>b</td></tr></table></main><footer>Copyright</footer></body></html>"#;
+        let options = MarkupOptions {
+            code_blocks: CodeBlockMode::Skip,
+            ..MarkupOptions::default()
+        };
+        let document = html_to_document(page, &options).unwrap();
+        assert_eq!(document.metadata.title.as_deref(), Some("News"));
+        assert_eq!(document.metadata.author.as_deref(), Some("Ann"));
+        assert_eq!(document.metadata.language.as_deref(), Some("en"));
+        assert_eq!(
+            chapters(&document),
+            vec![(Some("Story"), "Story.\n\nFirst line.\n\na, b.")]
+        );
+    }
+}
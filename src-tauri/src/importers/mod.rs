@@ -0,0 +1,315 @@
+pub mod epub;
+pub mod folder;
+pub mod markup;
+pub mod office;
+pub mod package;
+pub mod pdf;
+pub mod script;
+pub mod subtitles;
+pub mod text;
+pub mod xhtml;
+
+use markup::MarkupOptions;
+use pdf::PdfError;
+use script::ScriptConfig;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use text::TextEncoding;
+
+/// File extensions [`import_file`] understands, in lower case.
+pub const SUPPORTED_EXTENSIONS: &[&str] = &["epub", "pdf", "docx", "odt", "md", "markdown", "html", "htm", "txt"];
+
+/// Very common function words used to guess the language of documents whose
+/// metadata does not declare one.
+const STOPWORDS: &[(&str, &[&str])] = &[
+    ("en", &["the", "and", "of", "to", "is", "that", "it", "with", "was", "for", "you", "this"]),
+    ("es", &["el", "la", "de", "que", "y", "los", "las", "en", "por", "una", "con", "para"]),
+    ("fr", &["le", "la", "les", "et", "des", "est", "une", "que", "dans", "pour", "pas", "avec"]),
+    ("de", &["der", "die", "und", "das", "ist", "nicht", "mit", "ein", "den", "zu", "sich", "auch"]),
+    ("it", &["il", "di", "che", "e", "la", "per", "un", "non", "sono", "gli", "del", "della"]),
+    ("pt", &["o", "de", "que", "e", "os", "as", "um", "uma", "não", "para", "com", "do"]),
+];
+const MIN_LANGUAGE_HITS: usize = 5;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Chapter {
+    pub title: Option<String>,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CoverImage {
+    pub media_type: String,
+    /// Base64-encoded image bytes, ready for a `data:` URL.
+    pub data: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentMetadata {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub language: Option<String>,
+    pub cover: Option<CoverImage>,
+}
+
+/// A document split into chapters, as produced by the native importers.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportedDocument {
+    pub metadata: DocumentMetadata,
+    pub chapters: Vec<Chapter>,
+    /// Problems that did not stop the import, e.g. unreadable sections.
+    pub warnings: Vec<String>,
+    /// Encoding a plain-text source was decoded from.
+    pub encoding: Option<TextEncoding>,
+}
+
+impl ImportedDocument {
+    /// Wraps plain text without chapter structure.
+    pub fn from_text(title: Option<String>, text: String) -> Self {
+        Self {
+            metadata: DocumentMetadata {
+                title,
+                ..DocumentMetadata::default()
+            },
+            chapters: vec![Chapter { title: None, text }],
+            ..Self::default()
+        }
+    }
+
+    /// All chapters joined into one text, separated by blank lines.
+    pub fn full_text(&self) -> String {
+        self.chapters
+            .iter()
+            .map(|chapter| chapter.text.as_str())
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
+
+/// Configuration [`import_file`] needs besides the path itself.
+#[derive(Debug, Clone, Default)]
+pub struct ImportSettings {
+    pub markup: MarkupOptions,
+    /// Python used for the pypdf fallback.
+    pub scripts: ScriptConfig,
+    /// Bundled `import_pdf.py` as resolved by the host application, if any.
+    pub pdf_script: Option<PathBuf>,
+    /// Encoding of plain-text files; detected when `None`.
+    pub encoding: Option<TextEncoding>,
+}
+
+/// Imports `path` with the importer for its extension, filling in the title
+/// and language when the file does not declare them.
+pub async fn import_file(path: &Path, settings: &ImportSettings) -> Result<ImportedDocument, String> {
+    let path = path.to_path_buf();
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let file_title = path.file_stem().map(|stem| stem.to_string_lossy().into_owned());
+    let mut document = match extension.as_str() {
+        "epub" => {
+            let path = path.clone();
+            async_std::task::spawn_blocking(move || epub::import_epub(&path))
+                .await
+                .map_err(|err| err.to_string())?
+        }
+        "pdf" => {
+            let native_path = path.clone();
+            match async_std::task::spawn_blocking(move || pdf::import_pdf(&native_path)).await {
+                Ok(document) => document,
+                // pypdf copes with some font encodings the native extractor does not.
+                Err(PdfError::Extract(reason)) => {
+                    log::warn!("native PDF extraction failed ({reason}), falling back to pypdf");
+                    let script = script::resolve_script(settings.pdf_script.clone(), "import_pdf.py")
+                        .map_err(|err| err.to_string())?;
+                    let content = script::run_script(&settings.scripts, &script, &path)
+                        .await
+                        .map_err(|err| err.to_string())?;
+                    let mut document = ImportedDocument::from_text(None, content);
+                    document
+                        .warnings
+                        .push(format!("native PDF extraction failed: {reason}"));
+                    document
+                }
+                Err(err) => return Err(err.to_string()),
+            }
+        }
+        "docx" | "odt" => {
+            let path = path.clone();
+            let is_docx = extension == "docx";
+            async_std::task::spawn_blocking(move || {
+                if is_docx {
+                    office::import_docx(&path)
+                } else {
+                    office::import_odt(&path)
+                }
+            })
+            .await
+            .map_err(|err| err.to_string())?
+        }
+        "md" | "markdown" | "html" | "htm" => {
+            let path = path.clone();
+            let options = settings.markup.clone();
+            let is_markdown = matches!(extension.as_str(), "md" | "markdown");
+            async_std::task::spawn_blocking(move || {
+                if is_markdown {
+                    markup::import_markdown(&path, &options)
+                } else {
+                    markup::import_html(&path, &options)
+                }
+            })
+            .await
+            .map_err(|err| err.to_string())?
+        }
+        "txt" => {
+            let path = path.clone();
+            let encoding = settings.encoding;
+            async_std::task::spawn_blocking(move || text::import_text(&path, encoding))
+                .await
+                .map_err(|err| err.to_string())?
+        }
+        other => {
+            return Err(format!("Unsupported extension: {other}"));
+        }
+    };
+    if document.metadata.title.is_none() {
+        document.metadata.title = file_title;
+    }
+    if document.metadata.language.is_none() {
+        document.metadata.language = detect_language(&document.full_text());
+    }
+    Ok(document)
+}
+
+/// A paragraph-level piece of a structured document, in reading order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Block {
+    /// Heading with its outline level, 1 being the top level.
+    Heading {
+        level: u8,
+        text: String,
+    },
+    Paragraph(String),
+}
+
+/// Splits blocks into chapters at the top-most heading level that occurs.
+///
+/// Lower-level headings stay in the text as their own paragraphs. Headings get
+/// a full stop when they lack closing punctuation so the voice pauses after
+/// them. Text before the first chapter heading becomes an untitled chapter.
+pub fn chapters_from_blocks(blocks: Vec<Block>) -> Vec<Chapter> {
+    let top_level = blocks
+        .iter()
+        .filter_map(|block| match block {
+            Block::Heading { level, .. } => Some(*level),
+            Block::Paragraph(_) => None,
+        })
+        .min();
+    let mut chapters: Vec<Chapter> = Vec::new();
+    let mut paragraphs: Vec<String> = Vec::new();
+    let mut title: Option<String> = None;
+    let mut flush = |title: Option<String>, paragraphs: &mut Vec<String>| {
+        if !paragraphs.is_empty() {
+            chapters.push(Chapter {
+                title,
+                text: paragraphs.join("\n\n"),
+            });
+            paragraphs.clear();
+        }
+    };
+    for block in blocks {
+        match block {
+            Block::Heading { level, text } if Some(level) == top_level => {
+                flush(title.take(), &mut paragraphs);
+                paragraphs.push(spoken_heading(&text));
+                title = Some(text);
+            }
+            Block::Heading { text, .. } => paragraphs.push(spoken_heading(&text)),
+            Block::Paragraph(text) => paragraphs.push(text),
+        }
+    }
+    flush(title, &mut paragraphs);
+    chapters
+}
+
+fn spoken_heading(text: &str) -> String {
+    if text.ends_with(['.', '!', '?', ':', ';', '…']) {
+        text.to_string()
+    } else {
+        format!("{text}.")
+    }
+}
+
+/// Guesses a two-letter language code by counting stopwords in the first few
+/// thousand words. Returns `None` when the text is too short to tell.
+pub fn detect_language(text: &str) -> Option<String> {
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphabetic())
+        .filter(|word| !word.is_empty())
+        .take(5000)
+        .map(str::to_lowercase)
+        .collect();
+    STOPWORDS
+        .iter()
+        .map(|(language, stopwords)| {
+            let stopwords: HashSet<&str> = stopwords.iter().copied().collect();
+            let hits = words.iter().filter(|word| stopwords.contains(word.as_str())).count();
+            (hits, *language)
+        })
+        .filter(|(hits, _)| *hits >= MIN_LANGUAGE_HITS)
+        .max_by_key(|(hits, _)| *hits)
+        .map(|(_, language)| language.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_language_from_stopwords() {
+        let spanish = "En un lugar de la Mancha, de cuyo nombre no quiero acordarme, no ha mucho tiempo que vivía \
+                       un hidalgo de los de lanza en astillero, adarga antigua, rocín flaco y galgo corredor.";
+        let english = "It was the best of times, it was the worst of times, it was the age of wisdom, \
+                       it was the age of foolishness, it was the epoch of belief.";
+        assert_eq!(detect_language(spanish).as_deref(), Some("es"));
+        assert_eq!(detect_language(english).as_deref(), Some("en"));
+        assert_eq!(detect_language("Hola"), None);
+    }
+
+    #[test]
+    fn splits_chapters_at_top_level_headings() {
+        let heading = |level, text: &str| Block::Heading {
+            level,
+            text: text.to_string(),
+        };
+        let paragraph = |text: &str| Block::Paragraph(text.to_string());
+        let chapters = chapters_from_blocks(vec![
+            paragraph("Prefacio."),
+            heading(2, "Uno"),
+            paragraph("A."),
+            heading(3, "Uno bis"),
+            paragraph("B."),
+            heading(2, "Dos"),
+            paragraph("C."),
+        ]);
+        let summary: Vec<_> = chapters
+            .iter()
+            .map(|chapter| (chapter.title.as_deref(), chapter.text.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (None, "Prefacio."),
+                (Some("Uno"), "Uno.\n\nA.\n\nUno bis.\n\nB."),
+                (Some("Dos"), "Dos.\n\nC."),
+            ]
+        );
+    }
+}
@@ -0,0 +1,361 @@
+use super::package::{parse_xml, read_entry, PackageError, XmlChild, XmlNode};
+use super::xhtml::collapse_whitespace;
+use super::{chapters_from_blocks, Block, DocumentMetadata, ImportedDocument};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek};
+use std::path::Path;
+use thiserror::Error;
+use zip::ZipArchive;
+
+/// Outline level given to Word's "Title" style, above `Heading 1`.
+const TITLE_LEVEL: u8 = 0;
+/// DOCX/ODT run elements whose content is never read aloud: comments, tracked
+/// deletions, field instructions, footnotes and embedded drawings.
+const DOCX_SKIPPED: &[&str] = &[
+    "del",
+    "delText",
+    "instrText",
+    "commentReference",
+    "footnoteReference",
+    "endnoteReference",
+    "drawing",
+    "pict",
+    "object",
+];
+const ODT_SKIPPED: &[&str] = &["annotation", "tracked-changes", "note", "sequence-decls", "forms"];
+
+#[derive(Debug, Error)]
+pub enum OfficeError {
+    #[error("failed to open document: {0}")]
+    Open(#[from] std::io::Error),
+    #[error(transparent)]
+    Package(#[from] PackageError),
+    #[error("document contains no text")]
+    NoText,
+}
+
+/// Imports a Word document. Paragraphs styled as headings (or carrying an
+/// outline level) become chapter boundaries; comments and tracked deletions
+/// are skipped while tracked insertions are kept.
+pub fn import_docx(path: &Path) -> Result<ImportedDocument, OfficeError> {
+    read_docx(File::open(path)?)
+}
+
+pub fn read_docx<R: Read + Seek>(reader: R) -> Result<ImportedDocument, OfficeError> {
+    let mut archive = ZipArchive::new(reader).map_err(PackageError::from)?;
+    let document = parse_xml("word/document.xml", &read_entry(&mut archive, "word/document.xml")?)?;
+    let styles = match read_entry(&mut archive, "word/styles.xml") {
+        Ok(content) => Some(parse_xml("word/styles.xml", &content)?),
+        Err(PackageError::MissingEntry(_)) => None,
+        Err(err) => return Err(err.into()),
+    };
+    let heading_levels = styles.as_ref().map(docx_heading_levels).unwrap_or_default();
+
+    let mut blocks = Vec::new();
+    if let Some(body) = document.find(&|node| node.name == "body") {
+        docx_blocks(body, &heading_levels, &mut blocks);
+    }
+    let mut metadata = read_core_properties(&mut archive, "docProps/core.xml")?;
+    if metadata.language.is_none() {
+        metadata.language = styles.as_ref().and_then(|styles| {
+            styles
+                .find(&|node| node.name == "docDefaults")
+                .and_then(|defaults| defaults.find(&|node| node.name == "lang"))
+                .and_then(|lang| lang.attr("val"))
+                .map(str::to_string)
+        });
+    }
+    finish(metadata, blocks)
+}
+
+/// Imports an OpenDocument text file, splitting chapters at `text:h`
+/// headings. Annotations, notes and tracked changes are skipped.
+pub fn import_odt(path: &Path) -> Result<ImportedDocument, OfficeError> {
+    read_odt(File::open(path)?)
+}
+
+pub fn read_odt<R: Read + Seek>(reader: R) -> Result<ImportedDocument, OfficeError> {
+    let mut archive = ZipArchive::new(reader).map_err(PackageError::from)?;
+    let content = parse_xml("content.xml", &read_entry(&mut archive, "content.xml")?)?;
+    let mut blocks = Vec::new();
+    if let Some(text) = content.find(&|node| node.name == "text" && node.find(&|child| child.name == "p").is_some()) {
+        odt_blocks(text, &mut blocks);
+    }
+    let metadata = read_core_properties(&mut archive, "meta.xml")?;
+    finish(metadata, blocks)
+}
+
+/// The document title paragraph only names the document, so it fills in the
+/// metadata instead of becoming a chapter of its own.
+fn finish(mut metadata: DocumentMetadata, mut blocks: Vec<Block>) -> Result<ImportedDocument, OfficeError> {
+    let is_title = |block: &Block| matches!(block, Block::Heading { level, .. } if *level == TITLE_LEVEL);
+    if metadata.title.is_none() {
+        metadata.title = blocks
+            .iter()
+            .find(|block| is_title(block))
+            .and_then(|block| match block {
+                Block::Heading { text, .. } => Some(text.clone()),
+                Block::Paragraph(_) => None,
+            });
+    }
+    blocks.retain(|block| !is_title(block));
+    let chapters = chapters_from_blocks(blocks);
+    if chapters.is_empty() {
+        return Err(OfficeError::NoText);
+    }
+    Ok(ImportedDocument {
+        metadata,
+        chapters,
+        ..ImportedDocument::default()
+    })
+}
+
+/// Reads Dublin Core title, creator and language from `docProps/core.xml`
+/// (DOCX) or `meta.xml` (ODT). Both are optional.
+fn read_core_properties<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    path: &str,
+) -> Result<DocumentMetadata, PackageError> {
+    let root = match read_entry(archive, path) {
+        Ok(content) => parse_xml(path, &content)?,
+        Err(PackageError::MissingEntry(_)) => return Ok(DocumentMetadata::default()),
+        Err(err) => return Err(err),
+    };
+    let text_of = |names: &[&str]| {
+        names.iter().find_map(|name| {
+            root.find(&|node| node.name == *name)
+                .map(|node| collapse_whitespace(&node.text()))
+                .filter(|text| !text.is_empty())
+        })
+    };
+    Ok(DocumentMetadata {
+        title: text_of(&["title"]),
+        author: text_of(&["creator", "initial-creator"]),
+        language: text_of(&["language"]),
+        cover: None,
+    })
+}
+
+/// Maps style ids to outline levels, following `basedOn` so custom styles
+/// derived from a heading style count as headings too.
+fn docx_heading_levels(styles: &XmlNode) -> HashMap<String, u8> {
+    let mut direct: HashMap<String, u8> = HashMap::new();
+    let mut based_on: HashMap<String, String> = HashMap::new();
+    for style in styles.find_all(&|node| node.name == "style" && node.attr("type") == Some("paragraph")) {
+        let Some(id) = style.attr("styleId") else {
+            continue;
+        };
+        let name = style
+            .child("name")
+            .and_then(|name| name.attr("val"))
+            .unwrap_or(id)
+            .to_ascii_lowercase();
+        let outline = style
+            .find(&|node| node.name == "outlineLvl")
+            .and_then(|level| level.attr("val"))
+            .and_then(|level| level.parse::<u8>().ok())
+            .filter(|level| *level < 9)
+            .map(|level| level + 1);
+        let named = if name == "title" {
+            Some(TITLE_LEVEL)
+        } else {
+            name.strip_prefix("heading ")
+                .and_then(|level| level.trim().parse::<u8>().ok())
+        };
+        if let Some(level) = named.or(outline) {
+            direct.insert(id.to_string(), level);
+        }
+        if let Some(parent) = style.child("basedOn").and_then(|parent| parent.attr("val")) {
+            based_on.insert(id.to_string(), parent.to_string());
+        }
+    }
+    let mut levels = direct.clone();
+    for id in based_on.keys() {
+        let mut current = id;
+        for _ in 0..=based_on.len() {
+            if let Some(level) = direct.get(current) {
+                levels.entry(id.clone()).or_insert(*level);
+                break;
+            }
+            match based_on.get(current) {
+                Some(parent) => current = parent,
+                None => break,
+            }
+        }
+    }
+    levels
+}
+
+fn docx_blocks(node: &XmlNode, heading_levels: &HashMap<String, u8>, blocks: &mut Vec<Block>) {
+    for child in node.elements() {
+        match child.name.as_str() {
+            "p" => {
+                let mut text = String::new();
+                docx_text(child, &mut text);
+                let text = collapse_whitespace(&text);
+                if text.is_empty() {
+                    continue;
+                }
+                let properties = child.child("pPr");
+                let level = properties
+                    .and_then(|properties| properties.child("outlineLvl"))
+                    .and_then(|level| level.attr("val"))
+                    .and_then(|level| level.parse::<u8>().ok())
+                    .filter(|level| *level < 9)
+                    .map(|level| level + 1)
+                    .or_else(|| {
+                        properties
+                            .and_then(|properties| properties.child("pStyle"))
+                            .and_then(|style| style.attr("val"))
+                            .and_then(|style| heading_levels.get(style).copied())
+                    });
+                blocks.push(match level {
+                    Some(level) => Block::Heading { level, text },
+                    None => Block::Paragraph(text),
+                });
+            }
+            "sectPr" => {}
+            _ => docx_blocks(child, heading_levels, blocks),
+        }
+    }
+}
+
+fn docx_text(node: &XmlNode, out: &mut String) {
+    for child in &node.children {
+        let XmlChild::Element(element) = child else {
+            continue;
+        };
+        match element.name.as_str() {
+            "t" => out.push_str(&element.text()),
+            "tab" | "br" | "cr" => out.push(' '),
+            "noBreakHyphen" => out.push('-'),
+            "pPr" | "rPr" => {}
+            name if DOCX_SKIPPED.contains(&name) => {}
+            _ => docx_text(element, out),
+        }
+    }
+}
+
+fn odt_blocks(node: &XmlNode, blocks: &mut Vec<Block>) {
+    for child in node.elements() {
+        match child.name.as_str() {
+            "h" | "p" => {
+                let mut text = String::new();
+                odt_text(child, &mut text);
+                let text = collapse_whitespace(&text);
+                if text.is_empty() {
+                    continue;
+                }
+                blocks.push(if child.name == "h" {
+                    let level = child
+                        .attr("outline-level")
+                        .and_then(|level| level.parse::<u8>().ok())
+                        .unwrap_or(1);
+                    Block::Heading { level, text }
+                } else {
+                    Block::Paragraph(text)
+                });
+            }
+            name if ODT_SKIPPED.contains(&name) => {}
+            _ => odt_blocks(child, blocks),
+        }
+    }
+}
+
+fn odt_text(node: &XmlNode, out: &mut String) {
+    for child in &node.children {
+        match child {
+            XmlChild::Text(text) => out.push_str(text),
+            XmlChild::Element(element) => match element.name.as_str() {
+                "s" | "tab" | "line-break" => out.push(' '),
+                name if ODT_SKIPPED.contains(&name) => {}
+                _ => odt_text(element, out),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+    use zip::write::FileOptions;
+
+    fn zip(files: &[(&str, &str)]) -> Cursor<Vec<u8>> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in files {
+            writer.start_file(*name, FileOptions::default()).unwrap();
+            writer.write_all(content.as_bytes()).unwrap();
+        }
+        Cursor::new(writer.finish().unwrap().into_inner())
+    }
+
+    #[test]
+    fn reads_docx_headings_and_skips_revisions() {
+        let document = r#"<w:document xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main"><w:body>
+<w:p><w:pPr><w:pStyle w:val="Ttulo"/></w:pPr><w:r><w:t>Informe</w:t></w:r></w:p>
+<w:p><w:pPr><w:pStyle w:val="Ttulo1"/></w:pPr><w:r><w:t>Introducción</w:t></w:r></w:p>
+<w:p><w:r><w:t xml:space="preserve">Texto </w:t></w:r><w:del><w:r><w:delText>borrado </w:delText></w:r></w:del><w:ins><w:r><w:t>nuevo</w:t></w:r></w:ins><w:r><w:commentReference w:id="0"/></w:r><w:r><w:t>.</w:t></w:r></w:p>
+<w:p><w:pPr><w:pStyle w:val="MiCapitulo"/></w:pPr><w:r><w:t>Resultados</w:t></w:r></w:p>
+<w:tbl><w:tr><w:tc><w:p><w:r><w:t>Celda</w:t></w:r></w:p></w:tc></w:tr></w:tbl>
+<w:sectPr/></w:body></w:document>"#;
+        let styles = r#"<w:styles xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main">
+<w:docDefaults><w:rPrDefault><w:rPr><w:lang w:val="es-ES"/></w:rPr></w:rPrDefault></w:docDefaults>
+<w:style w:type="paragraph" w:styleId="Ttulo"><w:name w:val="Title"/></w:style>
+<w:style w:type="paragraph" w:styleId="Ttulo1"><w:name w:val="heading 1"/><w:pPr><w:outlineLvl w:val="0"/></w:pPr></w:style>
+<w:style w:type="paragraph" w:styleId="MiCapitulo"><w:name w:val="Mi capítulo"/><w:basedOn w:val="Ttulo1"/></w:style>
+</w:styles>"#;
+        let core = r#"<cp:coreProperties xmlns:cp="http://schemas.openxmlformats.org/package/2006/metadata/core-properties" xmlns:dc="http://purl.org/dc/elements/1.1/"><dc:creator>Luis</dc:creator></cp:coreProperties>"#;
+        let imported = read_docx(zip(&[
+            ("word/document.xml", document),
+            ("word/styles.xml", styles),
+            ("docProps/core.xml", core),
+        ]))
+        .unwrap();
+        assert_eq!(imported.metadata.title.as_deref(), Some("Informe"));
+        assert_eq!(imported.metadata.author.as_deref(), Some("Luis"));
+        assert_eq!(imported.metadata.language.as_deref(), Some("es-ES"));
+        let chapters: Vec<_> = imported
+            .chapters
+            .iter()
+            .map(|chapter| (chapter.title.as_deref(), chapter.text.as_str()))
+            .collect();
+        assert_eq!(
+            chapters,
+            vec![
+                (Some("Introducción"), "Introducción.\n\nTexto nuevo."),
+                (Some("Resultados"), "Resultados.\n\nCelda"),
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_odt_chapters() {
+        let content = r#"<office:document-content xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0" xmlns:text="urn:oasis:names:tc:opendocument:xmlns:text:1.0"><office:body><office:text>
+<text:tracked-changes><text:changed-region><text:deletion><text:p>Eliminado</text:p></text:deletion></text:changed-region></text:tracked-changes>
+<text:h text:outline-level="1">Uno</text:h>
+<text:p>Primer<text:s/>párrafo<office:annotation><text:p>Comentario</text:p></office:annotation>.</text:p>
+<text:list><text:list-item><text:p>Punto</text:p></text:list-item></text:list>
+<text:h text:outline-level="2">Detalle</text:h>
+<text:h text:outline-level="1">Dos</text:h>
+<text:p>Fin<text:note><text:note-body><text:p>Nota</text:p></text:note-body></text:note>.</text:p>
+</office:text></office:body></office:document-content>"#;
+        let meta = r#"<office:document-meta xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0" xmlns:dc="http://purl.org/dc/elements/1.1/"><office:meta><dc:title>Notas</dc:title><dc:language>es</dc:language></office:meta></office:document-meta>"#;
+        let imported = read_odt(zip(&[("content.xml", content), ("meta.xml", meta)])).unwrap();
+        assert_eq!(imported.metadata.title.as_deref(), Some("Notas"));
+        assert_eq!(imported.metadata.language.as_deref(), Some("es"));
+        let chapters: Vec<_> = imported
+            .chapters
+            .iter()
+            .map(|chapter| (chapter.title.as_deref(), chapter.text.as_str()))
+            .collect();
+        assert_eq!(
+            chapters,
+            vec![
+                (Some("Uno"), "Uno.\n\nPrimer párrafo.\n\nPunto\n\nDetalle."),
+                (Some("Dos"), "Dos.\n\nFin."),
+            ]
+        );
+    }
+}
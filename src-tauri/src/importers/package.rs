@@ -0,0 +1,168 @@
+use super::xhtml::resolve_html_entity;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::io::{Read, Seek};
+use thiserror::Error;
+use zip::ZipArchive;
+
+/// Errors reading the zipped XML packages used by EPUB, DOCX and ODT.
+#[derive(Debug, Error)]
+pub enum PackageError {
+    #[error("failed to read archive entry: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("not a valid ZIP archive: {0}")]
+    Zip(#[from] zip::result::ZipError),
+    #[error("document is missing '{0}'")]
+    MissingEntry(String),
+    #[error("malformed XML in '{path}': {message}")]
+    Xml { path: String, message: String },
+}
+
+pub fn read_entry<R: Read + Seek>(archive: &mut ZipArchive<R>, path: &str) -> Result<String, PackageError> {
+    let mut file = match archive.by_name(path) {
+        Ok(file) => file,
+        Err(zip::result::ZipError::FileNotFound) => return Err(PackageError::MissingEntry(path.to_string())),
+        Err(err) => return Err(err.into()),
+    };
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Minimal element tree for the small XML documents inside office and EPUB
+/// packages. Names are stored without namespace prefix.
+#[derive(Debug, Default)]
+pub struct XmlNode {
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<XmlChild>,
+}
+
+#[derive(Debug)]
+pub enum XmlChild {
+    Element(XmlNode),
+    Text(String),
+}
+
+impl XmlNode {
+    pub fn attr(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn elements(&self) -> impl Iterator<Item = &XmlNode> {
+        self.children.iter().filter_map(|child| match child {
+            XmlChild::Element(node) => Some(node),
+            XmlChild::Text(_) => None,
+        })
+    }
+
+    pub fn child(&self, name: &str) -> Option<&XmlNode> {
+        self.elements().find(|node| node.name == name)
+    }
+
+    pub fn find(&self, predicate: &dyn Fn(&XmlNode) -> bool) -> Option<&XmlNode> {
+        self.elements().find_map(|node| {
+            if predicate(node) {
+                Some(node)
+            } else {
+                node.find(predicate)
+            }
+        })
+    }
+
+    /// All matching descendants in document order.
+    pub fn find_all(&self, predicate: &dyn Fn(&XmlNode) -> bool) -> Vec<&XmlNode> {
+        let mut found = Vec::new();
+        for node in self.elements() {
+            if predicate(node) {
+                found.push(node);
+            }
+            found.extend(node.find_all(predicate));
+        }
+        found
+    }
+
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        for child in &self.children {
+            match child {
+                XmlChild::Text(value) => text.push_str(value),
+                XmlChild::Element(node) => text.push_str(&node.text()),
+            }
+        }
+        text
+    }
+}
+
+pub fn parse_xml(path: &str, content: &str) -> Result<XmlNode, PackageError> {
+    let xml_error = |message: String| PackageError::Xml {
+        path: path.to_string(),
+        message,
+    };
+    let mut reader = Reader::from_str(content);
+    reader.check_end_names(false);
+    let mut stack = vec![XmlNode::default()];
+    loop {
+        match reader.read_event().map_err(|err| xml_error(err.to_string()))? {
+            Event::Start(start) => stack.push(element(&start)),
+            Event::Empty(start) => {
+                let node = element(&start);
+                push_child(&mut stack, XmlChild::Element(node));
+            }
+            Event::End(_) if stack.len() > 1 => {
+                let node = stack.pop().expect("stack has an open element");
+                push_child(&mut stack, XmlChild::Element(node));
+            }
+            Event::Text(text) => {
+                let value = text
+                    .unescape_with(resolve_html_entity)
+                    .map(|cow| cow.into_owned())
+                    .unwrap_or_else(|_| String::from_utf8_lossy(&text).into_owned());
+                push_child(&mut stack, XmlChild::Text(value));
+            }
+            Event::CData(data) => {
+                push_child(&mut stack, XmlChild::Text(String::from_utf8_lossy(&data).into_owned()));
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    while stack.len() > 1 {
+        let node = stack.pop().expect("stack has an open element");
+        push_child(&mut stack, XmlChild::Element(node));
+    }
+    let document = stack.pop().expect("document node");
+    if document.elements().next().is_none() {
+        return Err(xml_error("no root element".to_string()));
+    }
+    Ok(document)
+}
+
+fn element(start: &BytesStart<'_>) -> XmlNode {
+    let attributes = start
+        .attributes()
+        .flatten()
+        .map(|attr| {
+            let key = String::from_utf8_lossy(attr.key.local_name().as_ref()).into_owned();
+            let value = attr
+                .unescape_value()
+                .map(|cow| cow.into_owned())
+                .unwrap_or_else(|_| String::from_utf8_lossy(&attr.value).into_owned());
+            (key, value)
+        })
+        .collect();
+    XmlNode {
+        name: String::from_utf8_lossy(start.local_name().as_ref()).into_owned(),
+        attributes,
+        children: Vec::new(),
+    }
+}
+
+fn push_child(stack: &mut [XmlNode], child: XmlChild) {
+    if let Some(parent) = stack.last_mut() {
+        parent.children.push(child);
+    }
+}
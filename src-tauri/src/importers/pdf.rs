@@ -0,0 +1,260 @@
+use super::{Chapter, ImportedDocument};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use thiserror::Error;
+
+/// Lines at the top and bottom of each page considered for header/footer removal.
+const EDGE_LINES: usize = 2;
+/// A normalized edge line must repeat on at least this many pages...
+const MIN_REPEATS: usize = 3;
+/// ...and on at least this share of pages (running heads often alternate
+/// between even and odd pages, so half the pages is typical).
+const MIN_REPEAT_RATIO: f64 = 0.4;
+/// A line shorter than this share of the page's longest line that ends a
+/// sentence closes its paragraph.
+const SHORT_LINE_RATIO: f64 = 0.75;
+
+#[derive(Debug, Error)]
+pub enum PdfError {
+    #[error("failed to read PDF: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("failed to extract PDF text: {0}")]
+    Extract(String),
+    #[error("PDF contains no extractable text; it may be a scanned document")]
+    NoText,
+}
+
+/// Imports the text layer of a PDF as one chapter of paragraphs.
+///
+/// Running headers and footers, page numbers and end-of-line hyphenation are
+/// removed, and lines are merged back into paragraphs. Pages without any text
+/// are reported as warnings since they are usually scanned images.
+pub fn import_pdf(path: &Path) -> Result<ImportedDocument, PdfError> {
+    let bytes = std::fs::read(path)?;
+    // pdf-extract panics on some malformed fonts instead of returning an error.
+    let pages = std::panic::catch_unwind(|| pdf_extract::extract_text_from_mem_by_pages(&bytes))
+        .map_err(|_| PdfError::Extract("unsupported PDF structure".to_string()))?
+        .map_err(|err| PdfError::Extract(err.to_string()))?;
+    document_from_pages(&pages)
+}
+
+fn document_from_pages(pages: &[String]) -> Result<ImportedDocument, PdfError> {
+    let mut pages: Vec<Vec<String>> = pages.iter().map(|page| page_lines(page)).collect();
+    let mut warnings = Vec::new();
+    for (number, page) in pages.iter().enumerate() {
+        if page.iter().all(|line| line.is_empty()) {
+            warnings.push(format!("page {} has no text layer (scanned image?)", number + 1));
+        }
+    }
+    strip_page_furniture(&mut pages);
+
+    let text = merge_paragraphs(&pages).join("\n\n");
+    if text.is_empty() {
+        return Err(PdfError::NoText);
+    }
+    Ok(ImportedDocument {
+        chapters: vec![Chapter { title: None, text }],
+        warnings,
+        ..ImportedDocument::default()
+    })
+}
+
+/// Trimmed lines with runs of blank lines collapsed to one empty string.
+fn page_lines(page: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in page.lines() {
+        let line = line.split_whitespace().collect::<Vec<_>>().join(" ");
+        if line.is_empty() && lines.last().is_none_or(String::is_empty) {
+            continue;
+        }
+        lines.push(line);
+    }
+    while lines.last().is_some_and(String::is_empty) {
+        lines.pop();
+    }
+    lines
+}
+
+/// Indices of the first and last non-empty lines of a page.
+fn edge_indices(page: &[String]) -> Vec<usize> {
+    let filled: Vec<usize> = (0..page.len()).filter(|&idx| !page[idx].is_empty()).collect();
+    let mut indices: Vec<usize> = filled.iter().take(EDGE_LINES).copied().collect();
+    indices.extend(filled.iter().rev().take(EDGE_LINES).copied());
+    indices.sort_unstable();
+    indices.dedup();
+    indices
+}
+
+/// Removes page numbers and lines repeated at the edges of many pages.
+fn strip_page_furniture(pages: &mut [Vec<String>]) {
+    let text_pages = pages.iter().filter(|page| !page.is_empty()).count();
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for page in pages.iter() {
+        let keys: HashSet<String> = edge_indices(page)
+            .into_iter()
+            .map(|idx| furniture_key(&page[idx]))
+            .collect();
+        for key in keys {
+            *counts.entry(key).or_default() += 1;
+        }
+    }
+    let repeated: HashSet<String> = counts
+        .into_iter()
+        .filter(|(_, count)| *count >= MIN_REPEATS && *count as f64 >= text_pages as f64 * MIN_REPEAT_RATIO)
+        .map(|(key, _)| key)
+        .collect();
+
+    for page in pages.iter_mut() {
+        let remove: HashSet<usize> = edge_indices(page)
+            .into_iter()
+            .filter(|&idx| is_page_number(&page[idx]) || repeated.contains(&furniture_key(&page[idx])))
+            .collect();
+        let mut idx = 0;
+        page.retain(|_| {
+            idx += 1;
+            !remove.contains(&(idx - 1))
+        });
+        while page.first().is_some_and(String::is_empty) {
+            page.remove(0);
+        }
+        while page.last().is_some_and(String::is_empty) {
+            page.pop();
+        }
+    }
+}
+
+/// Running heads usually differ only in the page number they carry.
+fn furniture_key(line: &str) -> String {
+    line.chars()
+        .map(|c| if c.is_ascii_digit() { '#' } else { c })
+        .collect::<String>()
+        .to_lowercase()
+}
+
+fn is_page_number(line: &str) -> bool {
+    let trimmed = line.trim_matches(|c: char| c == '-' || c == '–' || c == '—' || c == '|' || c.is_whitespace());
+    let lower = trimmed.to_lowercase();
+    let rest = ["page", "página", "pagina", "pág.", "p."]
+        .iter()
+        .find_map(|prefix| lower.strip_prefix(prefix))
+        .unwrap_or(&lower)
+        .trim();
+    let mut parts = rest
+        .split(|c: char| c == '/' || c.is_whitespace())
+        .filter(|part| !part.is_empty() && *part != "of" && *part != "de");
+    let Some(first) = parts.next() else {
+        return false;
+    };
+    let is_number = |part: &str| {
+        part.chars().all(|c| c.is_ascii_digit()) && part.len() <= 4
+            || part.len() <= 6 && part.chars().all(|c| "ivxlc".contains(c))
+    };
+    is_number(first) && parts.all(|part| part.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Joins page lines into paragraphs, undoing hyphenation at line ends.
+/// Paragraphs may continue across page breaks.
+fn merge_paragraphs(pages: &[Vec<String>]) -> Vec<String> {
+    let mut paragraphs: Vec<String> = Vec::new();
+    let mut current = String::new();
+    for page in pages {
+        let longest = page.iter().map(|line| line.chars().count()).max().unwrap_or(0);
+        for line in page {
+            if line.is_empty() {
+                flush(&mut paragraphs, &mut current);
+                continue;
+            }
+            append_line(&mut current, line);
+            let short = (line.chars().count() as f64) < longest as f64 * SHORT_LINE_RATIO;
+            if short && ends_sentence(line) {
+                flush(&mut paragraphs, &mut current);
+            }
+        }
+        if ends_sentence(&current) {
+            flush(&mut paragraphs, &mut current);
+        }
+    }
+    flush(&mut paragraphs, &mut current);
+    paragraphs
+}
+
+fn append_line(current: &mut String, line: &str) {
+    if current.is_empty() {
+        current.push_str(line);
+        return;
+    }
+    let next_starts_lower = line.chars().next().is_some_and(char::is_lowercase);
+    if let Some(stem) = current.strip_suffix('\u{ad}') {
+        let len = stem.len();
+        current.truncate(len);
+    } else if current.ends_with('-') && next_starts_lower && letter_before_hyphen(current) {
+        current.pop();
+    } else {
+        current.push(' ');
+    }
+    current.push_str(line);
+}
+
+fn letter_before_hyphen(text: &str) -> bool {
+    text.chars().rev().nth(1).is_some_and(char::is_alphabetic)
+}
+
+fn ends_sentence(text: &str) -> bool {
+    let trimmed = text.trim_end_matches(['"', '\'', ')', '»', '”', '’']);
+    trimmed.ends_with(['.', '!', '?', ':', '…'])
+}
+
+fn flush(paragraphs: &mut Vec<String>, current: &mut String) {
+    if !current.is_empty() {
+        paragraphs.push(std::mem::take(current));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(number: usize, body: &str) -> String {
+        let header = if number.is_multiple_of(2) {
+            "Manual de usuario"
+        } else {
+            "Capítulo 2 · Instalación"
+        };
+        format!("{header}\n\n{body}\n\n{number}\n")
+    }
+
+    #[test]
+    fn removes_furniture_and_rebuilds_paragraphs() {
+        let pages = vec![
+            page(1, "La instalación requiere un equipo con\nsuficiente memoria y un sistema operativo com-\npatible con la aplicación."),
+            page(2, "Descargue el paquete desde la página del\nproyecto y ejecute el instalador. Siga los"),
+            page(3, "pasos del asistente y reinicie el equipo\ncuando termine."),
+            page(4, "Abra la aplicación desde el menú de inicio\ny seleccione un documento."),
+            page(5, "Cierre la aplicación al terminar."),
+            page(6, "Fin del capítulo."),
+            String::new(),
+        ];
+        let document = document_from_pages(&pages).unwrap();
+        assert_eq!(
+            document.chapters[0].text,
+            "La instalación requiere un equipo con suficiente memoria y un sistema operativo compatible con la \
+             aplicación.\n\nDescargue el paquete desde la página del proyecto y ejecute el instalador. Siga los \
+             pasos del asistente y reinicie el equipo cuando termine.\n\nAbra la aplicación desde el menú de \
+             inicio y seleccione un documento.\n\nCierre la aplicación al terminar.\n\nFin del capítulo."
+        );
+        assert_eq!(
+            document.warnings,
+            vec!["page 7 has no text layer (scanned image?)".to_string()]
+        );
+    }
+
+    #[test]
+    fn recognises_page_numbers() {
+        for line in ["12", "- 12 -", "Page 3 of 10", "Página 4", "xiv", "3 / 10"] {
+            assert!(is_page_number(line), "{line}");
+        }
+        for line in ["2024 was a good year", "Capítulo 1", "I think so"] {
+            assert!(!is_page_number(line), "{line}");
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use async_process::{Command, Stdio};
+use async_std::io::ReadExt;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use thiserror::Error;
+
+/// Exit code the importer scripts use when a Python package is missing.
+const EXIT_MISSING_DEPENDENCY: i32 = 2;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ScriptConfig {
+    /// Python interpreter; when unset `python3`, then `python`, is looked up
+    /// on `PATH`.
+    pub python: Option<PathBuf>,
+    pub timeout_secs: u64,
+}
+
+impl Default for ScriptConfig {
+    fn default() -> Self {
+        Self {
+            python: std::env::var_os("PIPERDESK_PYTHON").map(PathBuf::from),
+            timeout_secs: 120,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ScriptError {
+    #[error("importer script '{0}' is not bundled with this build")]
+    ScriptNotFound(String),
+    #[error("Python interpreter not found ({0}); install Python 3 or set its path in the settings")]
+    PythonNotFound(String),
+    #[error("failed to run '{path}': {source}")]
+    Spawn {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("missing Python dependency: {0}")]
+    MissingDependency(String),
+    #[error("importer exited with code {code:?}: {stderr}")]
+    Failed { code: Option<i32>, stderr: String },
+    #[error("importer did not finish within {0} seconds")]
+    TimedOut(u64),
+    #[error("importer produced invalid UTF-8 output")]
+    InvalidOutput,
+}
+
+/// Finds a bundled importer script. `resource` is the location Tauri resolved
+/// for the bundled copy; next to the executable and the source checkout are
+/// tried after it so development builds work from any working directory.
+pub fn resolve_script(resource: Option<PathBuf>, name: &str) -> Result<PathBuf, ScriptError> {
+    let beside_exe = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.join("scripts").join(name)));
+    let checkout = Path::new(env!("CARGO_MANIFEST_DIR")).join("..").join("scripts").join(name);
+    resource
+        .into_iter()
+        .chain(beside_exe)
+        .chain(Some(checkout))
+        .find(|path| path.is_file())
+        .ok_or_else(|| ScriptError::ScriptNotFound(name.to_string()))
+}
+
+pub fn locate_python(config: &ScriptConfig) -> Result<PathBuf, ScriptError> {
+    if let Some(path) = &config.python {
+        if path.is_absolute() {
+            return if path.is_file() {
+                Ok(path.clone())
+            } else {
+                Err(ScriptError::PythonNotFound(format!("'{}' does not exist", path.display())))
+            };
+        }
+        return which::which(path).map_err(|err| ScriptError::PythonNotFound(format!("'{}': {err}", path.display())));
+    }
+    which::which("python3")
+        .or_else(|_| which::which("python"))
+        .map_err(|_| ScriptError::PythonNotFound("not on PATH".to_string()))
+}
+
+/// Runs `script --file <input>` and returns its trimmed stdout, killing the
+/// interpreter after the configured timeout.
+pub async fn run_script(config: &ScriptConfig, script: &Path, input: &Path) -> Result<String, ScriptError> {
+    let python = locate_python(config)?;
+    let spawn_error = |source| ScriptError::Spawn {
+        path: python.clone(),
+        source,
+    };
+    let mut child = Command::new(&python)
+        .arg(script)
+        .arg("--file")
+        .arg(input)
+        .env("PYTHONIOENCODING", "utf-8")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(spawn_error)?;
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let mut stderr = child.stderr.take().expect("stderr is piped");
+    let stdout_task = async_std::task::spawn(async move {
+        let mut buf = Vec::new();
+        let _ = stdout.read_to_end(&mut buf).await;
+        buf
+    });
+    let stderr_task = async_std::task::spawn(async move {
+        let mut buf = Vec::new();
+        let _ = stderr.read_to_end(&mut buf).await;
+        buf
+    });
+
+    let timeout = Duration::from_secs(config.timeout_secs.max(1));
+    let status = match async_std::future::timeout(timeout, child.status()).await {
+        Ok(status) => status.map_err(spawn_error)?,
+        Err(_) => {
+            let _ = child.kill();
+            let _ = child.status().await;
+            return Err(ScriptError::TimedOut(config.timeout_secs));
+        }
+    };
+    let stdout = stdout_task.await;
+    let stderr = String::from_utf8_lossy(&stderr_task.await).trim().to_string();
+    match status.code() {
+        Some(0) => String::from_utf8(stdout)
+            .map(|content| content.trim().to_string())
+            .map_err(|_| ScriptError::InvalidOutput),
+        Some(EXIT_MISSING_DEPENDENCY) => Err(ScriptError::MissingDependency(stderr)),
+        code => Err(ScriptError::Failed { code, stderr }),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn run(body: &str, timeout_secs: u64) -> Result<String, ScriptError> {
+        let script = std::env::temp_dir().join(format!("piperdesk-script-{}.sh", Uuid::new_v4()));
+        std::fs::write(&script, body).unwrap();
+        let config = ScriptConfig {
+            python: Some(PathBuf::from("/bin/sh")),
+            timeout_secs,
+        };
+        let result = async_std::task::block_on(run_script(&config, &script, Path::new("doc.pdf")));
+        std::fs::remove_file(&script).unwrap();
+        result
+    }
+
+    #[test]
+    fn maps_exit_codes_to_errors() {
+        assert_eq!(run("echo \"$2\"", 5).unwrap(), "doc.pdf");
+        assert!(matches!(
+            run("echo 'install pypdf' >&2; exit 2", 5),
+            Err(ScriptError::MissingDependency(message)) if message == "install pypdf"
+        ));
+        assert!(matches!(run("exit 1", 5), Err(ScriptError::Failed { code: Some(1), .. })));
+        assert!(matches!(run("sleep 5", 1), Err(ScriptError::TimedOut(1))));
+    }
+}
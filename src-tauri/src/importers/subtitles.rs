@@ -0,0 +1,177 @@
+use super::text::decode_text;
+use super::xhtml::{collapse_whitespace, resolve_html_entity};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use thiserror::Error;
+
+/// One subtitle cue with its position on the video timeline.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubtitleCue {
+    /// 1-based position in playback order.
+    pub index: usize,
+    pub start_ms: u64,
+    pub end_ms: u64,
+    pub text: String,
+}
+
+#[derive(Debug, Error)]
+pub enum SubtitleError {
+    #[error("failed to read subtitles: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid timestamp '{value}' on line {line}")]
+    InvalidTimestamp { line: usize, value: String },
+    #[error("subtitle file contains no cues")]
+    NoCues,
+}
+
+/// Reads an SRT or WebVTT file, detecting its text encoding.
+pub fn import_subtitles(path: &Path) -> Result<Vec<SubtitleCue>, SubtitleError> {
+    let bytes = std::fs::read(path)?;
+    let (content, _) = decode_text(&bytes, None);
+    parse_subtitles(&content)
+}
+
+/// Parses SRT or WebVTT cues, sorted by start time.
+///
+/// Cue identifiers, WebVTT cue settings and `NOTE`/`STYLE`/`REGION` blocks are
+/// ignored. Markup such as `<i>`, `<v Speaker>` or `{\an8}` is removed and the
+/// cue lines are joined into one sentence for the voice.
+pub fn parse_subtitles(content: &str) -> Result<Vec<SubtitleCue>, SubtitleError> {
+    let mut cues = Vec::new();
+    let mut lines = content.lines().enumerate().peekable();
+    while let Some((number, line)) = lines.next() {
+        let Some((start, rest)) = line.split_once("-->") else {
+            continue;
+        };
+        let timestamp = |value: &str| {
+            parse_timestamp(value).ok_or_else(|| SubtitleError::InvalidTimestamp {
+                line: number + 1,
+                value: value.to_string(),
+            })
+        };
+        let start_ms = timestamp(start.trim())?;
+        let end_ms = timestamp(rest.split_whitespace().next().unwrap_or_default())?;
+        let mut text = String::new();
+        while let Some((_, line)) = lines.next_if(|(_, line)| !line.trim().is_empty()) {
+            text.push_str(line);
+            text.push(' ');
+        }
+        let text = collapse_whitespace(&strip_markup(&text));
+        if !text.is_empty() {
+            cues.push(SubtitleCue {
+                index: 0,
+                start_ms,
+                end_ms: end_ms.max(start_ms),
+                text,
+            });
+        }
+    }
+    if cues.is_empty() {
+        return Err(SubtitleError::NoCues);
+    }
+    cues.sort_by_key(|cue| cue.start_ms);
+    for (position, cue) in cues.iter_mut().enumerate() {
+        cue.index = position + 1;
+    }
+    Ok(cues)
+}
+
+/// Accepts `hh:mm:ss,mmm`, `hh:mm:ss.mmm` and WebVTT's short `mm:ss.mmm`.
+fn parse_timestamp(value: &str) -> Option<u64> {
+    let (clock, millis) = value.split_once([',', '.']).unwrap_or((value, "0"));
+    let mut parts = clock.rsplit(':').map(|part| part.parse::<u64>().ok());
+    let seconds = parts.next()??;
+    let minutes = parts.next()??;
+    let hours = parts.next().unwrap_or(Some(0))?;
+    if parts.next().is_some() || millis.is_empty() || millis.len() > 3 {
+        return None;
+    }
+    let millis: u64 = format!("{millis:0<3}").parse().ok()?;
+    Some(((hours * 60 + minutes) * 60 + seconds) * 1000 + millis)
+}
+
+/// Removes HTML-like tags and SSA override blocks and decodes entities.
+fn strip_markup(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '<' => {
+                chars.by_ref().find(|&c| c == '>');
+            }
+            '{' if chars.as_str().starts_with('\\') => {
+                chars.by_ref().find(|&c| c == '}');
+            }
+            '&' => {
+                let rest = chars.as_str();
+                let entity = rest.split_once(';').map(|(entity, _)| entity).filter(|entity| {
+                    entity.len() <= 8 && entity.chars().all(|c| c.is_ascii_alphanumeric() || c == '#')
+                });
+                let decoded = entity.and_then(|entity| match entity {
+                    "amp" => Some("&"),
+                    "lt" => Some("<"),
+                    "gt" => Some(">"),
+                    "quot" => Some("\""),
+                    "apos" => Some("'"),
+                    other => resolve_html_entity(other),
+                });
+                match (entity, decoded) {
+                    (Some(entity), Some(decoded)) => {
+                        out.push_str(decoded);
+                        chars = rest[entity.len() + 1..].chars();
+                    }
+                    _ => out.push('&'),
+                }
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_srt() {
+        let srt = "1\r\n00:00:01,000 --> 00:00:03,500\r\n<i>Hola,</i>\r\nmundo.\r\n\r\n\
+                   2\r\n00:00:05,250 --> 00:00:06,000\r\n{\\an8}Adiós &amp; gracias.\r\n";
+        let (content, _) = decode_text(srt.as_bytes(), None);
+        let cues = parse_subtitles(&content).unwrap();
+        assert_eq!(
+            cues,
+            vec![
+                SubtitleCue {
+                    index: 1,
+                    start_ms: 1000,
+                    end_ms: 3500,
+                    text: "Hola, mundo.".into(),
+                },
+                SubtitleCue {
+                    index: 2,
+                    start_ms: 5250,
+                    end_ms: 6000,
+                    text: "Adiós & gracias.".into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_webvtt() {
+        let vtt = "WEBVTT\n\nNOTE exported by hand\n\nSTYLE\n::cue { color: yellow }\n\n\
+                   intro\n00:02.500 --> 00:04.000 align:start\n<v Ana>Buenos días.</v>\n\n\
+                   01:00:00.000 --> 01:00:01.000\nFin.\n";
+        let cues = parse_subtitles(vtt).unwrap();
+        assert_eq!(cues.len(), 2);
+        assert_eq!((cues[0].start_ms, cues[0].end_ms), (2500, 4000));
+        assert_eq!(cues[0].text, "Buenos días.");
+        assert_eq!(cues[1].start_ms, 3_600_000);
+        assert!(matches!(
+            parse_subtitles("1\n00:00:xx,000 --> 00:00:01,000\nA\n"),
+            Err(SubtitleError::InvalidTimestamp { line: 2, .. })
+        ));
+    }
+}
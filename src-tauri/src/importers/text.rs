@@ -0,0 +1,165 @@
+use super::ImportedDocument;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use unicode_normalization::UnicodeNormalization;
+
+/// Bytes inspected when guessing whether BOM-less text is UTF-16.
+const UTF16_SNIFF_BYTES: usize = 4096;
+/// Share of NUL bytes in one byte position that marks ASCII-heavy UTF-16.
+const UTF16_NUL_RATIO: f64 = 0.3;
+
+/// Encodings plain-text files are read with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TextEncoding {
+    #[serde(rename = "utf-8")]
+    Utf8,
+    #[serde(rename = "utf-16le")]
+    Utf16Le,
+    #[serde(rename = "utf-16be")]
+    Utf16Be,
+    #[serde(rename = "windows-1252")]
+    Windows1252,
+    #[serde(rename = "iso-8859-1")]
+    Iso8859_1,
+    #[serde(rename = "iso-8859-15")]
+    Iso8859_15,
+}
+
+impl TextEncoding {
+    fn decode(self, bytes: &[u8]) -> String {
+        let encoding = match self {
+            // encoding_rs follows WHATWG and decodes ISO-8859-1 as
+            // Windows-1252, which differs in 0x80..=0x9F.
+            TextEncoding::Iso8859_1 => return bytes.iter().map(|&byte| byte as char).collect(),
+            TextEncoding::Utf8 => encoding_rs::UTF_8,
+            TextEncoding::Utf16Le => encoding_rs::UTF_16LE,
+            TextEncoding::Utf16Be => encoding_rs::UTF_16BE,
+            TextEncoding::Windows1252 => encoding_rs::WINDOWS_1252,
+            TextEncoding::Iso8859_15 => encoding_rs::ISO_8859_15,
+        };
+        encoding.decode_without_bom_handling(bytes).0.into_owned()
+    }
+}
+
+/// Reads a plain-text file, detecting its encoding unless `encoding` is given.
+/// The detected encoding is reported so the user can override a wrong guess.
+pub fn import_text(path: &Path, encoding: Option<TextEncoding>) -> std::io::Result<ImportedDocument> {
+    let bytes = std::fs::read(path)?;
+    let (text, encoding) = decode_text(&bytes, encoding);
+    let mut document = ImportedDocument::from_text(None, text);
+    document.encoding = Some(encoding);
+    Ok(document)
+}
+
+/// Decodes `bytes` to NFC-normalized text with `\n` line endings. A byte
+/// order mark wins over detection but not over an explicit `encoding`.
+pub fn decode_text(bytes: &[u8], encoding: Option<TextEncoding>) -> (String, TextEncoding) {
+    let (bom, body) = strip_bom(bytes);
+    let encoding = encoding.or(bom).unwrap_or_else(|| detect_encoding(body));
+    let body = if bom.is_some_and(|bom| bom != encoding) {
+        bytes
+    } else {
+        body
+    };
+    let text = encoding.decode(body);
+    let text = text.replace("\r\n", "\n").replace('\r', "\n");
+    (text.nfc().collect(), encoding)
+}
+
+fn strip_bom(bytes: &[u8]) -> (Option<TextEncoding>, &[u8]) {
+    if let Some(rest) = bytes.strip_prefix(b"\xEF\xBB\xBF") {
+        (Some(TextEncoding::Utf8), rest)
+    } else if let Some(rest) = bytes.strip_prefix(b"\xFF\xFE") {
+        (Some(TextEncoding::Utf16Le), rest)
+    } else if let Some(rest) = bytes.strip_prefix(b"\xFE\xFF") {
+        (Some(TextEncoding::Utf16Be), rest)
+    } else {
+        (None, bytes)
+    }
+}
+
+/// Guesses the encoding of text without a byte order mark.
+///
+/// Valid UTF-8 is taken as such. Otherwise the file is assumed to be one of
+/// the Western single-byte encodings: bytes in 0x80..=0x9F are control codes
+/// in ISO-8859 but punctuation (curly quotes, dashes, €) in Windows-1252, and
+/// a 0xA4 next to a digit is far more likely a euro sign than `¤`.
+fn detect_encoding(bytes: &[u8]) -> TextEncoding {
+    if let Some(encoding) = detect_utf16(bytes) {
+        return encoding;
+    }
+    if std::str::from_utf8(bytes).is_ok() {
+        return TextEncoding::Utf8;
+    }
+    if bytes.iter().any(|byte| (0x80..=0x9F).contains(byte)) {
+        return TextEncoding::Windows1252;
+    }
+    let euro = bytes.windows(2).any(|pair| {
+        pair[0] == 0xA4 && (pair[1].is_ascii_digit() || pair[1] == b' ')
+            || pair[1] == 0xA4 && (pair[0].is_ascii_digit() || pair[0] == b' ')
+    });
+    if euro {
+        TextEncoding::Iso8859_15
+    } else {
+        TextEncoding::Iso8859_1
+    }
+}
+
+/// Mostly-ASCII UTF-16 has a NUL in every other byte.
+fn detect_utf16(bytes: &[u8]) -> Option<TextEncoding> {
+    let sample = &bytes[..bytes.len().min(UTF16_SNIFF_BYTES) & !1];
+    if sample.is_empty() {
+        return None;
+    }
+    let pairs = sample.len() / 2;
+    let nul_at = |offset: usize| sample.iter().skip(offset).step_by(2).filter(|&&byte| byte == 0).count();
+    let (even, odd) = (nul_at(0), nul_at(1));
+    let threshold = pairs as f64 * UTF16_NUL_RATIO;
+    if odd as f64 >= threshold && even == 0 {
+        Some(TextEncoding::Utf16Le)
+    } else if even as f64 >= threshold && odd == 0 {
+        Some(TextEncoding::Utf16Be)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_common_encodings() {
+        let cases: Vec<(Vec<u8>, TextEncoding)> = vec![
+            (b"\xEF\xBB\xBFa\xC3\xB1o".to_vec(), TextEncoding::Utf8),
+            (
+                "año".encode_utf16().flat_map(u16::to_le_bytes).collect(),
+                TextEncoding::Utf16Le,
+            ),
+            (
+                [0xFE, 0xFF]
+                    .into_iter()
+                    .chain("año".encode_utf16().flat_map(u16::to_be_bytes))
+                    .collect(),
+                TextEncoding::Utf16Be,
+            ),
+            (b"\x93a\xF1o\x94".to_vec(), TextEncoding::Windows1252),
+            (b"a\xF1o".to_vec(), TextEncoding::Iso8859_1),
+            (b"5 \xA4 al a\xF1o".to_vec(), TextEncoding::Iso8859_15),
+        ];
+        for (bytes, expected) in cases {
+            let (text, encoding) = decode_text(&bytes, None);
+            assert_eq!(encoding, expected, "{bytes:?}");
+            assert!(text.contains("año"), "{text}");
+        }
+    }
+
+    #[test]
+    fn normalizes_text_and_honours_override() {
+        let (text, encoding) = decode_text(b"line\r\nother\rlast n\xCC\x83", None);
+        assert_eq!(encoding, TextEncoding::Utf8);
+        assert_eq!(text, "line\nother\nlast ñ");
+        let (text, _) = decode_text("a\u{f1}o".as_bytes(), Some(TextEncoding::Windows1252));
+        assert_eq!(text, "aÃ±o");
+    }
+}
@@ -0,0 +1,215 @@
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+
+const BLOCK_TAGS: &[&str] = &[
+    "p",
+    "div",
+    "section",
+    "article",
+    "aside",
+    "header",
+    "footer",
+    "main",
+    "blockquote",
+    "pre",
+    "li",
+    "ul",
+    "ol",
+    "dl",
+    "dt",
+    "dd",
+    "tr",
+    "table",
+    "figure",
+    "figcaption",
+    "hr",
+    "br",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "body",
+];
+const SKIPPED_TAGS: &[&str] = &["head", "script", "style", "title", "rt", "rp", "svg", "math"];
+const HEADING_TAGS: &[&str] = &["h1", "h2", "h3"];
+
+/// Speakable text extracted from an (X)HTML document.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct XhtmlText {
+    /// Paragraphs separated by blank lines.
+    pub text: String,
+    /// Text of the first `h1`-`h3`, used as a fallback chapter title.
+    pub heading: Option<String>,
+}
+
+/// Converts an EPUB content document to plain paragraphs.
+///
+/// The reader is lenient: mismatched end tags and unknown HTML entities do not
+/// abort the conversion, since real-world EPUBs are rarely valid XHTML.
+pub fn xhtml_to_text(markup: &str) -> XhtmlText {
+    let mut reader = Reader::from_str(markup);
+    reader.check_end_names(false);
+    let mut paragraphs: Vec<String> = Vec::new();
+    let mut current = String::new();
+    let mut stack: Vec<(String, bool)> = Vec::new();
+    let mut skip_depth = 0usize;
+    let mut heading: Option<String> = None;
+    let mut heading_buf: Option<String> = None;
+
+    loop {
+        let event = match reader.read_event() {
+            Ok(event) => event,
+            Err(err) => {
+                log::debug!("stopping XHTML conversion early: {err}");
+                break;
+            }
+        };
+        match event {
+            Event::Start(start) => {
+                let name = tag_name(&start);
+                let skipped = SKIPPED_TAGS.contains(&name.as_str()) || is_page_break(&start);
+                if skipped {
+                    skip_depth += 1;
+                }
+                if BLOCK_TAGS.contains(&name.as_str()) {
+                    flush_paragraph(&mut paragraphs, &mut current);
+                }
+                if heading.is_none() && HEADING_TAGS.contains(&name.as_str()) {
+                    heading_buf = Some(String::new());
+                }
+                stack.push((name, skipped));
+            }
+            Event::Empty(start) if BLOCK_TAGS.contains(&tag_name(&start).as_str()) => {
+                flush_paragraph(&mut paragraphs, &mut current);
+            }
+            Event::End(_) => {
+                if let Some((name, skipped)) = stack.pop() {
+                    if skipped {
+                        skip_depth -= 1;
+                    }
+                    if BLOCK_TAGS.contains(&name.as_str()) {
+                        flush_paragraph(&mut paragraphs, &mut current);
+                    }
+                    if HEADING_TAGS.contains(&name.as_str()) {
+                        if let Some(buf) = heading_buf.take() {
+                            let title = collapse_whitespace(&buf);
+                            if !title.is_empty() {
+                                heading = Some(title);
+                            }
+                        }
+                    }
+                }
+            }
+            Event::Text(text) if skip_depth == 0 => {
+                let decoded = text
+                    .unescape_with(resolve_html_entity)
+                    .map(|cow| cow.into_owned())
+                    .unwrap_or_else(|_| String::from_utf8_lossy(&text).into_owned());
+                current.push_str(&decoded);
+                if let Some(buf) = heading_buf.as_mut() {
+                    buf.push_str(&decoded);
+                }
+            }
+            Event::CData(data) if skip_depth == 0 => {
+                current.push_str(&String::from_utf8_lossy(&data));
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    flush_paragraph(&mut paragraphs, &mut current);
+    XhtmlText {
+        text: paragraphs.join("\n\n"),
+        heading,
+    }
+}
+
+fn tag_name(start: &BytesStart<'_>) -> String {
+    String::from_utf8_lossy(start.local_name().as_ref()).to_ascii_lowercase()
+}
+
+fn is_page_break(start: &BytesStart<'_>) -> bool {
+    start.attributes().flatten().any(|attr| {
+        let key = attr.key.local_name();
+        let value = String::from_utf8_lossy(&attr.value);
+        (key.as_ref() == b"type" && value.contains("pagebreak"))
+            || (key.as_ref() == b"role" && value == "doc-pagebreak")
+    })
+}
+
+fn flush_paragraph(paragraphs: &mut Vec<String>, current: &mut String) {
+    let paragraph = collapse_whitespace(current);
+    if !paragraph.is_empty() {
+        paragraphs.push(paragraph);
+    }
+    current.clear();
+}
+
+pub fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Named entities that show up in EPUBs without a DTD declaring them.
+pub fn resolve_html_entity(entity: &str) -> Option<&'static str> {
+    Some(match entity {
+        "nbsp" => "\u{a0}",
+        "shy" => "",
+        "ensp" | "emsp" | "thinsp" => " ",
+        "mdash" => "—",
+        "ndash" => "–",
+        "hellip" => "…",
+        "lsquo" => "‘",
+        "rsquo" => "’",
+        "ldquo" => "“",
+        "rdquo" => "”",
+        "laquo" => "«",
+        "raquo" => "»",
+        "iexcl" => "¡",
+        "iquest" => "¿",
+        "copy" => "©",
+        "reg" => "®",
+        "trade" => "™",
+        "deg" => "°",
+        "middot" => "·",
+        "bull" => "•",
+        "euro" => "€",
+        "aacute" => "á",
+        "eacute" => "é",
+        "iacute" => "í",
+        "oacute" => "ó",
+        "uacute" => "ú",
+        "ntilde" => "ñ",
+        "uuml" => "ü",
+        "Aacute" => "Á",
+        "Eacute" => "É",
+        "Iacute" => "Í",
+        "Oacute" => "Ó",
+        "Uacute" => "Ú",
+        "Ntilde" => "Ñ",
+        "Uuml" => "Ü",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_paragraphs_and_heading() {
+        let markup = r#"<?xml version="1.0"?>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head><title>Ignorado</title><style>p { color: red }</style></head>
+<body>
+  <h1>Capítulo&nbsp;1</h1>
+  <p>Había una   vez<br/>un <em>dragón</em>.</p>
+  <span epub:type="pagebreak" title="12">12</span>
+  <p>Fin&hellip;</p>
+</body></html>"#;
+        let converted = xhtml_to_text(markup);
+        assert_eq!(converted.heading.as_deref(), Some("Capítulo 1"));
+        assert_eq!(converted.text, "Capítulo 1\n\nHabía una vez\n\nun dragón.\n\nFin…");
+    }
+}
@@ -0,0 +1,200 @@
+//! Synthesis core shared by the PiperDesk window and the `piperdesk`
+//! command-line tool: Piper engine, importers, queue, dictionary and audio.
+
+pub mod audio;
+pub mod bookmarks;
+pub mod cache;
+pub mod captions;
+pub mod dict;
+pub mod dubbing;
+pub mod espeak;
+pub mod ffmpeg;
+pub mod hotfolder;
+pub mod importers;
+#[cfg(any(test, feature = "test-support"))]
+pub mod mock;
+pub mod naming;
+pub mod piper;
+pub mod playback;
+pub mod queue;
+pub mod retry;
+pub mod server;
+pub mod session;
+pub mod ssml;
+pub mod text;
+pub mod timing;
+pub mod tts;
+pub mod wyoming;
+
+use crate::audio::AudioFormat;
+use crate::bookmarks::{Listening, ListeningState};
+use crate::cache::{CacheConfig, SynthesisCache};
+use crate::espeak::EspeakConfig;
+use crate::ffmpeg::{FfmpegConfig, FfmpegInfo};
+use crate::hotfolder::HotFolderConfig;
+use crate::importers::markup::MarkupOptions;
+use crate::importers::script::ScriptConfig;
+use crate::importers::text::TextEncoding;
+use crate::importers::ImportSettings;
+use crate::piper::PiperConfig;
+use crate::queue::QueueEvents;
+use crate::retry::{ItemError, RetryConfig};
+use crate::server::ServerConfig;
+use crate::timing::TimingMap;
+use crate::tts::TtsEngine;
+use crate::wyoming::WyomingConfig;
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::PathBuf;
+use std::sync::Arc;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueueItem {
+    pub id: Uuid,
+    pub title: String,
+    pub text: String,
+    pub voice: String,
+    pub rate: f32,
+    pub format: AudioFormat,
+    pub status: QueueStatus,
+    pub output: Option<PathBuf>,
+    /// Sentence/word timing map written next to `output`.
+    #[serde(default)]
+    pub timings: Option<PathBuf>,
+    /// Name of the imported document this item belongs to.
+    #[serde(default)]
+    pub project: Option<String>,
+    /// 1-based chapter position within `project`.
+    #[serde(default)]
+    pub index: Option<usize>,
+    /// Where playback of this item was left.
+    #[serde(default)]
+    pub listening: Option<ListeningState>,
+    /// Synthesis attempts since the item was queued or last retried by hand,
+    /// automatic retries included.
+    #[serde(default)]
+    pub attempts: u32,
+    #[serde(default)]
+    pub last_attempt_at: Option<DateTime<Utc>>,
+    /// When a pending item that failed transiently may be claimed again.
+    #[serde(default)]
+    pub next_retry_at: Option<DateTime<Utc>>,
+    /// Why the last attempt failed; cleared once the item completes.
+    #[serde(default)]
+    pub error: Option<ItemError>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum QueueStatus {
+    Pending,
+    Processing,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AppConfig {
+    pub piper: PiperConfig,
+    /// Second backend, for languages without a Piper voice.
+    pub espeak: EspeakConfig,
+    pub export_dir: PathBuf,
+    /// Output path template relative to `export_dir`, see [`naming::FilenameTemplate`].
+    pub filename_template: String,
+    pub ffmpeg: FfmpegConfig,
+    /// Automatic retries of queue items that failed for a transient reason.
+    pub retry: RetryConfig,
+    pub cache: CacheConfig,
+    /// Python fallback importers.
+    pub importers: ScriptConfig,
+    /// How Markdown and HTML structure is read aloud.
+    pub markup: MarkupOptions,
+    /// Inbox that documents are imported from and synthesized unattended.
+    pub hot_folder: HotFolderConfig,
+    /// Local OpenAI-compatible HTTP API.
+    pub server: ServerConfig,
+    /// Wyoming text-to-speech server for Home Assistant.
+    pub wyoming: WyomingConfig,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        let piper = PiperConfig::from_env().unwrap_or_default();
+        let export_dir = dirs::audio_dir().unwrap_or_else(|| std::env::temp_dir());
+        Self {
+            piper,
+            espeak: EspeakConfig::default(),
+            export_dir,
+            filename_template: naming::DEFAULT_TEMPLATE.to_string(),
+            ffmpeg: FfmpegConfig::default(),
+            retry: RetryConfig::default(),
+            cache: CacheConfig::default(),
+            importers: ScriptConfig::default(),
+            markup: MarkupOptions::default(),
+            hot_folder: HotFolderConfig::default(),
+            server: ServerConfig::default(),
+            wyoming: WyomingConfig::default(),
+        }
+    }
+}
+
+impl AppConfig {
+    /// Importer settings from this configuration. `pdf_script` is the bundled
+    /// pypdf fallback as located by the host application.
+    pub fn import_settings(&self, pdf_script: Option<PathBuf>, encoding: Option<TextEncoding>) -> ImportSettings {
+        ImportSettings {
+            markup: self.markup.clone(),
+            scripts: self.importers.clone(),
+            pdf_script,
+            encoding,
+        }
+    }
+}
+
+pub struct SharedState {
+    pub queue: Mutex<VecDeque<QueueItem>>,
+    pub current: Mutex<Option<Uuid>>,
+    pub config: Mutex<AppConfig>,
+    pub tts: TtsEngine,
+    pub cache: Arc<SynthesisCache>,
+    pub timings: Mutex<HashMap<Uuid, TimingMap>>,
+    /// Project resume points and bookmarks.
+    pub listening: Mutex<Listening>,
+    /// Result of the last ffmpeg detection, `None` if it failed or has not run.
+    pub ffmpeg: Mutex<Option<FfmpegInfo>>,
+    pub events: QueueEvents,
+    /// Items whose automatic retries their caller waits out itself, which
+    /// [`queue::run_due_retries`] leaves alone.
+    pub awaited_retries: Mutex<HashSet<Uuid>>,
+}
+
+impl SharedState {
+    pub fn new(config: AppConfig) -> anyhow::Result<Self> {
+        let cache = Arc::new(SynthesisCache::open(config.cache.clone()));
+        let tts = TtsEngine::from_config(&config, cache.clone())?;
+        Ok(Self::with_engine(config, tts, cache))
+    }
+
+    /// State that synthesizes with `tts` instead of the configured backends,
+    /// e.g. `mock::MockBackend` in tests.
+    pub fn with_engine(config: AppConfig, tts: TtsEngine, cache: Arc<SynthesisCache>) -> Self {
+        Self {
+            queue: Mutex::new(VecDeque::new()),
+            current: Mutex::new(None),
+            config: Mutex::new(config),
+            tts,
+            cache,
+            timings: Mutex::new(HashMap::new()),
+            listening: Mutex::new(Listening::default()),
+            ffmpeg: Mutex::new(None),
+            events: QueueEvents::default(),
+            awaited_retries: Mutex::new(HashSet::new()),
+        }
+    }
+}
@@ -1,75 +1,11 @@
-mod audio;
 mod cmds;
-mod dict;
-mod piper;
-mod ssml;
 
-use crate::audio::AudioFormat;
-use crate::cmds::register_commands;
-use crate::piper::{PiperConfig, PiperEngine};
-use parking_lot::Mutex;
-use serde::{Deserialize, Serialize};
-use std::collections::VecDeque;
-use std::path::PathBuf;
+use crate::cmds::{
+    forward_queue_events, register_commands, restart_api_server, restart_wyoming_server, spawn_player, watch_hot_folder,
+    ApiServer, SpeechStreams, WyomingServer,
+};
+use piperdesk::{ffmpeg, queue, session, AppConfig, SharedState};
 use tauri::{AppHandle, Manager};
-use uuid::Uuid;
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct QueueItem {
-    pub id: Uuid,
-    pub title: String,
-    pub text: String,
-    pub voice: String,
-    pub rate: f32,
-    pub format: AudioFormat,
-    pub status: QueueStatus,
-    pub output: Option<PathBuf>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
-#[serde(rename_all = "camelCase")]
-pub enum QueueStatus {
-    Pending,
-    Processing,
-    Completed,
-    Failed,
-    Cancelled,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct AppConfig {
-    pub piper: PiperConfig,
-    pub export_dir: PathBuf,
-}
-
-impl Default for AppConfig {
-    fn default() -> Self {
-        let piper = PiperConfig::from_env().unwrap_or_default();
-        let export_dir = dirs::audio_dir().unwrap_or_else(|| std::env::temp_dir());
-        Self { piper, export_dir }
-    }
-}
-
-pub struct SharedState {
-    pub queue: Mutex<VecDeque<QueueItem>>,
-    pub current: Mutex<Option<Uuid>>,
-    pub config: Mutex<AppConfig>,
-    pub piper: PiperEngine,
-}
-
-impl SharedState {
-    pub fn new(config: AppConfig) -> anyhow::Result<Self> {
-        let engine = PiperEngine::new(config.piper.clone())?;
-        Ok(Self {
-            queue: Mutex::new(VecDeque::new()),
-            current: Mutex::new(None),
-            config: Mutex::new(config),
-            piper: engine,
-        })
-    }
-}
 
 fn main() {
     if std::env::var("RUST_LOG").is_err() {
@@ -79,16 +15,69 @@ fn main() {
 
     tauri::Builder::default()
         .manage(SharedState::new(AppConfig::default()).expect("failed to initialise state"))
+        .manage(ApiServer::default())
+        .manage(WyomingServer::default())
+        .manage(SpeechStreams::default())
         .invoke_handler(register_commands())
         .setup(|app| {
             let handle = app.handle();
             initialise_window(&handle)?;
+            app.manage(spawn_player(&handle));
+            restore_session(&handle);
+            tauri::async_runtime::spawn(check_ffmpeg(handle.clone()));
+            tauri::async_runtime::spawn(forward_queue_events(handle.clone()));
+            tauri::async_runtime::spawn(run_due_retries(handle.clone()));
+            tauri::async_runtime::spawn(watch_hot_folder(handle.clone()));
+            let wyoming_handle = handle.clone();
+            tauri::async_runtime::spawn(async move {
+                if let Err(err) = restart_api_server(handle).await {
+                    log::error!("API server not started: {err}");
+                }
+            });
+            tauri::async_runtime::spawn(async move {
+                if let Err(err) = restart_wyoming_server(wyoming_handle).await {
+                    log::error!("Wyoming server not started: {err}");
+                }
+            });
             Ok(())
         })
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
 
+async fn check_ffmpeg(app: AppHandle) {
+    let state = app.state::<SharedState>();
+    let config = state.config.lock().ffmpeg.clone();
+    match ffmpeg::detect(&config).await {
+        Ok(info) => {
+            if info.supported {
+                log::info!("using ffmpeg {} at {}", info.version, info.path.display());
+            } else {
+                log::warn!("ffmpeg {} at {} is older than supported", info.version, info.path.display());
+            }
+            *state.ffmpeg.lock() = Some(info);
+        }
+        Err(err) => log::warn!("MP3 export unavailable: {err}"),
+    }
+}
+
+/// Synthesizes failed items again when their automatic retry is due.
+async fn run_due_retries(app: AppHandle) {
+    queue::run_due_retries(app.state::<SharedState>().inner()).await;
+}
+
+/// Brings back the queue of the previous run and keeps saving it.
+fn restore_session(app: &AppHandle) {
+    let path = session::default_path();
+    if let Err(err) = session::restore(app.state::<SharedState>().inner(), &path) {
+        log::warn!("previous queue not restored: {err:#}");
+    }
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        session::persist(app.state::<SharedState>().inner(), &path).await;
+    });
+}
+
 fn initialise_window(app: &AppHandle) -> anyhow::Result<()> {
     if let Some(window) = app.get_window("main") {
         window.set_title("PiperDesk")?;
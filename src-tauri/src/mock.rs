@@ -0,0 +1,258 @@
+//! Stand-ins for the synthesizers, so the queue, exports and servers can be
+//! tested without Piper or voice models: [`MockBackend`] runs in process and
+//! [`FakePiper`] is a shell script that speaks Piper's command-line protocol.
+//!
+//! Both produce predictable audio, can be slowed down and can be told to fail.
+
+use crate::audio::WavAudio;
+use crate::piper::PiperError;
+use crate::text::split_sentences;
+use crate::tts::{Capabilities, PcmStream, TtsBackend, TtsEngine, VoiceInfo};
+use anyhow::{anyhow, Result};
+use futures::future::BoxFuture;
+use futures::StreamExt;
+use parking_lot::Mutex;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub const MOCK_VOICE: &str = "mock_ES-prueba-medium";
+pub const MOCK_SAMPLE_RATE: u32 = 16_000;
+/// How long the mock speaks each character of text.
+pub const MS_PER_CHAR: u64 = 10;
+
+#[derive(Debug, Default)]
+struct Behaviour {
+    delay: Duration,
+    fail_on: Option<String>,
+    failures: usize,
+}
+
+/// A backend that turns every text into a square wave lasting
+/// [`MS_PER_CHAR`] per character, and records what it was asked to say.
+/// Clones share their behaviour and requests, so a test can keep one and
+/// hand another to the engine.
+#[derive(Clone, Default)]
+pub struct MockBackend {
+    behaviour: Arc<Mutex<Behaviour>>,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// An engine whose only backend is a clone of this one, with
+    /// [`MOCK_VOICE`] as the default voice.
+    pub fn engine(&self) -> TtsEngine {
+        TtsEngine::new(vec![Arc::new(self.clone())], MOCK_VOICE)
+    }
+
+    /// Waits this long before each clip.
+    pub fn set_delay(&self, delay: Duration) {
+        self.behaviour.lock().delay = delay;
+    }
+
+    /// Fails every clip whose text contains `pattern`, as a crashed Piper
+    /// would, so the failure counts as transient.
+    pub fn fail_on(&self, pattern: impl Into<String>) {
+        self.behaviour.lock().fail_on = Some(pattern.into());
+    }
+
+    /// Fails the next `count` clips, whatever their text.
+    pub fn fail_next(&self, count: usize) {
+        self.behaviour.lock().failures = count;
+    }
+
+    /// Every text synthesized so far, one per clip, in order.
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().clone()
+    }
+
+    /// The audio the mock produces for `text`.
+    pub fn clip(text: &str) -> WavAudio {
+        let mut audio = WavAudio::new(MOCK_SAMPLE_RATE, 1);
+        let frames = text.chars().count() as u64 * MS_PER_CHAR * u64::from(MOCK_SAMPLE_RATE) / 1000;
+        audio.samples = (0..frames)
+            .map(|frame| if frame / 40 % 2 == 0 { 8_000 } else { -8_000 })
+            .collect();
+        audio
+    }
+
+    async fn synthesize_clip(&self, text: &str) -> Result<WavAudio> {
+        let delay = self.behaviour.lock().delay;
+        if !delay.is_zero() {
+            async_std::task::sleep(delay).await;
+        }
+        self.requests.lock().push(text.to_string());
+        let mut behaviour = self.behaviour.lock();
+        let matches = behaviour
+            .fail_on
+            .as_deref()
+            .is_some_and(|pattern| text.contains(pattern));
+        if matches || behaviour.failures > 0 {
+            behaviour.failures = behaviour.failures.saturating_sub(1);
+            // Fails the way a crashed Piper process does.
+            return Err(PiperError::CommandFailed(format!("injected failure synthesizing '{text}'")).into());
+        }
+        Ok(Self::clip(text))
+    }
+}
+
+impl TtsBackend for MockBackend {
+    fn id(&self) -> &'static str {
+        "mock"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
+
+    fn voices(&self) -> Vec<VoiceInfo> {
+        vec![VoiceInfo {
+            name: MOCK_VOICE.into(),
+            backend: "mock".into(),
+            description: Some("Mock voice".into()),
+            language: Some("es_ES".into()),
+            quality: Some("medium".into()),
+            model_path: None,
+        }]
+    }
+
+    fn synthesize_clips<'a>(
+        &'a self,
+        _voice: &'a VoiceInfo,
+        texts: &'a [&'a str],
+        _speaker: Option<u32>,
+        _speed: Option<f32>,
+    ) -> BoxFuture<'a, Result<Vec<WavAudio>>> {
+        Box::pin(async move {
+            let mut clips = Vec::with_capacity(texts.len());
+            for text in texts {
+                clips.push(self.synthesize_clip(text).await?);
+            }
+            Ok(clips)
+        })
+    }
+
+    /// One clip per sentence, like espeak-ng.
+    fn synthesize_stream(
+        &self,
+        _voice: &VoiceInfo,
+        text: &str,
+        _speaker: Option<u32>,
+        _speed: Option<f32>,
+    ) -> Result<PcmStream> {
+        let sentences: Vec<String> = split_sentences(text)
+            .into_iter()
+            .map(|sentence| sentence.text)
+            .collect();
+        if sentences.is_empty() {
+            return Err(anyhow!("no text to synthesize"));
+        }
+        let backend = self.clone();
+        Ok(Box::pin(futures::stream::iter(sentences).then(move |sentence| {
+            let backend = backend.clone();
+            async move { backend.synthesize_clip(&sentence).await }
+        })))
+    }
+}
+
+/// A shell script installed in place of the Piper binary. It reads Piper's
+/// JSON input lines and answers each with [`FakePiper::clip`], as a WAV file
+/// in `output_file` or as raw PCM on stdout with `--output-raw`. Every line
+/// is appended to `piper.log` next to the script.
+#[cfg(unix)]
+#[derive(Debug, Clone, Default)]
+pub struct FakePiper {
+    /// Waits this long before each clip.
+    pub delay: Duration,
+    /// Exits with status 1 once it reads a line containing this text.
+    pub fail_on: Option<String>,
+    /// Reads its input and exits with this status without writing audio.
+    pub exit_code: Option<i32>,
+    /// Bytes logged to stderr for every line, as Piper logs each sentence.
+    pub stderr_per_line: usize,
+}
+
+#[cfg(unix)]
+impl FakePiper {
+    /// Voice installed by [`FakePiper::install`].
+    pub const VOICE: &'static str = "es_ES-prueba-medium";
+
+    /// The clip returned for every line: a quarter of a second at 22050 Hz.
+    pub fn clip() -> WavAudio {
+        let mut audio = WavAudio::new(22_050, 1);
+        audio.samples = (0..5_512)
+            .map(|frame| if frame / 50 % 2 == 0 { 6_000 } else { -6_000 })
+            .collect();
+        audio
+    }
+
+    /// Writes the script and a voice model into `dir` and returns a Piper
+    /// configuration that uses them.
+    pub fn install(&self, dir: &std::path::Path) -> Result<crate::piper::PiperConfig> {
+        use std::fmt::Write;
+        use std::fs;
+        use std::os::unix::fs::PermissionsExt;
+
+        let voices_dir = dir.join("voices");
+        fs::create_dir_all(&voices_dir)?;
+        fs::write(voices_dir.join(format!("{}.onnx", Self::VOICE)), b"")?;
+        let clip = Self::clip();
+        let wav = dir.join("piper-clip.wav");
+        crate::audio::write_wav(&wav, &clip)?;
+        let raw = dir.join("piper-clip.raw");
+        fs::write(
+            &raw,
+            clip.samples.iter().flat_map(|s| s.to_le_bytes()).collect::<Vec<u8>>(),
+        )?;
+
+        let mut script = String::from("#!/bin/sh\n");
+        if let Some(code) = self.exit_code {
+            writeln!(script, "cat > /dev/null\necho 'fake piper crashed' >&2\nexit {code}")?;
+        }
+        script.push_str("raw=0\nfor arg in \"$@\"; do\n  if [ \"$arg\" = --output-raw ]; then raw=1; fi\ndone\n");
+        script.push_str("while IFS= read -r line; do\n");
+        writeln!(
+            script,
+            "  printf '%s\\n' \"$line\" >> '{}'",
+            dir.join("piper.log").display()
+        )?;
+        if !self.delay.is_zero() {
+            writeln!(script, "  sleep {}", self.delay.as_secs_f32())?;
+        }
+        if self.stderr_per_line > 0 {
+            let log_line = dir.join("piper-stderr.txt");
+            fs::write(&log_line, "x".repeat(self.stderr_per_line))?;
+            writeln!(script, "  cat '{}' >&2", log_line.display())?;
+        }
+        if let Some(pattern) = &self.fail_on {
+            writeln!(
+                script,
+                "  case \"$line\" in *'{pattern}'*) echo 'fake piper failed' >&2; exit 1;; esac"
+            )?;
+        }
+        writeln!(script, "  if [ $raw = 1 ]; then cat '{}'; continue; fi", raw.display())?;
+        script.push_str("  out=$(printf '%s' \"$line\" | sed 's/.*\"output_file\":\"\\([^\"]*\\)\".*/\\1/')\n");
+        writeln!(script, "  cp '{}' \"$out\"\ndone", wav.display())?;
+
+        let binary_path = dir.join("piper");
+        fs::write(&binary_path, script)?;
+        fs::set_permissions(&binary_path, fs::Permissions::from_mode(0o755))?;
+        Ok(crate::piper::PiperConfig {
+            binary_path,
+            voices_dir,
+            default_voice: Self::VOICE.into(),
+            models_manifest: None,
+            ..crate::piper::PiperConfig::default()
+        })
+    }
+
+    /// Lines the script has read from every Piper run so far.
+    pub fn log(dir: &std::path::Path) -> Vec<String> {
+        std::fs::read_to_string(dir.join("piper.log"))
+            .map(|log| log.lines().map(String::from).collect())
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,379 @@
+use chrono::{DateTime, Local};
+use std::fs::OpenOptions;
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use unicode_normalization::UnicodeNormalization;
+use uuid::Uuid;
+
+pub const DEFAULT_TEMPLATE: &str = "{title}.{ext}";
+
+const MAX_COMPONENT_CHARS: usize = 120;
+const MAX_COLLISION_SUFFIX: usize = 10_000;
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9", "LPT1", "LPT2",
+    "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum TemplateError {
+    #[error("unknown placeholder '{{{0}}}'")]
+    UnknownPlaceholder(String),
+    #[error("invalid format spec '{spec}' for '{{{name}}}'")]
+    InvalidSpec { name: String, spec: String },
+    #[error("unclosed '{{' in filename template")]
+    Unclosed,
+    #[error("filename template must be a relative path without '..'")]
+    EscapesDirectory,
+    #[error("filename template must end with '.{{ext}}'")]
+    MissingExtension,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Title,
+    Voice,
+    Project,
+    Date,
+    Time,
+    Id,
+    Ext,
+    Index { width: usize },
+}
+
+/// A parsed filename template such as `{date}/{project}/{index:03}-{title}.{ext}`.
+///
+/// `/` separates directories. Placeholder values are slugged so they can never
+/// introduce separators of their own; placeholders that resolve to nothing are
+/// dropped together with the separators around them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilenameTemplate {
+    parts: Vec<Part>,
+}
+
+/// Values available to a [`FilenameTemplate`].
+#[derive(Debug, Clone)]
+pub struct NameContext<'a> {
+    pub title: &'a str,
+    pub voice: &'a str,
+    pub project: Option<&'a str>,
+    pub index: Option<usize>,
+    pub id: Uuid,
+    pub ext: &'a str,
+    pub now: DateTime<Local>,
+}
+
+impl FilenameTemplate {
+    pub fn parse(template: &str) -> Result<Self, TemplateError> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(ch) = chars.next() {
+            match ch {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut inner = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(ch) => inner.push(ch),
+                            None => return Err(TemplateError::Unclosed),
+                        }
+                    }
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(parse_placeholder(&inner)?);
+                }
+                '\\' => literal.push('/'),
+                ch => literal.push(ch),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+        let ends_with_ext = matches!(parts.as_slice(), [.., Part::Literal(dot), Part::Ext] if dot.ends_with('.'));
+        if !ends_with_ext {
+            return Err(TemplateError::MissingExtension);
+        }
+        let climbs = parts.iter().any(|part| match part {
+            Part::Literal(text) => text.split('/').any(|segment| segment.trim() == ".."),
+            _ => false,
+        });
+        if template.starts_with(['/', '\\']) || climbs {
+            return Err(TemplateError::EscapesDirectory);
+        }
+        Ok(Self { parts })
+    }
+
+    /// Renders the template into a path relative to the export directory.
+    pub fn render(&self, ctx: &NameContext<'_>) -> PathBuf {
+        let mut rendered = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(text) => rendered.push_str(text),
+                Part::Title => rendered.push_str(&slugify(ctx.title)),
+                Part::Voice => rendered.push_str(&slugify(ctx.voice)),
+                Part::Project => rendered.push_str(&ctx.project.map(slugify).unwrap_or_default()),
+                Part::Date => rendered.push_str(&ctx.now.format("%Y-%m-%d").to_string()),
+                Part::Time => rendered.push_str(&ctx.now.format("%H%M%S").to_string()),
+                Part::Id => rendered.push_str(&ctx.id.simple().to_string()[..8]),
+                Part::Ext => rendered.push_str(ctx.ext),
+                Part::Index { width } => {
+                    if let Some(index) = ctx.index {
+                        rendered.push_str(&format!("{index:0width$}"));
+                    }
+                }
+            }
+        }
+
+        let segments: Vec<&str> = rendered.split('/').collect();
+        let last = segments.len() - 1;
+        let mut path = PathBuf::new();
+        for (idx, segment) in segments.into_iter().enumerate() {
+            let cleaned = if idx == last {
+                clean_file_name(segment, ctx.ext)
+            } else {
+                clean_component(segment)
+            };
+            if let Some(cleaned) = cleaned {
+                path.push(cleaned);
+            }
+        }
+        path
+    }
+}
+
+fn parse_placeholder(inner: &str) -> Result<Part, TemplateError> {
+    let (name, spec) = match inner.split_once(':') {
+        Some((name, spec)) => (name.trim(), Some(spec.trim())),
+        None => (inner.trim(), None),
+    };
+    let part = match name {
+        "title" => Part::Title,
+        "voice" => Part::Voice,
+        "project" => Part::Project,
+        "date" => Part::Date,
+        "time" => Part::Time,
+        "id" => Part::Id,
+        "ext" => Part::Ext,
+        "index" => {
+            let width = match spec {
+                None => 0,
+                Some(spec) => spec.parse().map_err(|_| TemplateError::InvalidSpec {
+                    name: name.to_string(),
+                    spec: spec.to_string(),
+                })?,
+            };
+            return Ok(Part::Index { width });
+        }
+        other => return Err(TemplateError::UnknownPlaceholder(other.to_string())),
+    };
+    if let Some(spec) = spec {
+        return Err(TemplateError::InvalidSpec {
+            name: name.to_string(),
+            spec: spec.to_string(),
+        });
+    }
+    Ok(part)
+}
+
+/// Turns arbitrary text into a file-name-safe slug, keeping letters and digits
+/// from every script (so "Capítulo único" stays "Capítulo_único").
+pub fn slugify(input: &str) -> String {
+    let mut slug = String::new();
+    let mut pending_separator = false;
+    for ch in input.nfc() {
+        if ch.is_alphanumeric() {
+            if pending_separator && !slug.is_empty() {
+                slug.push('_');
+            }
+            pending_separator = false;
+            slug.push(ch);
+        } else if matches!(ch, '-' | '.') && !slug.is_empty() && !pending_separator {
+            slug.push(ch);
+        } else {
+            pending_separator = true;
+        }
+    }
+    let slug = slug.trim_end_matches(['-', '.', '_']);
+    truncate_chars(slug, MAX_COMPONENT_CHARS).to_string()
+}
+
+fn clean_component(segment: &str) -> Option<String> {
+    let cleaned: String = segment
+        .chars()
+        .filter(|ch| !ch.is_control() && !matches!(ch, '<' | '>' | ':' | '"' | '|' | '?' | '*'))
+        .collect();
+    let cleaned = collapse_separators(&cleaned);
+    let cleaned = truncate_chars(cleaned.trim_matches(['-', '_', ' ', '.']), MAX_COMPONENT_CHARS);
+    if cleaned.is_empty() {
+        return None;
+    }
+    Some(avoid_reserved(cleaned))
+}
+
+fn clean_file_name(segment: &str, ext: &str) -> Option<String> {
+    let stem = segment
+        .strip_suffix(ext)
+        .and_then(|stem| stem.strip_suffix('.'))
+        .unwrap_or(segment);
+    let stem = clean_component(stem).unwrap_or_else(|| "untitled".to_string());
+    Some(format!("{stem}.{ext}"))
+}
+
+/// Collapses the doubled separators left behind by empty placeholders, so
+/// `{index}-{title}` with no index does not produce a leading `-`.
+fn collapse_separators(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    let mut previous: Option<char> = None;
+    for ch in input.chars() {
+        let is_separator = matches!(ch, '-' | '_' | ' ');
+        if is_separator && previous.is_some_and(|prev| matches!(prev, '-' | '_' | ' ')) {
+            continue;
+        }
+        out.push(ch);
+        previous = Some(ch);
+    }
+    out
+}
+
+fn truncate_chars(input: &str, max: usize) -> &str {
+    match input.char_indices().nth(max) {
+        Some((idx, _)) => &input[..idx],
+        None => input,
+    }
+}
+
+fn avoid_reserved(name: &str) -> String {
+    let base = name.split('.').next().unwrap_or(name);
+    if RESERVED_NAMES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(base))
+    {
+        format!("_{name}")
+    } else {
+        name.to_string()
+    }
+}
+
+/// Atomically claims `path`, or the first free `name-2.ext`, `name-3.ext`...
+/// variant, by creating an empty placeholder file. Synthesis later overwrites
+/// the placeholder, so two items with the same title never share a file.
+pub fn claim_unique_path(path: &Path) -> io::Result<PathBuf> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("untitled");
+    let ext = path.extension().and_then(|s| s.to_str());
+    for attempt in 1..=MAX_COLLISION_SUFFIX {
+        let candidate = if attempt == 1 {
+            path.to_path_buf()
+        } else {
+            let name = match ext {
+                Some(ext) => format!("{stem}-{attempt}.{ext}"),
+                None => format!("{stem}-{attempt}"),
+            };
+            path.with_file_name(name)
+        };
+        match OpenOptions::new().write(true).create_new(true).open(&candidate) {
+            Ok(_) => return Ok(candidate),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!("no free file name for {}", path.display()),
+    ))
+}
+
+/// Removes a placeholder left by [`claim_unique_path`] if nothing was written.
+pub fn release_unused_path(path: &Path) {
+    if path.metadata().map(|meta| meta.len() == 0).unwrap_or(false) {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn context<'a>(title: &'a str, project: Option<&'a str>, index: Option<usize>) -> NameContext<'a> {
+        NameContext {
+            title,
+            voice: "es_ES-davefx-medium",
+            project,
+            index,
+            id: Uuid::nil(),
+            ext: "wav",
+            now: Local.with_ymd_and_hms(2024, 3, 9, 8, 30, 0).unwrap(),
+        }
+    }
+
+    #[test]
+    fn slugs_keep_unicode_letters() {
+        assert_eq!(slugify("Capítulo único"), "Capítulo_único");
+        assert_eq!(slugify("第一章 はじめに"), "第一章_はじめに");
+        assert_eq!(slugify("  ¿Qué? / ¡Sí!  "), "Qué_Sí");
+        assert_eq!(slugify("a/../../b"), "a_b");
+    }
+
+    #[test]
+    fn renders_templates_with_directories() {
+        let template = FilenameTemplate::parse("{date}/{project}/{index:03}-{title}-{voice}.{ext}").unwrap();
+        let path = template.render(&context("Capítulo único", Some("Mi libro"), Some(7)));
+        assert_eq!(
+            path,
+            PathBuf::from("2024-03-09/Mi_libro/007-Capítulo_único-es_ES-davefx-medium.wav")
+        );
+
+        let path = template.render(&context("Intro", None, None));
+        assert_eq!(path, PathBuf::from("2024-03-09/Intro-es_ES-davefx-medium.wav"));
+    }
+
+    #[test]
+    fn handles_empty_and_reserved_names() {
+        let template = FilenameTemplate::parse(DEFAULT_TEMPLATE).unwrap();
+        assert_eq!(
+            template.render(&context("¿?", None, None)),
+            PathBuf::from("untitled.wav")
+        );
+        assert_eq!(template.render(&context("con", None, None)), PathBuf::from("_con.wav"));
+    }
+
+    #[test]
+    fn rejects_invalid_templates() {
+        assert_eq!(
+            FilenameTemplate::parse("{nope}.{ext}"),
+            Err(TemplateError::UnknownPlaceholder("nope".into()))
+        );
+        assert_eq!(
+            FilenameTemplate::parse("../{title}.{ext}"),
+            Err(TemplateError::EscapesDirectory)
+        );
+        assert_eq!(FilenameTemplate::parse("{title}"), Err(TemplateError::MissingExtension));
+        assert_eq!(FilenameTemplate::parse("{title.{ext"), Err(TemplateError::Unclosed));
+    }
+
+    #[test]
+    fn claims_collision_free_paths() {
+        let dir = std::env::temp_dir().join(format!("piperdesk-naming-{}", Uuid::new_v4()));
+        let first = claim_unique_path(&dir.join("Intro.wav")).unwrap();
+        let second = claim_unique_path(&dir.join("Intro.wav")).unwrap();
+        assert_eq!(first, dir.join("Intro.wav"));
+        assert_eq!(second, dir.join("Intro-2.wav"));
+        release_unused_path(&second);
+        assert!(!second.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
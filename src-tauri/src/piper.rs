@@ -1,21 +1,39 @@
-use crate::audio::AudioFormat;
+use crate::audio::WavAudio;
+use crate::cache::{ChunkParams, SynthesisCache};
+use crate::text::split_sentences;
+use crate::tts::{Capabilities, PcmStream, TtsBackend, VoiceInfo};
 use anyhow::{anyhow, Context, Result};
-use async_process::Command;
+use async_process::{Child, ChildStderr, ChildStdout, Command};
+use futures::future::BoxFuture;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+use std::sync::Arc;
 use std::time::Duration;
 use thiserror::Error;
+use uuid::Uuid;
 use walkdir::WalkDir;
 
+/// Pause Piper appends after every sentence, in seconds.
+const SENTENCE_SILENCE: f32 = 0.2;
+/// Bytes read from Piper's raw output per streamed chunk, about 90 ms at
+/// 22.05 kHz.
+const STREAM_CHUNK_BYTES: usize = 4096;
+/// Sample rate of voices whose model config does not state one.
+const DEFAULT_SAMPLE_RATE: u32 = 22050;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
+#[serde(rename_all = "camelCase", default)]
 pub struct PiperConfig {
     pub binary_path: PathBuf,
     pub voices_dir: PathBuf,
     pub default_voice: String,
     pub models_manifest: Option<PathBuf>,
+    /// Longest a Piper run may take, or go without producing streamed audio,
+    /// before it is killed.
+    pub timeout_secs: u64,
 }
 
 impl Default for PiperConfig {
@@ -29,6 +47,7 @@ impl Default for PiperConfig {
             voices_dir,
             default_voice: "en_US-amy-medium".to_string(),
             models_manifest: None,
+            timeout_secs: 900,
         }
     }
 }
@@ -52,33 +71,19 @@ pub enum PiperError {
     VoiceNotFound(String),
     #[error("binary not executable: {0}")]
     InvalidBinary(String),
+    #[error("failed to spawn Piper: {0}")]
+    Spawn(#[source] std::io::Error),
     #[error("command execution failed: {0}")]
     CommandFailed(String),
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct VoiceInfo {
-    pub name: String,
-    pub description: Option<String>,
-    pub language: Option<String>,
-    pub quality: Option<String>,
-    pub model_path: PathBuf,
+    #[error("Piper did not finish within {0} seconds")]
+    TimedOut(u64),
 }
 
 #[derive(Debug, Clone)]
 pub struct PiperEngine {
     config: PiperConfig,
     voices: HashMap<String, VoiceInfo>,
-}
-
-#[derive(Debug, Clone)]
-pub struct SynthesisRequest {
-    pub text: String,
-    pub voice: Option<String>,
-    pub output: PathBuf,
-    pub speed: Option<f32>,
-    pub format: AudioFormat,
+    cache: Option<Arc<SynthesisCache>>,
 }
 
 impl PiperEngine {
@@ -87,7 +92,16 @@ impl PiperEngine {
             log::warn!("Piper binary '{}' not found in PATH", config.binary_path.display());
         }
         let voices = discover_voices(&config)?;
-        Ok(Self { config, voices })
+        Ok(Self {
+            config,
+            voices,
+            cache: None,
+        })
+    }
+
+    pub fn with_cache(mut self, cache: Arc<SynthesisCache>) -> Self {
+        self.cache = Some(cache);
+        self
     }
 
     pub fn config(&self) -> &PiperConfig {
@@ -98,6 +112,7 @@ impl PiperEngine {
         self.voices.values().cloned().collect()
     }
 
+    /// Looks up a Piper voice by its plain name, or the default voice.
     pub fn resolve_voice(&self, requested: Option<&str>) -> Result<VoiceInfo> {
         let name = requested.unwrap_or(&self.config.default_voice);
         self.voices
@@ -106,47 +121,334 @@ impl PiperEngine {
             .ok_or_else(|| PiperError::VoiceNotFound(name.to_string()).into())
     }
 
-    pub async fn synthesize(&self, request: SynthesisRequest) -> Result<PathBuf> {
-        let voice = self.resolve_voice(request.voice.as_deref())?;
-        let mut final_output = request.output;
-        if let Some(parent) = final_output.parent() {
-            fs::create_dir_all(parent)?;
+    /// Synthesizes each text as a separate clip in a single Piper run.
+    /// Clips already in the synthesis cache are not sent to Piper again.
+    pub async fn synthesize_clips(
+        &self,
+        voice: &VoiceInfo,
+        texts: &[&str],
+        speaker: Option<u32>,
+        speed: Option<f32>,
+    ) -> Result<Vec<WavAudio>> {
+        let model_path = model_path(voice)?;
+        let model = fs::metadata(model_path).ok();
+        let params = ChunkParams {
+            voice: &voice.name,
+            model_path,
+            model_size: model.as_ref().map(|meta| meta.len()).unwrap_or_default(),
+            model_modified: model
+                .and_then(|meta| meta.modified().ok())
+                .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|elapsed| elapsed.as_secs())
+                .unwrap_or_default(),
+            speaker,
+            length_scale: speed,
+            sentence_silence: SENTENCE_SILENCE,
+        };
+        let keys: Vec<String> = texts.iter().map(|text| SynthesisCache::key(text, &params)).collect();
+        let mut pieces: Vec<Option<WavAudio>> = keys
+            .iter()
+            .map(|key| self.cache.as_ref().and_then(|cache| cache.get(key)))
+            .collect();
+        let missing: Vec<usize> = (0..pieces.len()).filter(|&idx| pieces[idx].is_none()).collect();
+
+        if !missing.is_empty() {
+            let missing_texts: Vec<&str> = missing.iter().map(|&idx| texts[idx]).collect();
+            let work_dir = std::env::temp_dir().join(format!("piperdesk-{}", Uuid::new_v4()));
+            fs::create_dir_all(&work_dir)?;
+            let result = self
+                .synthesize_chunks(voice, &missing_texts, speaker, speed, &work_dir)
+                .await
+                .and_then(|chunks| {
+                    chunks
+                        .iter()
+                        .map(|chunk| crate::audio::read_wav(chunk))
+                        .collect::<Result<Vec<_>>>()
+                });
+            let _ = fs::remove_dir_all(&work_dir);
+            for (idx, audio) in missing.into_iter().zip(result?) {
+                if let Some(cache) = &self.cache {
+                    if let Err(err) = cache.insert(&keys[idx], &audio) {
+                        log::warn!("failed to cache synthesized chunk: {err}");
+                    }
+                }
+                pieces[idx] = Some(audio);
+            }
+        }
+        if let Some(cache) = &self.cache {
+            if let Err(err) = cache.flush() {
+                log::warn!("failed to persist synthesis cache index: {err}");
+            }
+        }
+
+        pieces
+            .into_iter()
+            .collect::<Option<_>>()
+            .context("Piper produced no audio")
+    }
+
+    /// Runs Piper with `--output-raw` and yields its audio while it is still
+    /// speaking, so playback can start after the first sentence. Streamed
+    /// audio bypasses the synthesis cache. Dropping the stream stops Piper.
+    pub fn synthesize_stream(
+        &self,
+        voice: &VoiceInfo,
+        text: &str,
+        speaker: Option<u32>,
+        speed: Option<f32>,
+    ) -> Result<PcmStream> {
+        let sentences = split_sentences(text);
+        if sentences.is_empty() {
+            return Err(anyhow!("no text to synthesize"));
         }
-        let mut piper_output = final_output.clone();
-        if request.format == AudioFormat::Mp3 {
-            piper_output = final_output.with_extension("wav");
+        let mut input = String::new();
+        for sentence in &sentences {
+            let mut line = serde_json::json!({ "text": sentence.text });
+            if let Some(speaker) = speaker {
+                line["speaker_id"] = speaker.into();
+            }
+            input.push_str(&line.to_string());
+            input.push('\n');
         }
+
+        let model_path = model_path(voice)?;
         let mut cmd = Command::new(&self.config.binary_path);
         cmd.arg("--model")
-            .arg(&voice.model_path)
-            .arg("--output_file")
-            .arg(&piper_output)
+            .arg(model_path)
+            .arg("--json-input")
+            .arg("--output-raw")
             .arg("--sentence_silence")
-            .arg("0.2");
-        if let Some(speed) = request.speed {
+            .arg(SENTENCE_SILENCE.to_string());
+        if let Some(speed) = speed {
             cmd.arg("--length_scale").arg(format!("{speed}"));
         }
-        cmd.stdin(async_process::Stdio::piped());
-        let mut child = cmd.spawn().map_err(|err| anyhow!("failed to spawn Piper: {err}"))?;
+        cmd.stdin(async_process::Stdio::piped())
+            .stdout(async_process::Stdio::piped())
+            .stderr(async_process::Stdio::piped())
+            .kill_on_drop(true);
+        let mut child = cmd.spawn().map_err(PiperError::Spawn)?;
         if let Some(mut stdin) = child.stdin.take() {
-            use async_std::io::WriteExt;
-            stdin
-                .write_all(request.text.as_bytes())
-                .await
-                .context("write text to Piper")?;
+            async_std::task::spawn(async move {
+                use async_std::io::WriteExt;
+                // Fails only if Piper exits early, which `finish` reports.
+                let _ = stdin.write_all(input.as_bytes()).await;
+            });
+        }
+        // Drained concurrently so a chatty Piper never blocks on a full pipe.
+        let stderr = child.stderr.take().map(read_log);
+        let stdout = child.stdout.take().context("Piper stdout is not captured")?;
+        let output = RawOutput {
+            child,
+            stdout,
+            stderr,
+            sample_rate: model_sample_rate(model_path),
+            odd_byte: None,
+            timeout_secs: self.config.timeout_secs,
+        };
+        Ok(Box::pin(futures::stream::try_unfold(output, |mut output| async move {
+            match output.next_chunk().await? {
+                Some(audio) => Ok(Some((audio, output))),
+                None => output.finish().await.map(|()| None),
+            }
+        })))
+    }
+
+    async fn synthesize_chunks(
+        &self,
+        voice: &VoiceInfo,
+        texts: &[&str],
+        speaker: Option<u32>,
+        speed: Option<f32>,
+        work_dir: &Path,
+    ) -> Result<Vec<PathBuf>> {
+        let chunks: Vec<PathBuf> = (0..texts.len())
+            .map(|idx| work_dir.join(format!("chunk_{idx:05}.wav")))
+            .collect();
+        let mut input = String::new();
+        for (text, chunk) in texts.iter().zip(&chunks) {
+            let mut line = serde_json::json!({ "text": text, "output_file": chunk });
+            if let Some(speaker) = speaker {
+                line["speaker_id"] = speaker.into();
+            }
+            input.push_str(&line.to_string());
+            input.push('\n');
         }
-        let status = child.status().await?;
+
+        let mut cmd = Command::new(&self.config.binary_path);
+        cmd.arg("--model")
+            .arg(model_path(voice)?)
+            .arg("--json-input")
+            .arg("--output_dir")
+            .arg(work_dir)
+            .arg("--sentence_silence")
+            .arg(SENTENCE_SILENCE.to_string());
+        if let Some(speed) = speed {
+            cmd.arg("--length_scale").arg(format!("{speed}"));
+        }
+        cmd.stdin(async_process::Stdio::piped())
+            .stdout(async_process::Stdio::null())
+            .stderr(async_process::Stdio::piped());
+        let mut child = cmd.spawn().map_err(PiperError::Spawn)?;
+        // Written from a task while stderr is drained: Piper logs every
+        // sentence, and with both pipes full neither side would move.
+        let writer = child.stdin.take().map(|mut stdin| {
+            async_std::task::spawn(async move {
+                use async_std::io::WriteExt;
+                stdin.write_all(input.as_bytes()).await
+            })
+        });
+        let stderr = child.stderr.take().map(read_log);
+        let status = wait_or_kill(&mut child, self.config.timeout_secs).await?;
         if !status.success() {
-            return Err(PiperError::CommandFailed(status.to_string()).into());
+            let stderr = match stderr {
+                Some(task) => task.await,
+                None => String::new(),
+            };
+            return Err(PiperError::CommandFailed(format!("{status}: {}", stderr.trim())).into());
         }
-        if request.format == AudioFormat::Mp3 {
-            final_output = crate::audio::transcode_wav_to_mp3(&piper_output)?;
-            let _ = fs::remove_file(&piper_output);
-        } else {
-            final_output = piper_output;
+        if let Some(writer) = writer {
+            writer.await.context("write text to Piper")?;
+        }
+        Ok(chunks)
+    }
+}
+
+impl TtsBackend for PiperEngine {
+    fn id(&self) -> &'static str {
+        "piper"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            ssml: false,
+            speakers: true,
+            cached: self.cache.is_some(),
+        }
+    }
+
+    fn voices(&self) -> Vec<VoiceInfo> {
+        PiperEngine::voices(self)
+    }
+
+    fn synthesize_clips<'a>(
+        &'a self,
+        voice: &'a VoiceInfo,
+        texts: &'a [&'a str],
+        speaker: Option<u32>,
+        speed: Option<f32>,
+    ) -> BoxFuture<'a, Result<Vec<WavAudio>>> {
+        Box::pin(PiperEngine::synthesize_clips(self, voice, texts, speaker, speed))
+    }
+
+    fn synthesize_stream(
+        &self,
+        voice: &VoiceInfo,
+        text: &str,
+        speaker: Option<u32>,
+        speed: Option<f32>,
+    ) -> Result<PcmStream> {
+        PiperEngine::synthesize_stream(self, voice, text, speaker, speed)
+    }
+}
+
+/// A Piper process writing 16-bit mono PCM to stdout.
+struct RawOutput {
+    child: Child,
+    stdout: ChildStdout,
+    stderr: Option<async_std::task::JoinHandle<String>>,
+    sample_rate: u32,
+    /// First byte of a sample split across two reads.
+    odd_byte: Option<u8>,
+    timeout_secs: u64,
+}
+
+impl RawOutput {
+    async fn next_chunk(&mut self) -> Result<Option<WavAudio>> {
+        use async_std::io::ReadExt;
+        let mut buffer = vec![0; STREAM_CHUNK_BYTES];
+        loop {
+            let timeout = Duration::from_secs(self.timeout_secs.max(1));
+            let read = match async_std::future::timeout(timeout, self.stdout.read(&mut buffer)).await {
+                Ok(read) => read.context("read audio from Piper")?,
+                Err(_) => {
+                    let _ = self.child.kill();
+                    let _ = self.child.status().await;
+                    return Err(PiperError::TimedOut(self.timeout_secs).into());
+                }
+            };
+            if read == 0 {
+                return Ok(None);
+            }
+            let mut bytes: Vec<u8> = self.odd_byte.take().into_iter().collect();
+            bytes.extend_from_slice(&buffer[..read]);
+            if bytes.len() % 2 == 1 {
+                self.odd_byte = bytes.pop();
+            }
+            if bytes.is_empty() {
+                continue;
+            }
+            let mut audio = WavAudio::new(self.sample_rate, 1);
+            audio.samples = bytes
+                .chunks_exact(2)
+                .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+                .collect();
+            return Ok(Some(audio));
         }
-        Ok(final_output)
     }
+
+    async fn finish(mut self) -> Result<()> {
+        let status = wait_or_kill(&mut self.child, self.timeout_secs).await?;
+        let stderr = match self.stderr.take() {
+            Some(task) => task.await,
+            None => String::new(),
+        };
+        if !status.success() {
+            return Err(PiperError::CommandFailed(format!("{status}: {}", stderr.trim())).into());
+        }
+        Ok(())
+    }
+}
+
+/// Collects what Piper logs to stderr, for error messages.
+fn read_log(mut stderr: ChildStderr) -> async_std::task::JoinHandle<String> {
+    async_std::task::spawn(async move {
+        use async_std::io::ReadExt;
+        let mut log = String::new();
+        let _ = stderr.read_to_string(&mut log).await;
+        log
+    })
+}
+
+/// Waits for Piper to exit, killing it after `timeout_secs`.
+async fn wait_or_kill(child: &mut Child, timeout_secs: u64) -> Result<ExitStatus> {
+    let timeout = Duration::from_secs(timeout_secs.max(1));
+    match async_std::future::timeout(timeout, child.status()).await {
+        Ok(status) => Ok(status?),
+        Err(_) => {
+            let _ = child.kill();
+            let _ = child.status().await;
+            Err(PiperError::TimedOut(timeout_secs).into())
+        }
+    }
+}
+
+fn model_path(voice: &VoiceInfo) -> Result<&Path> {
+    voice
+        .model_path
+        .as_deref()
+        .with_context(|| format!("voice '{}' has no Piper model", voice.name))
+}
+
+/// Reads `audio.sample_rate` from the `<model>.onnx.json` next to the model.
+fn model_sample_rate(model_path: &Path) -> u32 {
+    let mut config_path = model_path.as_os_str().to_owned();
+    config_path.push(".json");
+    fs::read(&config_path)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice::<serde_json::Value>(&bytes).ok())
+        .and_then(|config| config["audio"]["sample_rate"].as_u64())
+        .and_then(|rate| u32::try_from(rate).ok())
+        .unwrap_or(DEFAULT_SAMPLE_RATE)
 }
 
 fn discover_voices(config: &PiperConfig) -> Result<HashMap<String, VoiceInfo>> {
@@ -169,11 +471,12 @@ fn discover_voices(config: &PiperConfig) -> Result<HashMap<String, VoiceInfo>> {
                     voices.insert(
                         stem.clone(),
                         VoiceInfo {
+                            language: voice_language(&stem),
                             name: stem,
+                            backend: "piper".into(),
                             description: None,
-                            language: None,
                             quality: None,
-                            model_path: path,
+                            model_path: Some(path),
                         },
                     );
                 }
@@ -183,6 +486,20 @@ fn discover_voices(config: &PiperConfig) -> Result<HashMap<String, VoiceInfo>> {
     Ok(voices)
 }
 
+/// Piper voice names start with their locale, e.g. `es_ES-davefx-medium`.
+fn voice_language(name: &str) -> Option<String> {
+    let locale = name.split('-').next()?;
+    let (language, region) = locale.split_once('_').unwrap_or((locale, ""));
+    let valid = (2..=3).contains(&language.len()) && language.chars().all(|c| c.is_ascii_lowercase());
+    valid.then(|| {
+        if region.is_empty() {
+            language.to_string()
+        } else {
+            format!("{language}-{region}")
+        }
+    })
+}
+
 fn default_voice_dir() -> PathBuf {
     dirs::data_dir()
         .map(|mut dir| {
@@ -192,3 +509,67 @@ fn default_voice_dir() -> PathBuf {
         })
         .unwrap_or_else(|| PathBuf::from("./voices"))
 }
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use futures::TryStreamExt;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn engine_with_script(root: &Path, script: &str) -> (PiperEngine, VoiceInfo) {
+        let voices_dir = root.join("voices");
+        fs::create_dir_all(&voices_dir).unwrap();
+        fs::write(voices_dir.join("es_ES-prueba-medium.onnx"), b"").unwrap();
+        fs::write(
+            voices_dir.join("es_ES-prueba-medium.onnx.json"),
+            r#"{"audio": {"sample_rate": 16000}}"#,
+        )
+        .unwrap();
+        let binary_path = root.join("piper");
+        fs::write(&binary_path, script).unwrap();
+        fs::set_permissions(&binary_path, fs::Permissions::from_mode(0o755)).unwrap();
+        let engine = PiperEngine::new(PiperConfig {
+            binary_path,
+            voices_dir,
+            default_voice: "es_ES-prueba-medium".into(),
+            models_manifest: None,
+            ..PiperConfig::default()
+        })
+        .unwrap();
+        let voice = engine.resolve_voice(None).unwrap();
+        (engine, voice)
+    }
+
+    #[test]
+    fn streams_raw_output_per_sentence() {
+        let root = std::env::temp_dir().join(format!("piperdesk-stream-{}", Uuid::new_v4()));
+        // Two samples per sentence, written as each input line arrives.
+        let (engine, voice) = engine_with_script(
+            &root,
+            "#!/bin/sh\nwhile IFS= read -r line; do printf '\\001\\000\\002\\000'; done\n",
+        );
+        let chunks: Vec<WavAudio> = async_std::task::block_on(
+            engine
+                .synthesize_stream(&voice, "Primera frase. Segunda frase.", None, None)
+                .unwrap()
+                .try_collect(),
+        )
+        .unwrap();
+        assert!(chunks
+            .iter()
+            .all(|chunk| chunk.sample_rate == 16000 && chunk.channels == 1));
+        let samples: Vec<i16> = chunks.into_iter().flat_map(|chunk| chunk.samples).collect();
+        assert_eq!(samples, vec![1, 2, 1, 2]);
+
+        let (engine, voice) = engine_with_script(&root, "#!/bin/sh\necho 'modelo roto' >&2\nexit 3\n");
+        let err = async_std::task::block_on(
+            engine
+                .synthesize_stream(&voice, "Hola.", None, None)
+                .unwrap()
+                .try_collect::<Vec<_>>(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("modelo roto"), "{err}");
+        let _ = fs::remove_dir_all(&root);
+    }
+}
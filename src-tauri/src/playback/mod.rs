@@ -0,0 +1,577 @@
+//! Reads the synthesized queue aloud: completed items are played in queue
+//! order on a dedicated thread, with pause, seek, skip, volume and speed.
+//! Progress goes out as [`PlaybackEvent`]s.
+
+pub mod sink;
+pub mod stretch;
+
+use self::sink::AudioSink;
+use self::stretch::TimeStretch;
+use crate::audio::{read_wav, WavAudio};
+use crate::{bookmarks, ffmpeg, queue};
+use crate::{QueueItem, QueueStatus, SharedState};
+use anyhow::Context;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use uuid::Uuid;
+
+/// Audio handed to the sink at a time; commands are handled between blocks.
+const BLOCK_MS: usize = 100;
+const POSITION_INTERVAL: Duration = Duration::from_millis(250);
+/// "Previous" further into a track than this restarts it instead.
+const RESTART_THRESHOLD_MS: u64 = 3000;
+pub const MIN_SPEED: f32 = 0.5;
+pub const MAX_SPEED: f32 = 3.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PlayerState {
+    #[default]
+    Stopped,
+    Playing,
+    Paused,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaybackStatus {
+    pub state: PlayerState,
+    /// Queue item being played or paused.
+    pub item_id: Option<Uuid>,
+    pub position_ms: u64,
+    pub duration_ms: u64,
+    /// 0.0 to 1.0.
+    pub volume: f32,
+    /// Playback speed; the pitch of the voice is kept.
+    pub speed: f32,
+}
+
+impl Default for PlaybackStatus {
+    fn default() -> Self {
+        Self {
+            state: PlayerState::Stopped,
+            item_id: None,
+            position_ms: 0,
+            duration_ms: 0,
+            volume: 1.0,
+            speed: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "camelCase")]
+pub enum PlayerCommand {
+    /// Plays an item from `positionMs`, by default from where it was left;
+    /// without an item, resumes or starts at the first completed item.
+    Play {
+        #[serde(default, rename = "itemId")]
+        item_id: Option<Uuid>,
+        #[serde(default, rename = "positionMs")]
+        position_ms: Option<u64>,
+    },
+    Pause,
+    Resume,
+    Stop,
+    Seek {
+        #[serde(rename = "positionMs")]
+        position_ms: u64,
+    },
+    Next,
+    Previous,
+    SetVolume {
+        volume: f32,
+    },
+    SetSpeed {
+        speed: f32,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub enum PlaybackEvent {
+    /// Started, paused, stopped, seeked or changed track, volume or speed.
+    State(PlaybackStatus),
+    /// Sent a few times a second while playing.
+    Position(PlaybackStatus),
+    /// An item was played to its end.
+    Finished(Box<QueueItem>),
+    Failed {
+        id: Uuid,
+        error: String,
+    },
+}
+
+impl PlaybackEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PlaybackEvent::State(_) => "player::state",
+            PlaybackEvent::Position(_) => "player::position",
+            PlaybackEvent::Finished(_) => "player::finished",
+            PlaybackEvent::Failed { .. } => "player::failed",
+        }
+    }
+
+    pub fn payload(&self) -> serde_json::Value {
+        match self {
+            PlaybackEvent::State(status) | PlaybackEvent::Position(status) => {
+                serde_json::to_value(status).unwrap_or_default()
+            }
+            PlaybackEvent::Finished(item) => serde_json::to_value(item).unwrap_or_default(),
+            PlaybackEvent::Failed { id, error } => serde_json::json!([id, error]),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum PlaybackError {
+    #[error("the player is no longer running")]
+    Closed,
+}
+
+/// Handle to the playback thread, which exits when the handle is dropped.
+pub struct Player {
+    commands: Sender<PlayerCommand>,
+    status: Arc<Mutex<PlaybackStatus>>,
+}
+
+impl Player {
+    /// Starts the playback thread. `state` is whatever the host keeps the
+    /// shared state in, e.g. an `Arc<SharedState>`.
+    pub fn spawn<S, K>(state: S, sink: K, on_event: impl FnMut(PlaybackEvent) + Send + 'static) -> Self
+    where
+        S: AsRef<SharedState> + Send + 'static,
+        K: AudioSink + 'static,
+    {
+        let (commands, receiver) = mpsc::channel();
+        let core = PlayerCore::new(sink, on_event);
+        let status = core.shared.clone();
+        std::thread::Builder::new()
+            .name("playback".into())
+            .spawn(move || run(state, core, receiver))
+            .expect("failed to spawn the playback thread");
+        Self { commands, status }
+    }
+
+    pub fn send(&self, command: PlayerCommand) -> Result<(), PlaybackError> {
+        self.commands.send(command).map_err(|_| PlaybackError::Closed)
+    }
+
+    pub fn status(&self) -> PlaybackStatus {
+        self.status.lock().clone()
+    }
+}
+
+fn run<S, K>(state: S, mut core: PlayerCore<K>, commands: Receiver<PlayerCommand>)
+where
+    S: AsRef<SharedState>,
+    K: AudioSink,
+{
+    loop {
+        // Commands are handled before every block; without playback there
+        // is nothing to do but wait for one.
+        let command = if core.status.state == PlayerState::Playing {
+            match commands.try_recv() {
+                Ok(command) => Some(command),
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => break,
+            }
+        } else {
+            match commands.recv() {
+                Ok(command) => Some(command),
+                Err(_) => break,
+            }
+        };
+        match command {
+            Some(command) => core.handle(state.as_ref(), command),
+            None => core.play_block(state.as_ref()),
+        }
+    }
+    core.stop();
+}
+
+struct Track {
+    item: QueueItem,
+    audio: WavAudio,
+    /// Next source frame to play.
+    position: f64,
+}
+
+/// Playback state machine, driven by the thread in [`Player::spawn`].
+pub struct PlayerCore<K> {
+    sink: K,
+    on_event: Box<dyn FnMut(PlaybackEvent) + Send>,
+    status: PlaybackStatus,
+    shared: Arc<Mutex<PlaybackStatus>>,
+    track: Option<Track>,
+    stretch: Option<TimeStretch>,
+    last_position: Option<Instant>,
+}
+
+impl<K: AudioSink> PlayerCore<K> {
+    pub fn new(sink: K, on_event: impl FnMut(PlaybackEvent) + Send + 'static) -> Self {
+        Self {
+            sink,
+            on_event: Box::new(on_event),
+            status: PlaybackStatus::default(),
+            shared: Arc::new(Mutex::new(PlaybackStatus::default())),
+            track: None,
+            stretch: None,
+            last_position: None,
+        }
+    }
+
+    pub fn status(&self) -> &PlaybackStatus {
+        &self.status
+    }
+
+    pub fn handle(&mut self, state: &SharedState, command: PlayerCommand) {
+        match command {
+            PlayerCommand::Play {
+                item_id: Some(id),
+                position_ms,
+            } => match queue::get_item(state, id) {
+                Some(item) => {
+                    self.sink.clear();
+                    self.start(state, item);
+                    let position_ms = position_ms.unwrap_or_else(|| bookmarks::resume_position(state, id));
+                    if position_ms > 0 && self.track.as_ref().is_some_and(|track| track.item.id == id) {
+                        self.handle(state, PlayerCommand::Seek { position_ms });
+                    }
+                }
+                None => self.fail(id, "item is not in the queue".into()),
+            },
+            PlayerCommand::Play { item_id: None, .. } | PlayerCommand::Resume => {
+                if self.track.is_some() {
+                    self.set_state(PlayerState::Playing);
+                } else if let Some(item) = playable(state).into_iter().next() {
+                    self.start(state, item);
+                }
+            }
+            PlayerCommand::Pause => {
+                if self.status.state == PlayerState::Playing {
+                    self.rewind_pending();
+                    self.set_state(PlayerState::Paused);
+                    self.remember(state);
+                }
+            }
+            PlayerCommand::Stop => {
+                self.remember(state);
+                self.stop();
+            }
+            PlayerCommand::Seek { position_ms } => {
+                if let Some(track) = &mut self.track {
+                    let frame = position_ms as f64 * f64::from(track.audio.sample_rate) / 1000.0;
+                    track.position = frame.min(frame_count(&track.audio) as f64);
+                    self.sink.clear();
+                    self.stretch = None;
+                    self.publish_state();
+                    self.remember(state);
+                }
+            }
+            PlayerCommand::Next => {
+                if let Some(current) = self.track.as_ref().map(|track| track.item.id) {
+                    self.sink.clear();
+                    match neighbour(state, current, true) {
+                        Some(item) => self.start(state, item),
+                        None => self.stop(),
+                    }
+                }
+            }
+            PlayerCommand::Previous => {
+                if let Some(current) = self.track.as_ref().map(|track| track.item.id) {
+                    self.sink.clear();
+                    match neighbour(state, current, false) {
+                        Some(item) if self.heard_ms() < RESTART_THRESHOLD_MS => self.start(state, item),
+                        _ => self.handle(state, PlayerCommand::Seek { position_ms: 0 }),
+                    }
+                }
+            }
+            PlayerCommand::SetVolume { volume } => {
+                self.status.volume = volume.clamp(0.0, 1.0);
+                self.publish_state();
+            }
+            PlayerCommand::SetSpeed { speed } => {
+                self.status.speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+                self.stretch = None;
+                self.publish_state();
+            }
+        }
+    }
+
+    /// Sends the next block of the current track to the sink, moving on to
+    /// the next completed item at its end.
+    pub fn play_block(&mut self, state: &SharedState) {
+        let speed = f64::from(self.status.speed);
+        let Some(track) = &mut self.track else {
+            self.set_state(PlayerState::Stopped);
+            return;
+        };
+        let channels = usize::from(track.audio.channels.max(1));
+        let frames = frame_count(&track.audio);
+        let block = track.audio.sample_rate as usize * BLOCK_MS / 1000;
+        let mut samples = Vec::with_capacity(block * channels);
+        if (speed - 1.0).abs() < 1e-3 {
+            let start = (track.position as usize).min(frames);
+            let end = (start + block).min(frames);
+            samples.extend_from_slice(&track.audio.samples[start * channels..end * channels]);
+            track.position = end as f64;
+        } else {
+            let stretch = self
+                .stretch
+                .get_or_insert_with(|| TimeStretch::new(track.audio.sample_rate, track.audio.channels));
+            while samples.len() < block * channels && (track.position as usize) < frames {
+                samples.extend(stretch.next(&track.audio.samples, track.position as usize));
+                track.position = (track.position + stretch.hop() as f64 * speed).min(frames as f64);
+            }
+        }
+
+        if samples.is_empty() {
+            let finished = track.item.clone();
+            bookmarks::mark_finished(state, finished.id);
+            (self.on_event)(PlaybackEvent::Finished(Box::new(finished.clone())));
+            // Straight on to the next item, without dropping buffered audio.
+            match neighbour(state, finished.id, true) {
+                Some(item) => self.start(state, item),
+                None => self.stop(),
+            }
+            return;
+        }
+        if self.status.volume < 1.0 {
+            let volume = self.status.volume;
+            samples
+                .iter_mut()
+                .for_each(|sample| *sample = (f32::from(*sample) * volume) as i16);
+        }
+        let audio = WavAudio {
+            sample_rate: track.audio.sample_rate,
+            channels: track.audio.channels,
+            samples,
+        };
+        let id = track.item.id;
+        if let Err(err) = self.sink.write(&audio) {
+            self.fail(id, format!("{err:#}"));
+            self.stop();
+            return;
+        }
+        if self
+            .last_position
+            .is_none_or(|last| last.elapsed() >= POSITION_INTERVAL)
+        {
+            self.last_position = Some(Instant::now());
+            self.refresh_position();
+            (self.on_event)(PlaybackEvent::Position(self.status.clone()));
+            self.remember(state);
+        }
+    }
+
+    pub fn stop(&mut self) {
+        self.sink.clear();
+        if let Err(err) = self.sink.finish() {
+            log::warn!("failed to finish playback output: {err:#}");
+        }
+        self.track = None;
+        self.stretch = None;
+        self.set_state(PlayerState::Stopped);
+    }
+
+    fn start(&mut self, state: &SharedState, item: QueueItem) {
+        // Items without audio are skipped when the playlist moves on by itself.
+        let mut next = Some(item);
+        while let Some(item) = next.take() {
+            match load_audio(state, &item) {
+                Ok(audio) => {
+                    self.track = Some(Track {
+                        item,
+                        audio,
+                        position: 0.0,
+                    });
+                    self.stretch = None;
+                    self.set_state(PlayerState::Playing);
+                    return;
+                }
+                Err(err) => {
+                    self.fail(item.id, format!("{err:#}"));
+                    next = neighbour(state, item.id, true);
+                }
+            }
+        }
+        self.stop();
+    }
+
+    /// Moves the position back over audio the sink has buffered but not
+    /// played, so pausing resumes where the listener stopped hearing.
+    fn rewind_pending(&mut self) {
+        let pending = self.sink.pending();
+        self.sink.clear();
+        self.stretch = None;
+        if let Some(track) = &mut self.track {
+            let frames = pending.as_secs_f64() * f64::from(track.audio.sample_rate) * f64::from(self.status.speed);
+            track.position = (track.position - frames).max(0.0);
+        }
+    }
+
+    /// Records how far the current track has been heard, to resume there.
+    fn remember(&self, state: &SharedState) {
+        if let Some(track) = &self.track {
+            bookmarks::record_position(state, track.item.id, self.heard_ms());
+        }
+    }
+
+    fn heard_ms(&self) -> u64 {
+        let Some(track) = &self.track else {
+            return 0;
+        };
+        let pending =
+            self.sink.pending().as_secs_f64() * f64::from(track.audio.sample_rate) * f64::from(self.status.speed);
+        let frame = (track.position - pending).max(0.0);
+        (frame * 1000.0 / f64::from(track.audio.sample_rate.max(1))) as u64
+    }
+
+    fn refresh_position(&mut self) {
+        self.status.position_ms = self.heard_ms();
+        self.status.item_id = self.track.as_ref().map(|track| track.item.id);
+        self.status.duration_ms = self.track.as_ref().map_or(0, |track| track.audio.duration_ms());
+        *self.shared.lock() = self.status.clone();
+    }
+
+    fn set_state(&mut self, state: PlayerState) {
+        self.status.state = state;
+        self.publish_state();
+    }
+
+    fn publish_state(&mut self) {
+        self.refresh_position();
+        (self.on_event)(PlaybackEvent::State(self.status.clone()));
+    }
+
+    fn fail(&mut self, id: Uuid, error: String) {
+        log::warn!("playback of {id} failed: {error}");
+        (self.on_event)(PlaybackEvent::Failed { id, error });
+    }
+}
+
+fn frame_count(audio: &WavAudio) -> usize {
+    audio.samples.len() / usize::from(audio.channels.max(1))
+}
+
+/// Completed items with audio, in queue order.
+fn playable(state: &SharedState) -> Vec<QueueItem> {
+    queue::list(state)
+        .into_iter()
+        .filter(|item| item.status == QueueStatus::Completed && item.output.is_some())
+        .collect()
+}
+
+/// The playable item after (or before) `current` in the queue.
+fn neighbour(state: &SharedState, current: Uuid, forward: bool) -> Option<QueueItem> {
+    let items = queue::list(state);
+    let index = items.iter().position(|item| item.id == current)?;
+    let is_playable = |item: &&QueueItem| item.status == QueueStatus::Completed && item.output.is_some();
+    if forward {
+        items[index + 1..].iter().find(is_playable).cloned()
+    } else {
+        items[..index].iter().rev().find(is_playable).cloned()
+    }
+}
+
+fn load_audio(state: &SharedState, item: &QueueItem) -> anyhow::Result<WavAudio> {
+    let path = item.output.as_deref().context("item has not been synthesized yet")?;
+    let is_wav = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("wav"));
+    if is_wav {
+        return read_wav(path);
+    }
+    decode(state, path)
+}
+
+/// Reads exported MP3s and other formats through ffmpeg.
+fn decode(state: &SharedState, path: &Path) -> anyhow::Result<WavAudio> {
+    let config = state.config.lock().ffmpeg.clone();
+    let wav = std::env::temp_dir().join(format!("piperdesk-play-{}.wav", Uuid::new_v4()));
+    let result = async_std::task::block_on(ffmpeg::decode_to_wav(&config, path, &wav))
+        .map_err(anyhow::Error::from)
+        .and_then(|()| read_wav(&wav));
+    let _ = std::fs::remove_file(&wav);
+    result.with_context(|| format!("cannot play {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::sink::FileSink;
+    use super::*;
+    use crate::audio::write_wav;
+    use crate::queue::NewQueueItem;
+    use crate::AppConfig;
+
+    #[test]
+    fn plays_completed_items_in_queue_order() {
+        let root = std::env::temp_dir().join(format!("piperdesk-player-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&root).unwrap();
+        let state = SharedState::new(AppConfig::default()).unwrap();
+        let new_item = |title: &str| NewQueueItem {
+            title: title.into(),
+            text: title.into(),
+            voice: None,
+            rate: None,
+            format: None,
+        };
+        let items = queue::enqueue_text(&state, vec![new_item("uno"), new_item("pendiente"), new_item("dos")]).unwrap();
+        for (index, item) in [(0, &items[0]), (2, &items[2])] {
+            let mut audio = WavAudio::new(1000, 1);
+            audio.samples = vec![(index as i16 + 1) * 1000; 500];
+            let output = root.join(format!("{index}.wav"));
+            write_wav(&output, &audio).unwrap();
+            let mut queue = state.queue.lock();
+            let queued = queue.iter_mut().find(|queued| queued.id == item.id).unwrap();
+            queued.status = QueueStatus::Completed;
+            queued.output = Some(output);
+        }
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = events.clone();
+        let recording = root.join("played.wav");
+        let mut core = PlayerCore::new(FileSink::new(&recording), move |event| recorded.lock().push(event));
+        core.handle(&state, PlayerCommand::SetVolume { volume: 0.5 });
+        core.handle(
+            &state,
+            PlayerCommand::Play {
+                item_id: None,
+                position_ms: None,
+            },
+        );
+        assert_eq!(core.status().item_id, Some(items[0].id));
+        core.play_block(&state);
+        core.handle(&state, PlayerCommand::Seek { position_ms: 400 });
+        core.handle(&state, PlayerCommand::Pause);
+        assert_eq!(core.status().state, PlayerState::Paused);
+        core.handle(&state, PlayerCommand::Resume);
+        while core.status().state == PlayerState::Playing {
+            core.play_block(&state);
+        }
+
+        let finished: Vec<Uuid> = events
+            .lock()
+            .iter()
+            .filter_map(|event| match event {
+                PlaybackEvent::Finished(item) => Some(item.id),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(finished, vec![items[0].id, items[2].id]);
+        let heard = queue::get_item(&state, items[0].id).unwrap().listening.unwrap();
+        assert!(heard.finished);
+        assert_eq!(bookmarks::resume_position(&state, items[0].id), 0);
+        // 100 ms of the first item, its last 100 ms after the seek, then the
+        // whole second item, all at half volume.
+        let played = read_wav(&recording).unwrap();
+        let expected: Vec<i16> = [vec![500; 200], vec![1500; 500]].concat();
+        assert_eq!(played.samples, expected);
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}
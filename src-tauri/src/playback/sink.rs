@@ -0,0 +1,131 @@
+//! Where the player sends its audio. The desktop app hands it to the webview;
+//! [`NullSink`] and [`FileSink`] let the player run without a sound device.
+
+use crate::audio::{write_wav, WavAudio};
+use anyhow::Result;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+pub trait AudioSink: Send {
+    /// Plays `audio`, blocking while the output is far enough ahead of what
+    /// is being heard.
+    fn write(&mut self, audio: &WavAudio) -> Result<()>;
+
+    /// Audio written but not heard yet.
+    fn pending(&self) -> Duration {
+        Duration::ZERO
+    }
+
+    /// Drops audio that was written but not heard yet, on pause, seek and stop.
+    fn clear(&mut self) {}
+
+    /// Called when playback stops or the playlist ends.
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Keeps a sink at most `lead` ahead of real time, so a buffering output
+/// such as Web Audio always has something queued without running far ahead
+/// of pause and seek.
+#[derive(Debug, Clone)]
+pub struct Pacer {
+    lead: Duration,
+    /// When the audio written so far will have finished playing.
+    until: Option<Instant>,
+}
+
+impl Pacer {
+    pub fn new(lead: Duration) -> Self {
+        Self { lead, until: None }
+    }
+
+    /// Waits until `duration` more audio can be queued.
+    pub fn wait(&mut self, duration: Duration) {
+        let now = Instant::now();
+        let until = self.until.filter(|&until| until > now).unwrap_or(now);
+        if let Some(ahead) = (until - now).checked_sub(self.lead) {
+            std::thread::sleep(ahead);
+        }
+        self.until = Some(until + duration);
+    }
+
+    pub fn pending(&self) -> Duration {
+        self.until
+            .map_or(Duration::ZERO, |until| until.saturating_duration_since(Instant::now()))
+    }
+
+    pub fn reset(&mut self) {
+        self.until = None;
+    }
+}
+
+/// Discards audio, either at once or at the pace it would play.
+#[derive(Debug, Clone, Default)]
+pub struct NullSink {
+    pacer: Option<Pacer>,
+}
+
+impl NullSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Takes as long as playing the audio would.
+    pub fn realtime() -> Self {
+        Self {
+            pacer: Some(Pacer::new(Duration::ZERO)),
+        }
+    }
+}
+
+impl AudioSink for NullSink {
+    fn write(&mut self, audio: &WavAudio) -> Result<()> {
+        if let Some(pacer) = &mut self.pacer {
+            pacer.wait(Duration::from_millis(audio.duration_ms()));
+        }
+        Ok(())
+    }
+
+    fn pending(&self) -> Duration {
+        self.pacer.as_ref().map_or(Duration::ZERO, Pacer::pending)
+    }
+
+    fn clear(&mut self) {
+        if let Some(pacer) = &mut self.pacer {
+            pacer.reset();
+        }
+    }
+}
+
+/// Records everything played into one WAV file, written when playback
+/// finishes.
+#[derive(Debug, Clone)]
+pub struct FileSink {
+    path: PathBuf,
+    audio: Option<WavAudio>,
+}
+
+impl FileSink {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            audio: None,
+        }
+    }
+}
+
+impl AudioSink for FileSink {
+    fn write(&mut self, audio: &WavAudio) -> Result<()> {
+        self.audio
+            .get_or_insert_with(|| WavAudio::new(audio.sample_rate, audio.channels))
+            .append(audio)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        match &self.audio {
+            Some(audio) => write_wav(&self.path, audio),
+            None => Ok(()),
+        }
+    }
+}
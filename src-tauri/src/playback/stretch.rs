@@ -0,0 +1,140 @@
+//! Speed changes that keep the pitch of the voice (WSOLA): overlapping
+//! windows are taken from the source further apart or closer together than
+//! they are laid down, each shifted a little to line up with the waveform of
+//! the previous one.
+
+/// Output advance per window.
+const HOP_MS: u32 = 15;
+/// How far a window may move from its nominal place to line up.
+const TOLERANCE_MS: u32 = 8;
+
+pub struct TimeStretch {
+    channels: usize,
+    hop: usize,
+    tolerance: usize,
+    /// Hann window two hops long, so consecutive windows sum to one.
+    window: Vec<f32>,
+    /// Second half of the previous window, still to be overlapped.
+    tail: Vec<f32>,
+    /// Source frame the previous window started at.
+    previous: Option<usize>,
+}
+
+impl TimeStretch {
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        let hop = (sample_rate * HOP_MS / 1000).max(1) as usize;
+        let length = 2 * hop;
+        let window = (0..length)
+            .map(|i| 0.5 - 0.5 * (std::f32::consts::TAU * i as f32 / length as f32).cos())
+            .collect();
+        let channels = usize::from(channels.max(1));
+        Self {
+            channels,
+            hop,
+            tolerance: (sample_rate * TOLERANCE_MS / 1000) as usize,
+            window,
+            tail: vec![0.0; hop * channels],
+            previous: None,
+        }
+    }
+
+    /// Frames produced by every call to [`TimeStretch::next`].
+    pub fn hop(&self) -> usize {
+        self.hop
+    }
+
+    /// Starts over without overlapping the previous window, after a seek or
+    /// a speed change.
+    pub fn reset(&mut self) {
+        self.tail.iter_mut().for_each(|sample| *sample = 0.0);
+        self.previous = None;
+    }
+
+    /// The next `hop` frames of output, taken from interleaved `source` close
+    /// to frame `nominal`. Advancing `nominal` by `hop * speed` between calls
+    /// plays the source `speed` times faster.
+    pub fn next(&mut self, source: &[i16], nominal: usize) -> Vec<i16> {
+        let frames = source.len() / self.channels;
+        let start = match self.previous {
+            Some(previous) => self.align(source, frames, previous + self.hop, nominal),
+            None => nominal.min(frames),
+        };
+        let first = self.previous.is_none();
+        let channels = self.channels;
+        let at = |frame: usize, channel: usize| {
+            source
+                .get((start + frame) * channels + channel)
+                .map_or(0.0, |&sample| f32::from(sample))
+        };
+        let mut out = Vec::with_capacity(self.hop * channels);
+        for i in 0..self.hop {
+            for channel in 0..channels {
+                let head = at(i, channel);
+                let slot = i * channels + channel;
+                let mixed = if first {
+                    head
+                } else {
+                    self.tail[slot] + self.window[i] * head
+                };
+                out.push(mixed.round().clamp(f32::from(i16::MIN), f32::from(i16::MAX)) as i16);
+                self.tail[slot] = self.window[self.hop + i] * at(self.hop + i, channel);
+            }
+        }
+        self.previous = Some(start);
+        out
+    }
+
+    /// Start near `nominal` whose opening best matches the source right
+    /// after the previous window, compared on the first channel.
+    fn align(&self, source: &[i16], frames: usize, natural: usize, nominal: usize) -> usize {
+        let last = frames.saturating_sub(2 * self.hop);
+        let low = nominal.saturating_sub(self.tolerance).min(last);
+        let high = (nominal + self.tolerance).min(last);
+        if natural + self.hop > frames || low >= high {
+            return nominal.min(frames);
+        }
+        let sample = |frame: usize| f32::from(source[frame * self.channels]);
+        // Every other candidate and sample is plenty to find the alignment.
+        (low..=high)
+            .step_by(2)
+            .map(|candidate| {
+                let score: f32 = (0..self.hop)
+                    .step_by(2)
+                    .map(|i| sample(candidate + i) * sample(natural + i))
+                    .sum();
+                (candidate, score)
+            })
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map_or(nominal, |(candidate, _)| candidate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn zero_crossings(samples: &[i16]) -> usize {
+        samples.windows(2).filter(|pair| (pair[0] < 0) != (pair[1] < 0)).count()
+    }
+
+    #[test]
+    fn keeps_pitch_while_changing_speed() {
+        let rate = 16000;
+        let tone: Vec<i16> = (0..rate)
+            .map(|i| ((i as f32 * 220.0 * std::f32::consts::TAU / rate as f32).sin() * 8000.0) as i16)
+            .collect();
+        let mut stretch = TimeStretch::new(rate as u32, 1);
+        let speed = 2.0;
+        let mut out = Vec::new();
+        let mut position = 0.0;
+        while (position as usize) < tone.len() {
+            out.extend(stretch.next(&tone, position as usize));
+            position += stretch.hop() as f64 * speed;
+        }
+        let seconds = out.len() as f32 / rate as f32;
+        assert!((seconds - 0.5).abs() < 0.05, "{seconds}s");
+        // 220 Hz crosses zero 440 times a second, at any speed.
+        let per_second = zero_crossings(&out) as f32 / seconds;
+        assert!((per_second - 440.0).abs() < 30.0, "{per_second} crossings/s");
+    }
+}
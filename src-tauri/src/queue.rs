@@ -0,0 +1,852 @@
+use crate::audio::AudioFormat;
+use crate::bookmarks::Listening;
+use crate::captions::{write_captions, CaptionOptions};
+use crate::ffmpeg;
+use crate::importers::{self, ImportSettings, ImportedDocument};
+use crate::naming::{claim_unique_path, release_unused_path, FilenameTemplate, NameContext};
+use crate::retry::{FailureKind, ItemError};
+use crate::timing::{timing_path_for, TextSpan, TimingMap};
+use crate::tts::{SynthesisOutput, SynthesisRequest};
+use crate::{QueueItem, QueueStatus, SharedState};
+use async_std::channel::{Receiver, Sender, TrySendError};
+use chrono::{DateTime, Local, Utc};
+use futures::StreamExt;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use uuid::Uuid;
+
+/// Documents `import_folder` imports at the same time.
+const FOLDER_IMPORT_CONCURRENCY: usize = 4;
+/// Events a subscriber may have waiting before new ones are dropped for it.
+pub const EVENT_BACKLOG: usize = 256;
+
+#[derive(Debug, Error)]
+pub enum QueueError {
+    #[error("item {0} not found")]
+    NotFound(Uuid),
+    #[error("item {0} is {1:?} and can no longer be cancelled")]
+    NotCancellable(Uuid, QueueStatus),
+    #[error("item {0} is {1:?}; only failed items can be retried")]
+    NotRetryable(Uuid, QueueStatus),
+}
+
+/// Something that happened to the queue, delivered to the GUI as a Tauri
+/// event and to API clients as a server-sent event of the same name.
+#[derive(Debug, Clone)]
+pub enum QueueEvent {
+    /// Items were added, removed, cancelled or reordered.
+    Changed,
+    Completed(Box<QueueItem>),
+    Failed {
+        id: Uuid,
+        error: String,
+    },
+}
+
+impl QueueEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            QueueEvent::Changed => "queue::changed",
+            QueueEvent::Completed(_) => "queue::completed",
+            QueueEvent::Failed { .. } => "queue::failed",
+        }
+    }
+
+    pub fn payload(&self) -> serde_json::Value {
+        match self {
+            QueueEvent::Changed => serde_json::Value::Null,
+            QueueEvent::Completed(item) => serde_json::to_value(item).unwrap_or_default(),
+            QueueEvent::Failed { id, error } => serde_json::json!([id, error]),
+        }
+    }
+}
+
+/// Fans queue events out to every subscriber. Subscribers that fall more
+/// than [`EVENT_BACKLOG`] events behind miss the newest ones.
+#[derive(Debug, Default)]
+pub struct QueueEvents {
+    subscribers: Mutex<Vec<Sender<QueueEvent>>>,
+}
+
+impl QueueEvents {
+    pub fn subscribe(&self) -> Receiver<QueueEvent> {
+        let (sender, receiver) = async_std::channel::bounded(EVENT_BACKLOG);
+        self.subscribers.lock().push(sender);
+        receiver
+    }
+
+    pub fn publish(&self, event: QueueEvent) {
+        self.subscribers
+            .lock()
+            .retain(|sender| !matches!(sender.try_send(event.clone()), Err(TrySendError::Closed(_))));
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewQueueItem {
+    pub title: String,
+    pub text: String,
+    pub voice: Option<String>,
+    pub rate: Option<f32>,
+    pub format: Option<AudioFormat>,
+}
+
+pub fn enqueue_text(state: &SharedState, items: Vec<NewQueueItem>) -> Result<Vec<QueueItem>, String> {
+    let mut queue = state.queue.lock();
+    let mut created = Vec::new();
+    for item in items {
+        let id = Uuid::new_v4();
+        let queue_item = QueueItem {
+            id,
+            title: item.title.clone(),
+            text: item.text.clone(),
+            voice: item.voice.unwrap_or_else(|| state.tts.default_voice().to_string()),
+            rate: item.rate.unwrap_or(1.0),
+            format: item.format.unwrap_or(AudioFormat::Wav),
+            status: QueueStatus::Pending,
+            output: None,
+            timings: None,
+            project: None,
+            index: None,
+            listening: None,
+            attempts: 0,
+            last_attempt_at: None,
+            next_retry_at: None,
+            error: None,
+        };
+        queue.push_back(queue_item.clone());
+        created.push(queue_item);
+    }
+    drop(queue);
+    state.events.publish(QueueEvent::Changed);
+    Ok(created)
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct EnqueueDocumentOptions {
+    /// Project the chapters are grouped under; defaults to the document title.
+    pub project: Option<String>,
+    /// Overrides the voice picked from the document language.
+    pub voice: Option<String>,
+    pub rate: Option<f32>,
+    pub format: Option<AudioFormat>,
+}
+
+/// Queues one item per chapter of `document`, numbered in reading order.
+pub fn enqueue_document(
+    state: &SharedState,
+    document: ImportedDocument,
+    options: EnqueueDocumentOptions,
+) -> Result<Vec<QueueItem>, String> {
+    if document.chapters.is_empty() {
+        return Err("Document has no chapters".to_string());
+    }
+    let project = options
+        .project
+        .or(document.metadata.title)
+        .unwrap_or_else(|| "Document".to_string());
+    let voice = options
+        .voice
+        .or_else(|| {
+            let language = document.metadata.language.as_deref()?;
+            state.tts.voice_for_language(language).map(|voice| voice.name)
+        })
+        .unwrap_or_else(|| state.tts.default_voice().to_string());
+
+    let mut queue = state.queue.lock();
+    let mut created = Vec::new();
+    for (position, chapter) in document.chapters.into_iter().enumerate() {
+        let index = position + 1;
+        let queue_item = QueueItem {
+            id: Uuid::new_v4(),
+            title: chapter.title.unwrap_or_else(|| format!("{project} {index}")),
+            text: chapter.text,
+            voice: voice.clone(),
+            rate: options.rate.unwrap_or(1.0),
+            format: options.format.unwrap_or(AudioFormat::Wav),
+            status: QueueStatus::Pending,
+            output: None,
+            timings: None,
+            project: Some(project.clone()),
+            index: Some(index),
+            listening: None,
+            attempts: 0,
+            last_attempt_at: None,
+            next_retry_at: None,
+            error: None,
+        };
+        queue.push_back(queue_item.clone());
+        created.push(queue_item);
+    }
+    drop(queue);
+    state.events.publish(QueueEvent::Changed);
+    Ok(created)
+}
+
+pub fn remove_item(state: &SharedState, id: Uuid) {
+    let removed = {
+        let mut queue = state.queue.lock();
+        queue
+            .iter()
+            .position(|item| item.id == id)
+            .and_then(|pos| queue.remove(pos))
+    };
+    state.timings.lock().remove(&id);
+    state.listening.lock().forget_item(id);
+    if removed.is_some() {
+        state.events.publish(QueueEvent::Changed);
+    }
+}
+
+pub fn clear(state: &SharedState) {
+    state.queue.lock().clear();
+    state.timings.lock().clear();
+    *state.listening.lock() = Listening::default();
+    state.events.publish(QueueEvent::Changed);
+}
+
+pub fn list(state: &SharedState) -> Vec<QueueItem> {
+    state.queue.lock().iter().cloned().collect()
+}
+
+pub fn get_item(state: &SharedState, id: Uuid) -> Option<QueueItem> {
+    state.queue.lock().iter().find(|item| item.id == id).cloned()
+}
+
+/// Marks a pending item, also one waiting for a retry, as cancelled so it is
+/// skipped; items already being synthesized run to completion.
+pub fn cancel_item(state: &SharedState, id: Uuid) -> Result<QueueItem, QueueError> {
+    let cancelled = {
+        let mut queue = state.queue.lock();
+        let item = queue
+            .iter_mut()
+            .find(|item| item.id == id)
+            .ok_or(QueueError::NotFound(id))?;
+        match item.status {
+            QueueStatus::Pending => {
+                item.status = QueueStatus::Cancelled;
+                item.next_retry_at = None;
+            }
+            QueueStatus::Cancelled => return Ok(item.clone()),
+            ref status => return Err(QueueError::NotCancellable(id, status.clone())),
+        }
+        item.clone()
+    };
+    state.events.publish(QueueEvent::Changed);
+    Ok(cancelled)
+}
+
+/// Moves an item to `position` (0-based, clamped to the end of the queue).
+pub fn move_item(state: &SharedState, id: Uuid, position: usize) -> Result<(), QueueError> {
+    {
+        let mut queue = state.queue.lock();
+        let from = queue
+            .iter()
+            .position(|item| item.id == id)
+            .ok_or(QueueError::NotFound(id))?;
+        let item = queue.remove(from).ok_or(QueueError::NotFound(id))?;
+        let to = position.min(queue.len());
+        queue.insert(to, item);
+    }
+    state.events.publish(QueueEvent::Changed);
+    Ok(())
+}
+
+/// Marks the first pending item as processing and returns it. Items waiting
+/// for a retry are skipped until it is due.
+pub fn claim_next(state: &SharedState) -> Option<QueueItem> {
+    let now = Utc::now();
+    let mut queue = state.queue.lock();
+    let item = queue.iter_mut().find(|item| is_due(item, now))?;
+    item.status = QueueStatus::Processing;
+    Some(item.clone())
+}
+
+/// Marks the queue item `id` as processing if it is still pending and not
+/// waiting for a retry, so the caller can synthesize it without racing
+/// `claim_next`.
+pub fn claim_pending(state: &SharedState, id: Uuid) -> Option<QueueItem> {
+    let now = Utc::now();
+    let mut queue = state.queue.lock();
+    let item = queue.iter_mut().find(|item| item.id == id && is_due(item, now))?;
+    item.status = QueueStatus::Processing;
+    Some(item.clone())
+}
+
+fn is_due(item: &QueueItem, now: DateTime<Utc>) -> bool {
+    item.status == QueueStatus::Pending && item.next_retry_at.is_none_or(|at| at <= now)
+}
+
+/// When the pending item `id` is retried, if it is waiting for a retry.
+pub fn retry_scheduled(state: &SharedState, id: Uuid) -> Option<DateTime<Utc>> {
+    get_item(state, id)
+        .filter(|item| item.status == QueueStatus::Pending)
+        .and_then(|item| item.next_retry_at)
+}
+
+/// The earliest retry any pending item is waiting for.
+pub fn next_retry_at(state: &SharedState) -> Option<DateTime<Utc>> {
+    state
+        .queue
+        .lock()
+        .iter()
+        .filter(|item| item.status == QueueStatus::Pending)
+        .filter_map(|item| item.next_retry_at)
+        .min()
+}
+
+/// Marks the first item whose automatic retry is due as processing, unless
+/// its caller waits out the retry itself (see
+/// [`SharedState::awaited_retries`]).
+pub fn claim_due_retry(state: &SharedState) -> Option<QueueItem> {
+    let now = Utc::now();
+    let awaited = state.awaited_retries.lock();
+    let mut queue = state.queue.lock();
+    let item = queue
+        .iter_mut()
+        .find(|item| item.next_retry_at.is_some() && is_due(item, now) && !awaited.contains(&item.id))?;
+    item.status = QueueStatus::Processing;
+    Some(item.clone())
+}
+
+/// Runs automatic retries into the export folder as they come due, for
+/// hosts where nothing else synthesizes the queue unattended. Items that
+/// were never attempted are left for `synthesize_next`. Runs until the
+/// queue's event channel closes.
+pub async fn run_due_retries(state: &SharedState) {
+    let events = state.events.subscribe();
+    loop {
+        while let Some(item) = claim_due_retry(state) {
+            let export_dir = state.config.lock().export_dir.clone();
+            if let Err(err) = run_item(state, item, &export_dir).await {
+                log::warn!("automatic retry failed: {err}");
+            }
+        }
+        // Woken early by queue events, since a retry may have been
+        // scheduled, cancelled or run by someone else meanwhile.
+        let next = {
+            let awaited = state.awaited_retries.lock();
+            state
+                .queue
+                .lock()
+                .iter()
+                .filter(|item| item.status == QueueStatus::Pending && !awaited.contains(&item.id))
+                .filter_map(|item| item.next_retry_at)
+                .min()
+        };
+        match next {
+            Some(at) => {
+                let wait = (at - Utc::now()).to_std().unwrap_or_default();
+                if let Ok(Err(_)) = async_std::future::timeout(wait, events.recv()).await {
+                    return;
+                }
+            }
+            None => {
+                if events.recv().await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Synthesizes a claimed item into `directory`, records the outcome in the
+/// queue and publishes `queue::completed` or `queue::failed`. After a
+/// transient failure the item is pending again, with `next_retry_at` set
+/// after a growing delay (see [`crate::retry`]), and `queue::changed` is
+/// published instead; once attempts run out, or for any other failure, the
+/// item is left as `Failed` with the error.
+pub async fn run_item(state: &SharedState, mut item: QueueItem, directory: &Path) -> Result<QueueItem, String> {
+    update_item(state, &mut item, |item| {
+        item.attempts += 1;
+        item.last_attempt_at = Some(Utc::now());
+        item.next_retry_at = None;
+    });
+    let result = synthesize_item(state, &item, directory, item.voice.clone(), item.rate, item.format)
+        .await
+        .and_then(|output| store_timings(state, item.id, output));
+
+    match result {
+        Ok((path, timings)) => {
+            update_item(state, &mut item, |item| {
+                item.status = QueueStatus::Completed;
+                item.output = Some(path.clone());
+                item.timings = Some(timings.clone());
+                item.error = None;
+            });
+            state.events.publish(QueueEvent::Completed(Box::new(item.clone())));
+            Ok(item)
+        }
+        Err(err) => {
+            let retry = state.config.lock().retry.clone();
+            if let Some(delay) = retry.delay(item.attempts, err.kind) {
+                log::warn!("'{}' failed ({:?}), retrying in {delay:?}: {err}", item.title, err.kind);
+                let retry_at =
+                    Utc::now() + chrono::Duration::from_std(delay).unwrap_or_else(|_| chrono::Duration::zero());
+                update_item(state, &mut item, |item| {
+                    item.status = QueueStatus::Pending;
+                    item.next_retry_at = Some(retry_at);
+                    item.error = Some(err.clone());
+                });
+                state.events.publish(QueueEvent::Changed);
+                let local = retry_at.with_timezone(&Local).format("%H:%M:%S");
+                return Err(format!("{err} (retrying at {local})"));
+            }
+            let message = err.message.clone();
+            update_item(state, &mut item, |item| {
+                item.status = QueueStatus::Failed;
+                item.error = Some(err.clone());
+            });
+            state.events.publish(QueueEvent::Failed {
+                id: item.id,
+                error: message.clone(),
+            });
+            Err(message)
+        }
+    }
+}
+
+/// Applies `change` to `item` and to its copy in the queue, if it is still
+/// queued.
+fn update_item(state: &SharedState, item: &mut QueueItem, change: impl Fn(&mut QueueItem)) {
+    if let Some(stored) = state.queue.lock().iter_mut().find(|stored| stored.id == item.id) {
+        change(stored);
+    }
+    change(item);
+}
+
+/// Queues a failed item again with a fresh round of automatic retries. The
+/// last error and attempt time are kept until it is synthesized.
+pub fn retry_item(state: &SharedState, id: Uuid) -> Result<QueueItem, QueueError> {
+    let retried = {
+        let mut queue = state.queue.lock();
+        let item = queue
+            .iter_mut()
+            .find(|item| item.id == id)
+            .ok_or(QueueError::NotFound(id))?;
+        if item.status != QueueStatus::Failed {
+            return Err(QueueError::NotRetryable(id, item.status.clone()));
+        }
+        item.status = QueueStatus::Pending;
+        item.attempts = 0;
+        item.clone()
+    };
+    state.events.publish(QueueEvent::Changed);
+    Ok(retried)
+}
+
+/// Queues every failed item again and returns them.
+pub fn retry_failed(state: &SharedState) -> Vec<QueueItem> {
+    let retried: Vec<QueueItem> = state
+        .queue
+        .lock()
+        .iter_mut()
+        .filter(|item| item.status == QueueStatus::Failed)
+        .map(|item| {
+            item.status = QueueStatus::Pending;
+            item.attempts = 0;
+            item.clone()
+        })
+        .collect();
+    if !retried.is_empty() {
+        state.events.publish(QueueEvent::Changed);
+    }
+    retried
+}
+
+/// Synthesizes the oldest pending item into the export directory.
+pub async fn synthesize_next(state: &SharedState) -> Result<QueueItem, String> {
+    let Some(item) = claim_next(state) else {
+        return Err(match next_retry_at(state) {
+            Some(at) => format!("No pending items until {}", at.with_timezone(&Local).format("%H:%M:%S")),
+            None => "No pending items".to_string(),
+        });
+    };
+    let export_dir = state.config.lock().export_dir.clone();
+    run_item(state, item, &export_dir).await
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportOptions {
+    pub item_id: Uuid,
+    pub format: AudioFormat,
+    pub speed: Option<f32>,
+    pub voice: Option<String>,
+    pub directory: Option<PathBuf>,
+    /// Also write SRT/WebVTT captions and a JSON timing map next to the audio.
+    #[serde(default)]
+    pub captions: Option<CaptionOptions>,
+}
+
+/// Synthesizes a queued item again with the export settings, without changing
+/// its status.
+pub async fn export_item(state: &SharedState, options: ExportOptions) -> Result<PathBuf, String> {
+    let item = {
+        let queue = state.queue.lock();
+        queue.iter().find(|item| item.id == options.item_id).cloned()
+    }
+    .ok_or_else(|| "Item not found".to_string())?;
+
+    let directory = options.directory.unwrap_or_else(|| {
+        let config = state.config.lock();
+        config.export_dir.clone()
+    });
+    let format = options.format;
+    let voice = options.voice.unwrap_or_else(|| item.voice.clone());
+    let speed = options.speed.unwrap_or(item.rate);
+    let output = synthesize_item(state, &item, &directory, voice, speed, format)
+        .await
+        .map_err(|err| err.message)?;
+    if let Some(captions) = &options.captions {
+        write_captions(&output.path, &output.timings, captions).map_err(|err| err.to_string())?;
+    }
+    let (path, timings) = store_timings(state, options.item_id, output).map_err(|err| err.message)?;
+    if let Some(stored) = state
+        .queue
+        .lock()
+        .iter_mut()
+        .find(|existing| existing.id == options.item_id)
+    {
+        stored.output = Some(path.clone());
+        stored.timings = Some(timings);
+        stored.format = format;
+    }
+    Ok(path)
+}
+
+/// Renders the configured filename template for `item` and claims a
+/// collision-free path for it inside `directory`.
+fn claim_output_path(
+    state: &SharedState,
+    item: &QueueItem,
+    directory: &Path,
+    voice: &str,
+    format: AudioFormat,
+) -> Result<PathBuf, ItemError> {
+    let template = {
+        let config = state.config.lock();
+        FilenameTemplate::parse(&config.filename_template)
+            .map_err(|err| ItemError::new(FailureKind::Other, err.to_string()))?
+    };
+    let index = item.index.or_else(|| {
+        let queue = state.queue.lock();
+        queue.iter().position(|queued| queued.id == item.id).map(|pos| pos + 1)
+    });
+    let relative = template.render(&NameContext {
+        title: &item.title,
+        voice,
+        project: item.project.as_deref(),
+        index,
+        id: item.id,
+        ext: format.extension(),
+        now: Local::now(),
+    });
+    claim_unique_path(&directory.join(relative)).map_err(|err| ItemError::new(FailureKind::Io, err.to_string()))
+}
+
+async fn synthesize_item(
+    state: &SharedState,
+    item: &QueueItem,
+    directory: &Path,
+    voice: String,
+    speed: f32,
+    format: AudioFormat,
+) -> Result<SynthesisOutput, ItemError> {
+    let output = claim_output_path(state, item, directory, &voice, format)?;
+    let result = synthesize_to(state, &item.text, voice, speed, format, &output).await;
+    if result.is_err() {
+        release_unused_path(&output);
+    }
+    result
+}
+
+/// Runs Piper into `output`, going through a temporary WAV and ffmpeg when
+/// `format` is MP3.
+pub async fn synthesize_to(
+    state: &SharedState,
+    text: &str,
+    voice: String,
+    speed: f32,
+    format: AudioFormat,
+    output: &Path,
+) -> Result<SynthesisOutput, ItemError> {
+    let wav_output = match format {
+        AudioFormat::Wav => output.to_path_buf(),
+        AudioFormat::Mp3 => std::env::temp_dir().join(format!("piperdesk-{}.wav", Uuid::new_v4())),
+    };
+    let request = SynthesisRequest {
+        text: text.to_string(),
+        voice: Some(voice),
+        output: wav_output.clone(),
+        speed: Some(speed),
+        speaker: None,
+    };
+    let mut synthesized = state.tts.synthesize(request).await?;
+    if format == AudioFormat::Mp3 {
+        let ffmpeg = state.config.lock().ffmpeg.clone();
+        let result = ffmpeg::transcode_wav_to_mp3(&ffmpeg, &wav_output, output).await;
+        let _ = std::fs::remove_file(&wav_output);
+        result.map_err(|err| ItemError::new(FailureKind::of_ffmpeg(&err), format!("MP3 export failed: {err}")))?;
+        synthesized.path = output.to_path_buf();
+    }
+    Ok(synthesized)
+}
+
+/// Writes the timing map next to the synthesized audio and caches it for
+/// read-along lookups. Returns the audio path and the timing map path.
+fn store_timings(state: &SharedState, id: Uuid, output: SynthesisOutput) -> Result<(PathBuf, PathBuf), ItemError> {
+    let timings_path = timing_path_for(&output.path);
+    output.timings.save(&timings_path)?;
+    state.timings.lock().insert(id, output.timings);
+    Ok((output.path, timings_path))
+}
+
+fn with_timing_map<T>(state: &SharedState, id: Uuid, f: impl FnOnce(&TimingMap) -> T) -> Result<T, String> {
+    if let Some(map) = state.timings.lock().get(&id) {
+        return Ok(f(map));
+    }
+    let path = {
+        let queue = state.queue.lock();
+        let item = queue
+            .iter()
+            .find(|item| item.id == id)
+            .ok_or_else(|| "Item not found".to_string())?;
+        item.timings.clone()
+    }
+    .ok_or_else(|| "Item has no timing map".to_string())?;
+    let map = TimingMap::load(&path).map_err(|err| err.to_string())?;
+    let result = f(&map);
+    state.timings.lock().insert(id, map);
+    Ok(result)
+}
+
+pub fn text_span(state: &SharedState, item_id: Uuid, position_ms: u64) -> Result<Option<TextSpan>, String> {
+    with_timing_map(state, item_id, |map| map.span_at(position_ms))
+}
+
+pub fn timing_map(state: &SharedState, item_id: Uuid) -> Result<TimingMap, String> {
+    with_timing_map(state, item_id, TimingMap::clone)
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ImportFolderOptions {
+    /// Glob patterns relative to the folder, e.g. `**/*.pdf`. Empty takes
+    /// every supported file.
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub voice: Option<String>,
+    pub rate: Option<f32>,
+    pub format: Option<AudioFormat>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileImportResult {
+    pub path: PathBuf,
+    /// Queue items created for the file, one per chapter.
+    pub queued: usize,
+    pub warnings: Vec<String>,
+    pub error: Option<String>,
+}
+
+/// Imports every supported document below `root` and queues them in natural
+/// order of their paths. A file that fails to import is reported and does not
+/// stop the others.
+pub async fn import_folder(
+    state: &SharedState,
+    root: &Path,
+    options: &ImportFolderOptions,
+    settings: &ImportSettings,
+) -> Result<Vec<FileImportResult>, String> {
+    let files =
+        importers::folder::scan_folder(root, &options.include, &options.exclude).map_err(|err| err.to_string())?;
+    // `buffered` yields results in input order, so queue order stays natural.
+    let mut imports = futures::stream::iter(files)
+        .map(|file| async move {
+            let result = importers::import_file(&file, settings).await;
+            (file, result)
+        })
+        .buffered(FOLDER_IMPORT_CONCURRENCY);
+    let mut report = Vec::new();
+    while let Some((file, result)) = imports.next().await {
+        let result = result.and_then(|document| {
+            let warnings = document.warnings.clone();
+            let enqueue_options = EnqueueDocumentOptions {
+                project: None,
+                voice: options.voice.clone(),
+                rate: options.rate,
+                format: options.format,
+            };
+            enqueue_document(state, document, enqueue_options).map(|items| (items.len(), warnings))
+        });
+        report.push(match result {
+            Ok((queued, warnings)) => FileImportResult {
+                path: file,
+                queued,
+                warnings,
+                error: None,
+            },
+            Err(error) => {
+                log::warn!("failed to import {}: {error}", file.display());
+                FileImportResult {
+                    path: file,
+                    queued: 0,
+                    warnings: Vec::new(),
+                    error: Some(error),
+                }
+            }
+        });
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AppConfig;
+
+    fn state() -> SharedState {
+        SharedState::new(AppConfig::default()).expect("state")
+    }
+
+    #[test]
+    fn enqueue_and_remove_items() {
+        let state = state();
+        let items = enqueue_text(
+            &state,
+            vec![NewQueueItem {
+                title: "Sample".into(),
+                text: "Hello".into(),
+                voice: None,
+                rate: Some(1.0),
+                format: None,
+            }],
+        )
+        .expect("enqueue");
+        assert_eq!(items.len(), 1);
+        assert_eq!(state.queue.lock().len(), 1);
+        remove_item(&state, items[0].id);
+        assert!(state.queue.lock().is_empty());
+    }
+
+    #[test]
+    fn clear_queue_removes_all() {
+        let state = state();
+        enqueue_text(
+            &state,
+            vec![
+                NewQueueItem {
+                    title: "One".into(),
+                    text: "A".into(),
+                    voice: None,
+                    rate: None,
+                    format: None,
+                },
+                NewQueueItem {
+                    title: "Two".into(),
+                    text: "B".into(),
+                    voice: None,
+                    rate: None,
+                    format: None,
+                },
+            ],
+        )
+        .expect("enqueue");
+        assert_eq!(state.queue.lock().len(), 2);
+        clear(&state);
+        assert!(state.queue.lock().is_empty());
+    }
+
+    #[test]
+    fn claims_pending_items_oldest_first() {
+        let state = state();
+        let new_item = |title: &str| NewQueueItem {
+            title: title.into(),
+            text: "A".into(),
+            voice: None,
+            rate: None,
+            format: None,
+        };
+        let items = enqueue_text(&state, vec![new_item("One"), new_item("Two"), new_item("Three")]).expect("enqueue");
+        cancel_item(&state, items[1].id).expect("cancel");
+        assert_eq!(claim_next(&state).map(|item| item.title), Some("One".to_string()));
+        assert_eq!(claim_next(&state).map(|item| item.title), Some("Three".to_string()));
+        assert!(claim_next(&state).is_none());
+        assert!(claim_pending(&state, items[0].id).is_none());
+    }
+
+    #[test]
+    fn enqueue_document_creates_item_per_chapter() {
+        let state = state();
+        let document = ImportedDocument {
+            metadata: importers::DocumentMetadata {
+                title: Some("Libro".into()),
+                ..Default::default()
+            },
+            chapters: vec![
+                importers::Chapter {
+                    title: Some("Prólogo".into()),
+                    text: "Uno".into(),
+                },
+                importers::Chapter {
+                    title: None,
+                    text: "Dos".into(),
+                },
+            ],
+            ..Default::default()
+        };
+        let items = enqueue_document(&state, document, EnqueueDocumentOptions::default()).expect("enqueue");
+        let summary: Vec<_> = items
+            .iter()
+            .map(|item| (item.title.as_str(), item.project.as_deref(), item.index))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![("Prólogo", Some("Libro"), Some(1)), ("Libro 2", Some("Libro"), Some(2))]
+        );
+        assert_eq!(state.queue.lock().len(), 2);
+        assert!(enqueue_document(&state, ImportedDocument::default(), EnqueueDocumentOptions::default()).is_err());
+    }
+
+    #[test]
+    fn cancels_and_reorders_items_with_events() {
+        let state = state();
+        let events = state.events.subscribe();
+        let new = |title: &str| NewQueueItem {
+            title: title.into(),
+            text: "Texto".into(),
+            voice: None,
+            rate: None,
+            format: None,
+        };
+        let items = enqueue_text(&state, vec![new("Uno"), new("Dos"), new("Tres")]).expect("enqueue");
+        move_item(&state, items[2].id, 0).expect("move");
+        let titles: Vec<_> = list(&state).into_iter().map(|item| item.title).collect();
+        assert_eq!(titles, vec!["Tres", "Uno", "Dos"]);
+
+        assert_eq!(
+            cancel_item(&state, items[2].id).expect("cancel").status,
+            QueueStatus::Cancelled
+        );
+        assert_eq!(claim_next(&state).map(|item| item.title), Some("Uno".to_string()));
+        assert!(matches!(
+            cancel_item(&state, items[0].id),
+            Err(QueueError::NotCancellable(_, QueueStatus::Processing))
+        ));
+        assert!(matches!(
+            move_item(&state, Uuid::new_v4(), 0),
+            Err(QueueError::NotFound(_))
+        ));
+
+        let names: Vec<_> = std::iter::from_fn(|| events.try_recv().ok())
+            .map(|event| event.name())
+            .collect();
+        assert_eq!(names, vec!["queue::changed"; 3]);
+    }
+}
@@ -0,0 +1,194 @@
+//! Why a queue item failed, and how often and how soon failures that may
+//! go away on their own are retried.
+
+use crate::espeak::EspeakError;
+use crate::ffmpeg::FfmpegError;
+use crate::piper::PiperError;
+use crate::tts::TtsError;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FailureKind {
+    /// The item's voice is not installed.
+    VoiceMissing,
+    /// Piper, espeak-ng or ffmpeg could not be found or started.
+    BinaryMissing,
+    /// The synthesizer exited with an error.
+    ProcessCrash,
+    Timeout,
+    /// ffmpeg could not convert the audio to MP3.
+    Transcode,
+    /// Reading or writing files failed.
+    Io,
+    Other,
+}
+
+impl FailureKind {
+    /// Failures worth trying again without changing anything.
+    pub fn is_transient(self) -> bool {
+        matches!(self, FailureKind::ProcessCrash | FailureKind::Timeout | FailureKind::Io)
+    }
+
+    /// Classifies the first error in `err`'s chain that is recognised.
+    pub fn of(err: &anyhow::Error) -> Self {
+        err.chain().find_map(Self::recognise).unwrap_or(FailureKind::Other)
+    }
+
+    fn recognise(err: &(dyn std::error::Error + 'static)) -> Option<Self> {
+        if let Some(err) = err.downcast_ref::<TtsError>() {
+            return Some(match err {
+                TtsError::VoiceNotFound(_) => FailureKind::VoiceMissing,
+            });
+        }
+        if let Some(err) = err.downcast_ref::<PiperError>() {
+            return Some(match err {
+                PiperError::VoiceNotFound(_) => FailureKind::VoiceMissing,
+                PiperError::InvalidBinary(_) | PiperError::Spawn(_) => FailureKind::BinaryMissing,
+                PiperError::CommandFailed(_) => FailureKind::ProcessCrash,
+                PiperError::TimedOut(_) => FailureKind::Timeout,
+            });
+        }
+        if let Some(err) = err.downcast_ref::<EspeakError>() {
+            return Some(match err {
+                EspeakError::Spawn(_) => FailureKind::BinaryMissing,
+                EspeakError::CommandFailed(_) => FailureKind::ProcessCrash,
+                EspeakError::TimedOut(_) => FailureKind::Timeout,
+            });
+        }
+        if let Some(err) = err.downcast_ref::<FfmpegError>() {
+            return Some(Self::of_ffmpeg(err));
+        }
+        err.is::<std::io::Error>().then_some(FailureKind::Io)
+    }
+
+    pub fn of_ffmpeg(err: &FfmpegError) -> Self {
+        match err {
+            FfmpegError::NotFound(_) | FfmpegError::Spawn { .. } => FailureKind::BinaryMissing,
+            FfmpegError::TimedOut(_) => FailureKind::Timeout,
+            FfmpegError::Failed { .. } | FfmpegError::UnknownVersion(_) => FailureKind::Transcode,
+        }
+    }
+}
+
+/// The last failure of a queue item.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemError {
+    pub kind: FailureKind,
+    pub message: String,
+    pub at: DateTime<Utc>,
+}
+
+impl ItemError {
+    pub fn new(kind: FailureKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            at: Utc::now(),
+        }
+    }
+}
+
+impl From<anyhow::Error> for ItemError {
+    fn from(err: anyhow::Error) -> Self {
+        Self::new(FailureKind::of(&err), err.to_string())
+    }
+}
+
+impl fmt::Display for ItemError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for ItemError {}
+
+/// Retries of transient failures, waiting twice as long before each one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RetryConfig {
+    /// Attempts in total, the first one included; 1 disables retries.
+    pub max_attempts: u32,
+    pub initial_delay_ms: u64,
+    pub max_delay_ms: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_delay_ms: 2_000,
+            max_delay_ms: 60_000,
+        }
+    }
+}
+
+impl RetryConfig {
+    /// How long to wait after `attempt` (1-based) failed, or `None` if the
+    /// failure is not retried.
+    pub fn delay(&self, attempt: u32, kind: FailureKind) -> Option<Duration> {
+        if !kind.is_transient() || attempt >= self.max_attempts {
+            return None;
+        }
+        let factor = 1u64.checked_shl(attempt - 1).unwrap_or(u64::MAX);
+        let delay = self.initial_delay_ms.saturating_mul(factor).min(self.max_delay_ms);
+        Some(Duration::from_millis(delay))
+    }
+}
+
+/// Waits until `at`, for callers that synthesize an item again once its
+/// retry is due.
+pub async fn sleep_until(at: DateTime<Utc>) {
+    if let Ok(wait) = (at - Utc::now()).to_std() {
+        async_std::task::sleep(wait).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn classifies_errors_through_their_context() {
+        let spawn = anyhow::Error::new(PiperError::Spawn(std::io::ErrorKind::NotFound.into()));
+        assert_eq!(FailureKind::of(&spawn), FailureKind::BinaryMissing);
+        let crash = anyhow::Error::new(EspeakError::CommandFailed("exit status: 1".into())).context("sentence 3");
+        assert_eq!(FailureKind::of(&crash), FailureKind::ProcessCrash);
+        let io = Err::<(), _>(std::io::Error::other("disk full"))
+            .context("write WAV")
+            .unwrap_err();
+        assert_eq!(FailureKind::of(&io), FailureKind::Io);
+        assert_eq!(
+            FailureKind::of(&TtsError::VoiceNotFound("x".into()).into()),
+            FailureKind::VoiceMissing
+        );
+        assert_eq!(FailureKind::of(&anyhow::anyhow!("no text")), FailureKind::Other);
+        let hung = anyhow::Error::new(PiperError::TimedOut(900)).context("sentence 1");
+        assert_eq!(FailureKind::of(&hung), FailureKind::Timeout);
+        assert_eq!(FailureKind::of(&EspeakError::TimedOut(60).into()), FailureKind::Timeout);
+        assert_eq!(
+            FailureKind::of_ffmpeg(&FfmpegError::TimedOut(300)),
+            FailureKind::Timeout
+        );
+    }
+
+    #[test]
+    fn backs_off_exponentially_up_to_a_limit() {
+        let config = RetryConfig {
+            max_attempts: 5,
+            initial_delay_ms: 1_000,
+            max_delay_ms: 5_000,
+        };
+        let delays: Vec<_> = (1..=5)
+            .map(|attempt| config.delay(attempt, FailureKind::Timeout))
+            .collect();
+        let seconds = |secs| Some(Duration::from_secs(secs));
+        assert_eq!(delays, [seconds(1), seconds(2), seconds(4), seconds(5), None]);
+        assert_eq!(config.delay(1, FailureKind::VoiceMissing), None);
+    }
+}
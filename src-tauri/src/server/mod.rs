@@ -0,0 +1,163 @@
+//! Local HTTP API for tools that cannot use the Tauri commands.
+
+pub mod openai;
+pub mod queue;
+
+use crate::SharedState;
+use futures::future::{BoxFuture, FutureExt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr};
+use thiserror::Error;
+use tide::http::mime;
+use tide::listener::Listener;
+use tide::{Middleware, Next, Request, Response, StatusCode};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ServerConfig {
+    pub enabled: bool,
+    /// Port on 127.0.0.1; the server never listens on other interfaces.
+    pub port: u16,
+    /// Bearer token every request must present.
+    pub token: String,
+    /// OpenAI voice names such as `alloy` mapped to installed voices.
+    pub voice_aliases: HashMap<String, String>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        let token = std::env::var("PIPERDESK_API_TOKEN").unwrap_or_else(|_| Uuid::new_v4().simple().to_string());
+        Self {
+            enabled: false,
+            port: 8787,
+            token,
+            voice_aliases: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ServerError {
+    #[error("an API token is required")]
+    MissingToken,
+    #[error("failed to listen on {0}: {1}")]
+    Listen(SocketAddr, std::io::Error),
+    #[error("API server failed: {0}")]
+    Serve(std::io::Error),
+}
+
+/// Binds the API to `127.0.0.1:<port>` and returns the future that serves it
+/// until it is dropped or cancelled. Binding up front lets callers report a
+/// port that is already taken.
+///
+/// `state` is whatever the host keeps the shared state in, e.g. an
+/// `Arc<SharedState>`.
+pub async fn bind<S>(
+    state: S,
+    config: &ServerConfig,
+) -> Result<BoxFuture<'static, Result<(), ServerError>>, ServerError>
+where
+    S: AsRef<SharedState> + Clone + Send + Sync + 'static,
+{
+    if config.token.trim().is_empty() {
+        return Err(ServerError::MissingToken);
+    }
+    let address = SocketAddr::from((Ipv4Addr::LOCALHOST, config.port));
+    let mut listener = app(state, config)
+        .bind(address)
+        .await
+        .map_err(|err| ServerError::Listen(address, err))?;
+    log::info!("API listening on http://{address}");
+    Ok(async move { listener.accept().await.map_err(ServerError::Serve) }.boxed())
+}
+
+/// Routes of the API behind the token check.
+pub fn app<S>(state: S, config: &ServerConfig) -> tide::Server<S>
+where
+    S: AsRef<SharedState> + Clone + Send + Sync + 'static,
+{
+    let mut app = tide::with_state(state);
+    app.with(RequireToken(config.token.trim().to_string()));
+    openai::routes(&mut app);
+    queue::routes(&mut app);
+    app
+}
+
+/// Rejects requests without `Authorization: Bearer <token>`.
+struct RequireToken(String);
+
+#[tide::utils::async_trait]
+impl<S: Clone + Send + Sync + 'static> Middleware<S> for RequireToken {
+    async fn handle(&self, request: Request<S>, next: Next<'_, S>) -> tide::Result {
+        let presented = request
+            .header("Authorization")
+            .and_then(|values| values.as_str().strip_prefix("Bearer "))
+            .map(str::trim);
+        if presented.is_some_and(|token| constant_time_eq(token.as_bytes(), self.0.as_bytes())) {
+            Ok(next.run(request).await)
+        } else {
+            Ok(error_response(
+                StatusCode::Unauthorized,
+                "invalid_api_key",
+                "missing or invalid API token",
+            ))
+        }
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Error body in the shape OpenAI clients expect.
+pub(crate) fn error_response(status: StatusCode, code: &str, message: &str) -> Response {
+    let kind = if status.is_client_error() {
+        "invalid_request_error"
+    } else {
+        "server_error"
+    };
+    let body = serde_json::json!({
+        "error": { "message": message, "type": kind, "code": code }
+    });
+    Response::builder(status).content_type(mime::JSON).body(body).build()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::AppConfig;
+    use std::sync::Arc;
+    use tide::http::{Method, Url};
+
+    const TOKEN: &str = "secreto";
+
+    pub(crate) fn server() -> (tide::Server<Arc<SharedState>>, Arc<SharedState>) {
+        let state = Arc::new(SharedState::new(AppConfig::default()).expect("state"));
+        let config = ServerConfig {
+            token: TOKEN.into(),
+            ..ServerConfig::default()
+        };
+        (app(state.clone(), &config), state)
+    }
+
+    /// Authorized request with an optional JSON body.
+    pub(crate) fn request(method: Method, path: &str, body: Option<&str>) -> tide::http::Request {
+        let url = Url::parse(&format!("http://127.0.0.1{path}")).unwrap();
+        let mut request = tide::http::Request::new(method, url);
+        request.insert_header("Authorization", format!("Bearer {TOKEN}"));
+        if let Some(body) = body {
+            request.set_body(body);
+            request.set_content_type(mime::JSON);
+        }
+        request
+    }
+
+    #[test]
+    fn compares_tokens() {
+        assert!(constant_time_eq(b"secreto", b"secreto"));
+        assert!(!constant_time_eq(b"secreto", b"secretO"));
+        assert!(!constant_time_eq(b"secreto", b"secret"));
+    }
+}
@@ -0,0 +1,278 @@
+//! `POST /v1/audio/speech` and `GET /v1/models` with the request and response
+//! shapes of the OpenAI speech API, so existing clients only need a new base
+//! URL and token.
+
+use super::error_response;
+use crate::audio::{encode_pcm, streaming_wav_header, AudioFormat};
+use crate::queue;
+use crate::SharedState;
+use futures::{StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use tide::{Body, Request, Response, StatusCode};
+use uuid::Uuid;
+
+/// Longest `input` OpenAI accepts; longer texts belong in the queue.
+const MAX_INPUT_CHARS: usize = 4096;
+
+/// Body of `POST /v1/audio/speech`. Field names follow OpenAI, not the
+/// camelCase used by the Tauri commands.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SpeechRequest {
+    /// Accepted for compatibility; the voice alone selects the model.
+    #[serde(default)]
+    pub model: Option<String>,
+    pub input: String,
+    pub voice: String,
+    /// 0.25 to 4.0, higher is faster.
+    #[serde(default)]
+    pub speed: Option<f32>,
+    /// `mp3` (default), `wav` or `pcm`.
+    #[serde(default)]
+    pub response_format: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelList {
+    pub object: &'static str,
+    pub data: Vec<Model>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Model {
+    pub id: String,
+    pub object: &'static str,
+    pub created: u64,
+    pub owned_by: &'static str,
+    pub language: Option<String>,
+}
+
+/// Audio the endpoint can return. PCM is the WAV payload without a header:
+/// 16-bit little-endian mono at the voice's sample rate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ResponseFormat {
+    Mp3,
+    Wav,
+    Pcm,
+}
+
+impl ResponseFormat {
+    fn parse(value: Option<&str>) -> Option<Self> {
+        match value.unwrap_or("mp3") {
+            "mp3" => Some(Self::Mp3),
+            "wav" => Some(Self::Wav),
+            "pcm" => Some(Self::Pcm),
+            _ => None,
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            Self::Mp3 => "audio/mpeg",
+            Self::Wav => "audio/wav",
+            Self::Pcm => "audio/pcm",
+        }
+    }
+}
+
+pub(crate) fn routes<S>(app: &mut tide::Server<S>)
+where
+    S: AsRef<SharedState> + Clone + Send + Sync + 'static,
+{
+    app.at("/v1/audio/speech").post(speech::<S>);
+    app.at("/v1/models").get(models::<S>);
+}
+
+async fn speech<S>(mut request: Request<S>) -> tide::Result
+where
+    S: AsRef<SharedState> + Clone + Send + Sync + 'static,
+{
+    let body: SpeechRequest = match request.body_json().await {
+        Ok(body) => body,
+        Err(err) => return reject(StatusCode::BadRequest, "invalid_request", &err.to_string()),
+    };
+    let input = body.input.trim();
+    if input.is_empty() {
+        return reject(StatusCode::BadRequest, "invalid_input", "input must not be empty");
+    }
+    if input.chars().count() > MAX_INPUT_CHARS {
+        let message = format!("input is longer than {MAX_INPUT_CHARS} characters");
+        return reject(StatusCode::BadRequest, "invalid_input", &message);
+    }
+    let Some(format) = ResponseFormat::parse(body.response_format.as_deref()) else {
+        let message = "response_format must be one of mp3, wav or pcm";
+        return reject(StatusCode::BadRequest, "unsupported_format", message);
+    };
+    let speed = body.speed.unwrap_or(1.0);
+    if !(0.25..=4.0).contains(&speed) {
+        return reject(
+            StatusCode::BadRequest,
+            "invalid_speed",
+            "speed must be between 0.25 and 4.0",
+        );
+    }
+
+    let state = request.state().as_ref();
+    let voice = resolve_voice(state, &body.voice);
+    // Piper's length scale is the inverse of OpenAI's speed.
+    let result = match format {
+        ResponseFormat::Mp3 => synthesize_mp3(state, input, voice, 1.0 / speed).await,
+        ResponseFormat::Wav | ResponseFormat::Pcm => stream_audio(state, input, &voice, 1.0 / speed, format).await,
+    };
+    match result {
+        Ok(body) => Ok(Response::builder(StatusCode::Ok)
+            .content_type(format.content_type())
+            .body(body)
+            .build()),
+        Err(err) => {
+            log::warn!("API speech request failed: {err}");
+            reject(StatusCode::InternalServerError, "synthesis_failed", &err)
+        }
+    }
+}
+
+/// MP3 needs the whole WAV for ffmpeg, so it is synthesized to a temporary
+/// file first.
+async fn synthesize_mp3(state: &SharedState, input: &str, voice: String, speed: f32) -> Result<Body, String> {
+    let output = std::env::temp_dir().join(format!("piperdesk-api-{}.mp3", Uuid::new_v4()));
+    let result = match queue::synthesize_to(state, input, voice, speed, AudioFormat::Mp3, &output).await {
+        Ok(_) => async_std::fs::read(&output).await.map_err(|err| err.to_string()),
+        Err(err) => Err(err.to_string()),
+    };
+    let _ = async_std::fs::remove_file(&output).await;
+    result.map(Body::from_bytes)
+}
+
+/// Sends audio while the synthesizer is still running: WAV with a header of
+/// unknown length, or bare PCM. The first clip is awaited so a failing voice
+/// still gets an error response; later failures end the body early.
+async fn stream_audio(
+    state: &SharedState,
+    input: &str,
+    voice: &str,
+    speed: f32,
+    format: ResponseFormat,
+) -> Result<Body, String> {
+    let voice = state.tts.resolve_voice(Some(voice)).map_err(|err| err.to_string())?;
+    let mut stream = state
+        .tts
+        .synthesize_stream(&voice, input, None, Some(speed))
+        .map_err(|err| format!("{err:#}"))?;
+    let first = match stream.next().await {
+        Some(chunk) => chunk.map_err(|err| format!("{err:#}"))?,
+        None => return Err("no audio from the synthesizer".to_string()),
+    };
+    let mut head = match format {
+        ResponseFormat::Wav => streaming_wav_header(first.sample_rate, first.channels),
+        ResponseFormat::Mp3 | ResponseFormat::Pcm => Vec::new(),
+    };
+    head.extend(encode_pcm(&first));
+
+    // The channel hands the audio to the response body; when the client
+    // disconnects, sending fails and dropping the stream stops synthesis.
+    let (sender, receiver) = async_std::channel::bounded::<std::io::Result<Vec<u8>>>(4);
+    async_std::task::spawn(async move {
+        if sender.send(Ok(head)).await.is_err() {
+            return;
+        }
+        while let Some(chunk) = stream.next().await {
+            let bytes = chunk.map(|audio| encode_pcm(&audio)).map_err(|err| {
+                log::warn!("API speech stream failed: {err:#}");
+                std::io::Error::other(format!("{err:#}"))
+            });
+            let failed = bytes.is_err();
+            if sender.send(bytes).await.is_err() || failed {
+                break;
+            }
+        }
+    });
+    Ok(Body::from_reader(receiver.into_async_read(), None))
+}
+
+fn reject(status: StatusCode, code: &str, message: &str) -> tide::Result {
+    Ok(error_response(status, code, message))
+}
+
+/// Installed voice names are used as they are; other names go through the
+/// configured aliases and fall back to the default voice, so clients that
+/// hard-code `alloy` keep working.
+fn resolve_voice(state: &SharedState, requested: &str) -> String {
+    if state.tts.resolve_voice(Some(requested)).is_ok() {
+        return requested.to_string();
+    }
+    if let Some(alias) = state.config.lock().server.voice_aliases.get(requested) {
+        return alias.clone();
+    }
+    let fallback = state.tts.default_voice().to_string();
+    log::debug!("API voice '{requested}' is not installed, using '{fallback}'");
+    fallback
+}
+
+async fn models<S>(request: Request<S>) -> tide::Result
+where
+    S: AsRef<SharedState> + Clone + Send + Sync + 'static,
+{
+    let mut voices = request.state().as_ref().tts.voices();
+    voices.sort_by(|a, b| a.name.cmp(&b.name));
+    let list = ModelList {
+        object: "list",
+        data: voices
+            .into_iter()
+            .map(|voice| Model {
+                id: voice.name,
+                object: "model",
+                created: 0,
+                owned_by: "piperdesk",
+                language: voice.language,
+            })
+            .collect(),
+    };
+    Ok(Response::builder(StatusCode::Ok)
+        .content_type(tide::http::mime::JSON)
+        .body(serde_json::to_value(list)?)
+        .build())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{request, server};
+    use tide::http::{Method, Response};
+
+    #[test]
+    fn requires_token() {
+        let (server, _) = server();
+        async_std::task::block_on(async {
+            let mut anonymous = request(Method::Get, "/v1/models", None);
+            anonymous.remove_header("Authorization");
+            let response: Response = server.respond(anonymous).await.unwrap();
+            assert_eq!(response.status(), 401);
+            let mut response: Response = server.respond(request(Method::Get, "/v1/models", None)).await.unwrap();
+            assert_eq!(response.status(), 200);
+            let list: serde_json::Value = response.body_json().await.unwrap();
+            assert_eq!(list["object"], "list");
+        });
+    }
+
+    #[test]
+    fn validates_speech_requests() {
+        let (server, _) = server();
+        async_std::task::block_on(async {
+            for (body, code) in [
+                (r#"{"voice": "alloy"}"#, "invalid_request"),
+                (r#"{"input": " ", "voice": "alloy"}"#, "invalid_input"),
+                (
+                    r#"{"input": "Hola", "voice": "alloy", "response_format": "opus"}"#,
+                    "unsupported_format",
+                ),
+                (r#"{"input": "Hola", "voice": "alloy", "speed": 5.0}"#, "invalid_speed"),
+            ] {
+                let mut response: Response = server
+                    .respond(request(Method::Post, "/v1/audio/speech", Some(body)))
+                    .await
+                    .unwrap();
+                assert_eq!(response.status(), 400, "{body}");
+                let error: serde_json::Value = response.body_json().await.unwrap();
+                assert_eq!(error["error"]["code"], code, "{body}");
+            }
+        });
+    }
+}
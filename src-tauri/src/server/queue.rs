@@ -0,0 +1,282 @@
+//! Queue management under `/v1/queue`, sharing the queue functions the Tauri
+//! commands use. `GET /v1/queue/events` streams the `queue::*` events as
+//! server-sent events.
+
+use super::error_response;
+use crate::queue::{self, NewQueueItem, QueueError};
+use crate::SharedState;
+use serde::{Deserialize, Serialize};
+use tide::http::mime;
+use tide::{Body, Request, Response, StatusCode};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MoveRequest {
+    /// 0-based target position.
+    position: usize,
+}
+
+pub(crate) fn routes<S>(app: &mut tide::Server<S>)
+where
+    S: AsRef<SharedState> + Clone + Send + Sync + 'static,
+{
+    app.at("/v1/queue").get(list::<S>).post(enqueue::<S>);
+    app.at("/v1/queue/next").post(next::<S>);
+    app.at("/v1/queue/retry").post(retry_failed::<S>);
+    app.at("/v1/queue/events").get(tide::sse::endpoint(events::<S>));
+    app.at("/v1/queue/:id").get(get::<S>).delete(remove::<S>);
+    app.at("/v1/queue/:id/cancel").post(cancel::<S>);
+    app.at("/v1/queue/:id/retry").post(retry::<S>);
+    app.at("/v1/queue/:id/move").post(move_to::<S>);
+    app.at("/v1/queue/:id/audio").get(audio::<S>);
+}
+
+async fn list<S>(request: Request<S>) -> tide::Result
+where
+    S: AsRef<SharedState> + Clone + Send + Sync + 'static,
+{
+    json(StatusCode::Ok, &queue::list(request.state().as_ref()))
+}
+
+/// Takes a JSON array of `{title, text, voice?, rate?, format?}`.
+async fn enqueue<S>(mut request: Request<S>) -> tide::Result
+where
+    S: AsRef<SharedState> + Clone + Send + Sync + 'static,
+{
+    let items: Vec<NewQueueItem> = match request.body_json().await {
+        Ok(items) => items,
+        Err(err) => return reject(StatusCode::BadRequest, "invalid_request", &err.to_string()),
+    };
+    match queue::enqueue_text(request.state().as_ref(), items) {
+        Ok(created) => json(StatusCode::Created, &created),
+        Err(err) => reject(StatusCode::BadRequest, "invalid_request", &err),
+    }
+}
+
+/// Synthesizes the next pending item into the export directory and returns
+/// it once done.
+async fn next<S>(request: Request<S>) -> tide::Result
+where
+    S: AsRef<SharedState> + Clone + Send + Sync + 'static,
+{
+    let state = request.state().as_ref();
+    let Some(item) = queue::claim_next(state) else {
+        return reject(StatusCode::NotFound, "queue_empty", "no pending items");
+    };
+    let export_dir = state.config.lock().export_dir.clone();
+    match queue::run_item(state, item, &export_dir).await {
+        Ok(done) => json(StatusCode::Ok, &done),
+        Err(err) => reject(StatusCode::InternalServerError, "synthesis_failed", &err),
+    }
+}
+
+async fn get<S>(request: Request<S>) -> tide::Result
+where
+    S: AsRef<SharedState> + Clone + Send + Sync + 'static,
+{
+    let Some(id) = item_id(&request) else {
+        return reject(StatusCode::BadRequest, "invalid_id", "item id must be a UUID");
+    };
+    match queue::get_item(request.state().as_ref(), id) {
+        Some(item) => json(StatusCode::Ok, &item),
+        None => queue_error(QueueError::NotFound(id)),
+    }
+}
+
+async fn remove<S>(request: Request<S>) -> tide::Result
+where
+    S: AsRef<SharedState> + Clone + Send + Sync + 'static,
+{
+    let Some(id) = item_id(&request) else {
+        return reject(StatusCode::BadRequest, "invalid_id", "item id must be a UUID");
+    };
+    let state = request.state().as_ref();
+    if queue::get_item(state, id).is_none() {
+        return queue_error(QueueError::NotFound(id));
+    }
+    queue::remove_item(state, id);
+    Ok(Response::new(StatusCode::NoContent))
+}
+
+async fn cancel<S>(request: Request<S>) -> tide::Result
+where
+    S: AsRef<SharedState> + Clone + Send + Sync + 'static,
+{
+    let Some(id) = item_id(&request) else {
+        return reject(StatusCode::BadRequest, "invalid_id", "item id must be a UUID");
+    };
+    match queue::cancel_item(request.state().as_ref(), id) {
+        Ok(item) => json(StatusCode::Ok, &item),
+        Err(err) => queue_error(err),
+    }
+}
+
+/// Queues a failed item again.
+async fn retry<S>(request: Request<S>) -> tide::Result
+where
+    S: AsRef<SharedState> + Clone + Send + Sync + 'static,
+{
+    let Some(id) = item_id(&request) else {
+        return reject(StatusCode::BadRequest, "invalid_id", "item id must be a UUID");
+    };
+    match queue::retry_item(request.state().as_ref(), id) {
+        Ok(item) => json(StatusCode::Ok, &item),
+        Err(err) => queue_error(err),
+    }
+}
+
+/// Queues every failed item again and returns them.
+async fn retry_failed<S>(request: Request<S>) -> tide::Result
+where
+    S: AsRef<SharedState> + Clone + Send + Sync + 'static,
+{
+    json(StatusCode::Ok, &queue::retry_failed(request.state().as_ref()))
+}
+
+/// Takes `{"position": n}` and returns the reordered queue.
+async fn move_to<S>(mut request: Request<S>) -> tide::Result
+where
+    S: AsRef<SharedState> + Clone + Send + Sync + 'static,
+{
+    let Some(id) = item_id(&request) else {
+        return reject(StatusCode::BadRequest, "invalid_id", "item id must be a UUID");
+    };
+    let body: MoveRequest = match request.body_json().await {
+        Ok(body) => body,
+        Err(err) => return reject(StatusCode::BadRequest, "invalid_request", &err.to_string()),
+    };
+    let state = request.state().as_ref();
+    match queue::move_item(state, id, body.position) {
+        Ok(()) => json(StatusCode::Ok, &queue::list(state)),
+        Err(err) => queue_error(err),
+    }
+}
+
+/// Downloads the synthesized audio of a completed item.
+async fn audio<S>(request: Request<S>) -> tide::Result
+where
+    S: AsRef<SharedState> + Clone + Send + Sync + 'static,
+{
+    let Some(id) = item_id(&request) else {
+        return reject(StatusCode::BadRequest, "invalid_id", "item id must be a UUID");
+    };
+    let Some(item) = queue::get_item(request.state().as_ref(), id) else {
+        return queue_error(QueueError::NotFound(id));
+    };
+    let Some(path) = item.output else {
+        return reject(StatusCode::NotFound, "no_output", "item has not been synthesized yet");
+    };
+    let body = match Body::from_file(&path).await {
+        Ok(body) => body,
+        Err(err) => {
+            let message = format!("cannot read {}: {err}", path.display());
+            return reject(StatusCode::NotFound, "no_output", &message);
+        }
+    };
+    let name = path.file_name().unwrap_or_default().to_string_lossy().replace('"', "");
+    Ok(Response::builder(StatusCode::Ok)
+        .header("Content-Disposition", format!("attachment; filename=\"{name}\""))
+        .body(body)
+        .build())
+}
+
+async fn events<S>(request: Request<S>, sender: tide::sse::Sender) -> tide::Result<()>
+where
+    S: AsRef<SharedState> + Clone + Send + Sync + 'static,
+{
+    let events = request.state().as_ref().events.subscribe();
+    while let Ok(event) = events.recv().await {
+        // Fails once the client has gone away.
+        sender.send(event.name(), event.payload().to_string(), None).await?;
+    }
+    Ok(())
+}
+
+fn item_id<S>(request: &Request<S>) -> Option<Uuid> {
+    request.param("id").ok().and_then(|id| Uuid::parse_str(id).ok())
+}
+
+fn json(status: StatusCode, value: &impl Serialize) -> tide::Result {
+    Ok(Response::builder(status)
+        .content_type(mime::JSON)
+        .body(serde_json::to_value(value)?)
+        .build())
+}
+
+fn reject(status: StatusCode, code: &str, message: &str) -> tide::Result {
+    Ok(error_response(status, code, message))
+}
+
+fn queue_error(err: QueueError) -> tide::Result {
+    match err {
+        QueueError::NotFound(_) => reject(StatusCode::NotFound, "not_found", &err.to_string()),
+        QueueError::NotCancellable(..) | QueueError::NotRetryable(..) => {
+            reject(StatusCode::Conflict, "conflict", &err.to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{request, server};
+    use crate::{QueueItem, QueueStatus};
+    use tide::http::{Method, Response};
+
+    #[test]
+    fn manages_queue_items() {
+        let (server, state) = server();
+        async_std::task::block_on(async {
+            let items = r#"[{"title": "Uno", "text": "A"}, {"title": "Dos", "text": "B"}]"#;
+            let mut response: Response = server
+                .respond(request(Method::Post, "/v1/queue", Some(items)))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), 201);
+            let created: Vec<QueueItem> = response.body_json().await.unwrap();
+            let (first, second) = (created[0].id, created[1].id);
+
+            let mut response: Response = server
+                .respond(request(
+                    Method::Post,
+                    &format!("/v1/queue/{second}/move"),
+                    Some(r#"{"position": 0}"#),
+                ))
+                .await
+                .unwrap();
+            let order: Vec<QueueItem> = response.body_json().await.unwrap();
+            assert_eq!(
+                order.iter().map(|item| item.id).collect::<Vec<_>>(),
+                vec![second, first]
+            );
+
+            let mut response: Response = server
+                .respond(request(Method::Post, &format!("/v1/queue/{first}/cancel"), None))
+                .await
+                .unwrap();
+            let cancelled: QueueItem = response.body_json().await.unwrap();
+            assert_eq!(cancelled.status, QueueStatus::Cancelled);
+            let response: Response = server
+                .respond(request(Method::Post, &format!("/v1/queue/{first}/retry"), None))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), 409);
+
+            let response: Response = server
+                .respond(request(Method::Delete, &format!("/v1/queue/{second}"), None))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), 204);
+            assert_eq!(state.queue.lock().len(), 1);
+
+            for (path, status) in [
+                (format!("/v1/queue/{second}"), 404),
+                (format!("/v1/queue/{first}/audio"), 404),
+                ("/v1/queue/not-a-uuid".to_string(), 400),
+            ] {
+                let response: Response = server.respond(request(Method::Get, &path, None)).await.unwrap();
+                assert_eq!(response.status(), status, "{path}");
+            }
+        });
+    }
+}
@@ -0,0 +1,200 @@
+//! The queue, listening positions and bookmarks, saved between runs of the
+//! desktop app.
+
+use crate::bookmarks::{Listening, ListeningState};
+use crate::queue::QueueEvent;
+use crate::{QueueItem, QueueStatus, SharedState};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// How often listening positions are written while they change.
+const SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Where the queue is saved. Positions and bookmarks go to
+/// `listening.json` next to it, so playback does not rewrite the queue.
+pub fn default_path() -> PathBuf {
+    dirs::data_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("piperdesk")
+        .join("queue.json")
+}
+
+fn listening_path(queue_path: &Path) -> PathBuf {
+    queue_path.with_file_name("listening.json")
+}
+
+/// The queue without listening positions, which are saved on their own.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct SavedQueue {
+    items: Vec<QueueItem>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct SavedListening {
+    /// Where each queue item was left, by item id.
+    positions: BTreeMap<Uuid, ListeningState>,
+    #[serde(flatten)]
+    listening: Listening,
+}
+
+fn queue_snapshot(state: &SharedState) -> Result<Vec<u8>> {
+    let items = state
+        .queue
+        .lock()
+        .iter()
+        .map(|item| QueueItem {
+            listening: None,
+            ..item.clone()
+        })
+        .collect();
+    Ok(serde_json::to_vec(&SavedQueue { items })?)
+}
+
+fn listening_snapshot(state: &SharedState) -> Result<Vec<u8>> {
+    let positions = state
+        .queue
+        .lock()
+        .iter()
+        .filter_map(|item| Some((item.id, item.listening.clone()?)))
+        .collect();
+    let saved = SavedListening {
+        positions,
+        listening: state.listening.lock().clone(),
+    };
+    Ok(serde_json::to_vec(&saved)?)
+}
+
+fn write(path: &Path, content: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        fs::create_dir_all(parent).context("failed to create session directory")?;
+    }
+    // Written aside and renamed, so a crash never leaves half a queue.
+    let partial = path.with_extension("json.partial");
+    fs::write(&partial, content).context("failed to write session")?;
+    fs::rename(&partial, path).context("failed to replace session")
+}
+
+/// Saves the queue to `path` and listening positions next to it.
+pub fn save(state: &SharedState, path: &Path) -> Result<()> {
+    write(path, &queue_snapshot(state)?)?;
+    write(&listening_path(path), &listening_snapshot(state)?)
+}
+
+/// Adds the saved items to the queue and restores positions and bookmarks.
+/// Items that were being synthesized when the app closed are pending again;
+/// items waiting for a retry keep waiting.
+pub fn restore(state: &SharedState, path: &Path) -> Result<()> {
+    if !path.exists() {
+        return Ok(());
+    }
+    let content = fs::read(path).context("failed to read session")?;
+    let queue: SavedQueue = serde_json::from_slice(&content).context("invalid session file")?;
+    let listening_path = listening_path(path);
+    let mut saved = if listening_path.exists() {
+        let content = fs::read(&listening_path).context("failed to read listening positions")?;
+        serde_json::from_slice(&content).context("invalid listening positions file")?
+    } else {
+        SavedListening::default()
+    };
+    state.queue.lock().extend(queue.items.into_iter().map(|mut item| {
+        if item.status == QueueStatus::Processing {
+            item.status = QueueStatus::Pending;
+        }
+        item.listening = saved.positions.remove(&item.id);
+        item
+    }));
+    *state.listening.lock() = saved.listening;
+    state.events.publish(QueueEvent::Changed);
+    Ok(())
+}
+
+/// Saves the queue whenever it changes, and listening positions every few
+/// seconds if they moved. Runs until the application exits.
+pub async fn persist(state: &SharedState, path: &Path) {
+    let events = state.events.subscribe();
+    let listening_path = listening_path(path);
+    let mut saved_listening = listening_snapshot(state).ok();
+    loop {
+        match async_std::future::timeout(SAVE_INTERVAL, events.recv()).await {
+            Ok(Err(_)) => break,
+            Ok(Ok(_)) => {
+                if let Err(err) = queue_snapshot(state).and_then(|queue| write(path, &queue)) {
+                    log::warn!("failed to save queue: {err:#}");
+                }
+            }
+            Err(_) => {}
+        }
+        let current = match listening_snapshot(state) {
+            Ok(current) => current,
+            Err(err) => {
+                log::warn!("failed to serialize listening positions: {err:#}");
+                continue;
+            }
+        };
+        if saved_listening.as_ref() == Some(&current) {
+            continue;
+        }
+        match write(&listening_path, &current) {
+            Ok(()) => saved_listening = Some(current),
+            Err(err) => log::warn!("failed to save listening positions: {err:#}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bookmarks::{self, NewBookmark};
+    use crate::queue::{self, NewQueueItem};
+    use crate::AppConfig;
+
+    #[test]
+    fn restores_queue_positions_and_bookmarks() {
+        let dir = std::env::temp_dir().join(format!("piperdesk-session-{}", Uuid::new_v4()));
+        let path = dir.join("queue.json");
+        let state = SharedState::new(AppConfig::default()).unwrap();
+        let new_item = |title: &str| NewQueueItem {
+            title: title.into(),
+            text: title.into(),
+            voice: None,
+            rate: None,
+            format: None,
+        };
+        let items = queue::enqueue_text(&state, vec![new_item("uno"), new_item("dos")]).unwrap();
+        queue::claim_next(&state).unwrap();
+        bookmarks::record_position(&state, items[1].id, 4_200);
+        let bookmark = bookmarks::add(
+            &state,
+            NewBookmark {
+                item_id: items[1].id,
+                position_ms: 1_000,
+                name: Some("inicio".into()),
+                note: None,
+            },
+        )
+        .unwrap();
+        save(&state, &path).unwrap();
+
+        let restored = SharedState::new(AppConfig::default()).unwrap();
+        restore(&restored, &path).unwrap();
+        let queued = queue::list(&restored);
+        assert_eq!(queued.len(), 2);
+        assert_eq!(queued[0].status, QueueStatus::Pending);
+        assert_eq!(
+            queued[1].listening.as_ref().map(|listening| listening.position_ms),
+            Some(4_200)
+        );
+        assert_eq!(bookmarks::list(&restored, None, None), vec![bookmark]);
+        // Positions are only in listening.json.
+        let saved: serde_json::Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        assert!(saved["items"][1]["listening"].is_null(), "{saved}");
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,87 @@
+use serde::{Deserialize, Serialize};
+
+/// A sentence-sized slice of the source text, with byte offsets into it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Sentence {
+    pub text: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+const TERMINATORS: &[char] = &['.', '!', '?', '…', '。', '！', '？'];
+const CLOSERS: &[char] = &['"', '\'', '”', '’', '»', ')', ']'];
+
+/// Splits `text` into sentences on terminal punctuation and paragraph breaks.
+///
+/// Newlines inside a sentence are collapsed to spaces so every chunk can be
+/// fed to Piper as a single line.
+pub fn split_sentences(text: &str) -> Vec<Sentence> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((idx, ch)) = chars.next() {
+        let boundary = if TERMINATORS.contains(&ch) {
+            let mut end = idx + ch.len_utf8();
+            while let Some(&(next_idx, next)) = chars.peek() {
+                if TERMINATORS.contains(&next) || CLOSERS.contains(&next) {
+                    end = next_idx + next.len_utf8();
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            match chars.peek() {
+                None => Some(end),
+                Some(&(_, next)) if next.is_whitespace() => Some(end),
+                _ => None,
+            }
+        } else if ch == '\n' && text[idx + 1..].starts_with(['\n', '\r']) {
+            Some(idx)
+        } else {
+            None
+        };
+        if let Some(end) = boundary {
+            push_sentence(&mut sentences, text, start, end);
+            start = end;
+        }
+    }
+    push_sentence(&mut sentences, text, start, text.len());
+    sentences
+}
+
+fn push_sentence(sentences: &mut Vec<Sentence>, text: &str, start: usize, end: usize) {
+    let slice = &text[start..end];
+    let trimmed = slice.trim_start();
+    let start = start + (slice.len() - trimmed.len());
+    let trimmed = trimmed.trim_end();
+    if trimmed.is_empty() {
+        return;
+    }
+    sentences.push(Sentence {
+        text: trimmed.split_whitespace().collect::<Vec<_>>().join(" "),
+        start,
+        end: start + trimmed.len(),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_on_punctuation_and_paragraphs() {
+        let text = "Hola mundo. ¿Qué tal?\nBien\n\nNuevo párrafo";
+        let sentences = split_sentences(text);
+        let texts: Vec<_> = sentences.iter().map(|s| s.text.as_str()).collect();
+        assert_eq!(texts, vec!["Hola mundo.", "¿Qué tal?", "Bien", "Nuevo párrafo"]);
+        assert_eq!(&text[sentences[1].start..sentences[1].end], "¿Qué tal?");
+    }
+
+    #[test]
+    fn keeps_decimals_and_closing_quotes_together() {
+        let sentences = split_sentences("Costó 3.5 euros. \"¡Caro!\" Sí.");
+        let texts: Vec<_> = sentences.iter().map(|s| s.text.as_str()).collect();
+        assert_eq!(texts, vec!["Costó 3.5 euros.", "\"¡Caro!\"", "Sí."]);
+    }
+}
@@ -0,0 +1,191 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Start/end time of one synthesized chunk and the text it covers.
+///
+/// `char_start`/`char_end` are character offsets into the queue item text.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimedSegment {
+    pub index: usize,
+    pub text: String,
+    pub start_ms: u64,
+    pub end_ms: u64,
+    pub char_start: usize,
+    pub char_end: usize,
+    #[serde(default)]
+    pub words: Vec<TimedWord>,
+}
+
+/// Estimated timing of a single word inside a segment.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimedWord {
+    pub text: String,
+    pub start_ms: u64,
+    pub end_ms: u64,
+    pub char_start: usize,
+    pub char_end: usize,
+}
+
+/// The text being spoken at a given playback position.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextSpan {
+    pub segment_index: usize,
+    pub word_index: Option<usize>,
+    pub char_start: usize,
+    pub char_end: usize,
+    pub start_ms: u64,
+    pub end_ms: u64,
+    pub sentence_char_start: usize,
+    pub sentence_char_end: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimingMap {
+    pub duration_ms: u64,
+    pub segments: Vec<TimedSegment>,
+}
+
+impl TimingMap {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path).context("failed to read timing map")?;
+        serde_json::from_str(&content).context("invalid timing map format")
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        fs::write(path, content).context("failed to write timing map")
+    }
+
+    /// Finds the word (or, failing that, the sentence) playing at `position_ms`.
+    pub fn span_at(&self, position_ms: u64) -> Option<TextSpan> {
+        let idx = self
+            .segments
+            .partition_point(|segment| segment.start_ms <= position_ms)
+            .checked_sub(1)?;
+        let segment = &self.segments[idx];
+        if position_ms >= segment.end_ms {
+            return None;
+        }
+        let mut span = TextSpan {
+            segment_index: segment.index,
+            word_index: None,
+            char_start: segment.char_start,
+            char_end: segment.char_end,
+            start_ms: segment.start_ms,
+            end_ms: segment.end_ms,
+            sentence_char_start: segment.char_start,
+            sentence_char_end: segment.char_end,
+        };
+        let word = segment
+            .words
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|(_, word)| word.start_ms <= position_ms);
+        if let Some((word_index, word)) = word {
+            span.word_index = Some(word_index);
+            span.char_start = word.char_start;
+            span.char_end = word.char_end;
+            span.start_ms = word.start_ms;
+            span.end_ms = word.end_ms;
+        }
+        Some(span)
+    }
+}
+
+/// Spreads the voiced part of a segment over its words, weighting each word by
+/// its length. Piper does not report word boundaries, so this is an estimate
+/// that is good enough to drive read-along highlighting.
+pub fn estimate_words(source: &str, char_offset: usize, start_ms: u64, end_ms: u64) -> Vec<TimedWord> {
+    let mut words = Vec::new();
+    let mut current: Option<(usize, String)> = None;
+    for (char_idx, ch) in (char_offset..).zip(source.chars()) {
+        if ch.is_whitespace() {
+            if let Some((start, text)) = current.take() {
+                words.push((start, text));
+            }
+        } else {
+            current.get_or_insert_with(|| (char_idx, String::new())).1.push(ch);
+        }
+    }
+    if let Some(word) = current {
+        words.push(word);
+    }
+
+    let weights: Vec<u64> = words.iter().map(|(_, text)| text.chars().count() as u64 + 1).collect();
+    let total: u64 = weights.iter().sum();
+    let duration = end_ms.saturating_sub(start_ms);
+    let mut consumed = 0;
+    words
+        .into_iter()
+        .zip(weights)
+        .map(|((char_start, text), weight)| {
+            let word_start = start_ms + duration * consumed / total.max(1);
+            consumed += weight;
+            let word_end = start_ms + duration * consumed / total.max(1);
+            TimedWord {
+                char_end: char_start + text.chars().count(),
+                text,
+                start_ms: word_start,
+                end_ms: word_end,
+                char_start,
+            }
+        })
+        .collect()
+}
+
+pub fn timing_path_for(audio: &Path) -> PathBuf {
+    audio.with_extension("timings.json")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_word_and_sentence_at_position() {
+        let source = "Hola mundo. Adiós";
+        let mut first = TimedSegment {
+            index: 0,
+            text: "Hola mundo.".into(),
+            start_ms: 0,
+            end_ms: 1200,
+            char_start: 0,
+            char_end: 11,
+            words: Vec::new(),
+        };
+        first.words = estimate_words(&source[..11], 0, 0, 1000);
+        let map = TimingMap {
+            duration_ms: 2000,
+            segments: vec![
+                first,
+                TimedSegment {
+                    index: 1,
+                    text: "Adiós".into(),
+                    start_ms: 1200,
+                    end_ms: 2000,
+                    char_start: 12,
+                    char_end: 17,
+                    words: Vec::new(),
+                },
+            ],
+        };
+
+        let span = map.span_at(700).expect("span");
+        assert_eq!(span.word_index, Some(1));
+        assert_eq!((span.char_start, span.char_end), (5, 11));
+        assert_eq!((span.sentence_char_start, span.sentence_char_end), (0, 11));
+
+        let span = map.span_at(1500).expect("span");
+        assert_eq!((span.segment_index, span.word_index), (1, None));
+        assert_eq!((span.char_start, span.char_end), (12, 17));
+
+        assert!(map.span_at(2000).is_none());
+    }
+}
@@ -0,0 +1,373 @@
+//! Speech synthesizers behind one interface. Piper is the main backend;
+//! espeak-ng covers languages Piper has no voice for.
+//!
+//! Voices are named `<backend>:<voice>`, e.g. `espeak:eu`. Voices of the
+//! first backend (Piper) keep their plain names, and plain names are looked
+//! up in every backend in order.
+
+use crate::audio::WavAudio;
+use crate::cache::SynthesisCache;
+use crate::espeak::EspeakEngine;
+use crate::piper::PiperEngine;
+use crate::text::{split_sentences, Sentence};
+use crate::timing::{estimate_words, TimedSegment, TimingMap};
+use crate::AppConfig;
+use anyhow::{anyhow, Context, Result};
+use futures::future::BoxFuture;
+use futures::stream::BoxStream;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use thiserror::Error;
+
+/// Mono PCM chunks in the order the backend produces them.
+pub type PcmStream = BoxStream<'static, Result<WavAudio>>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VoiceInfo {
+    pub name: String,
+    /// Backend that speaks with this voice, e.g. `piper` or `espeak`.
+    pub backend: String,
+    pub description: Option<String>,
+    pub language: Option<String>,
+    pub quality: Option<String>,
+    /// Model file, for backends that load one per voice.
+    pub model_path: Option<PathBuf>,
+}
+
+impl VoiceInfo {
+    /// Name without the backend prefix, as the backend knows the voice.
+    pub fn local_name(&self) -> &str {
+        self.name
+            .strip_prefix(self.backend.as_str())
+            .and_then(|rest| rest.strip_prefix(':'))
+            .unwrap_or(&self.name)
+    }
+}
+
+/// What a backend can do beyond reading plain text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Capabilities {
+    /// Understands SSML markup in the text.
+    pub ssml: bool,
+    /// Voices can have several speakers, chosen by speaker id.
+    pub speakers: bool,
+    /// Clips are kept in the synthesis cache.
+    pub cached: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackendInfo {
+    pub id: &'static str,
+    pub capabilities: Capabilities,
+    pub voices: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct SynthesisOutput {
+    pub path: PathBuf,
+    pub timings: TimingMap,
+}
+
+#[derive(Debug, Clone)]
+pub struct SynthesisRequest {
+    pub text: String,
+    pub voice: Option<String>,
+    pub output: PathBuf,
+    pub speed: Option<f32>,
+    pub speaker: Option<u32>,
+}
+
+#[derive(Debug, Error)]
+pub enum TtsError {
+    #[error("voice '{0}' not found")]
+    VoiceNotFound(String),
+}
+
+/// A speech synthesizer. Voices passed in are the backend's own, named
+/// without the backend prefix. `speed` is a length scale: 2.0 speaks half
+/// as fast.
+pub trait TtsBackend: Send + Sync {
+    /// Prefix of the backend's voice names.
+    fn id(&self) -> &'static str;
+
+    fn capabilities(&self) -> Capabilities;
+
+    /// Installed voices, named without the backend prefix.
+    fn voices(&self) -> Vec<VoiceInfo>;
+
+    /// Synthesizes each text as a separate clip.
+    fn synthesize_clips<'a>(
+        &'a self,
+        voice: &'a VoiceInfo,
+        texts: &'a [&'a str],
+        speaker: Option<u32>,
+        speed: Option<f32>,
+    ) -> BoxFuture<'a, Result<Vec<WavAudio>>>;
+
+    /// Yields audio while synthesis is still running. Dropping the stream
+    /// stops the synthesizer.
+    fn synthesize_stream(
+        &self,
+        voice: &VoiceInfo,
+        text: &str,
+        speaker: Option<u32>,
+        speed: Option<f32>,
+    ) -> Result<PcmStream>;
+}
+
+/// Every configured backend, with voices resolved across all of them.
+pub struct TtsEngine {
+    backends: Vec<Arc<dyn TtsBackend>>,
+    default_voice: String,
+}
+
+impl TtsEngine {
+    /// `backends` in order of preference; the first keeps plain voice names.
+    pub fn new(backends: Vec<Arc<dyn TtsBackend>>, default_voice: impl Into<String>) -> Self {
+        Self {
+            backends,
+            default_voice: default_voice.into(),
+        }
+    }
+
+    /// Piper, plus espeak-ng when it is enabled.
+    pub fn from_config(config: &AppConfig, cache: Arc<SynthesisCache>) -> Result<Self> {
+        let piper = PiperEngine::new(config.piper.clone())?.with_cache(cache);
+        let mut backends: Vec<Arc<dyn TtsBackend>> = vec![Arc::new(piper)];
+        if config.espeak.enabled {
+            backends.push(Arc::new(EspeakEngine::new(config.espeak.clone())));
+        }
+        Ok(Self::new(backends, config.piper.default_voice.clone()))
+    }
+
+    pub fn default_voice(&self) -> &str {
+        &self.default_voice
+    }
+
+    pub fn backends(&self) -> Vec<BackendInfo> {
+        self.backends
+            .iter()
+            .map(|backend| BackendInfo {
+                id: backend.id(),
+                capabilities: backend.capabilities(),
+                voices: backend.voices().len(),
+            })
+            .collect()
+    }
+
+    pub fn voices(&self) -> Vec<VoiceInfo> {
+        self.backends
+            .iter()
+            .enumerate()
+            .flat_map(|(index, backend)| {
+                backend.voices().into_iter().map(move |mut voice| {
+                    if index > 0 {
+                        voice.name = format!("{}:{}", backend.id(), voice.name);
+                    }
+                    voice
+                })
+            })
+            .collect()
+    }
+
+    pub fn resolve_voice(&self, requested: Option<&str>) -> Result<VoiceInfo> {
+        let name = requested.unwrap_or(&self.default_voice);
+        let voices = self.voices();
+        let prefixed = name.split_once(':').and_then(|(backend, local)| {
+            voices
+                .iter()
+                .find(|voice| voice.backend == backend && voice.local_name() == local)
+        });
+        prefixed
+            .or_else(|| voices.iter().find(|voice| voice.local_name() == name))
+            .cloned()
+            .ok_or_else(|| TtsError::VoiceNotFound(name.to_string()).into())
+    }
+
+    /// Picks a voice for a BCP 47 language tag such as `es` or `en-GB`,
+    /// preferring the default voice, then earlier backends, then an exact
+    /// region match.
+    pub fn voice_for_language(&self, language: &str) -> Option<VoiceInfo> {
+        let wanted = language.replace('_', "-").to_ascii_lowercase();
+        let primary = wanted.split('-').next().unwrap_or_default().to_string();
+        let backend_rank = |voice: &VoiceInfo| self.backends.iter().position(|backend| backend.id() == voice.backend);
+        let rank = |voice: &VoiceInfo| {
+            let tag = voice.language.as_deref()?.replace('_', "-").to_ascii_lowercase();
+            let exact = tag == wanted;
+            let same_primary = tag.split('-').next() == Some(primary.as_str());
+            (exact || same_primary).then_some((
+                voice.name != self.default_voice,
+                backend_rank(voice),
+                !exact,
+                voice.name.clone(),
+            ))
+        };
+        self.voices()
+            .into_iter()
+            .filter_map(|voice| rank(&voice).map(|key| (key, voice)))
+            .min_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(_, voice)| voice)
+    }
+
+    /// Synthesizes the request sentence by sentence and joins the clips into
+    /// one WAV file, recording where each sentence starts and ends in the audio.
+    pub async fn synthesize(&self, request: SynthesisRequest) -> Result<SynthesisOutput> {
+        let voice = self.resolve_voice(request.voice.as_deref())?;
+        let sentences = split_sentences(&request.text);
+        if sentences.is_empty() {
+            return Err(anyhow!("no text to synthesize"));
+        }
+        let final_output = request.output;
+        if let Some(parent) = final_output.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let texts: Vec<&str> = sentences.iter().map(|sentence| sentence.text.as_str()).collect();
+        let pieces = self
+            .synthesize_clips(&voice, &texts, request.speaker, request.speed)
+            .await?;
+        let (audio, timings) = join_chunks(&request.text, &sentences, &pieces)?;
+        crate::audio::write_wav(&final_output, &audio)?;
+        Ok(SynthesisOutput {
+            path: final_output,
+            timings,
+        })
+    }
+
+    pub async fn synthesize_clips(
+        &self,
+        voice: &VoiceInfo,
+        texts: &[&str],
+        speaker: Option<u32>,
+        speed: Option<f32>,
+    ) -> Result<Vec<WavAudio>> {
+        let (backend, voice) = self.backend_for(voice)?;
+        backend.synthesize_clips(&voice, texts, speaker, speed).await
+    }
+
+    pub fn synthesize_stream(
+        &self,
+        voice: &VoiceInfo,
+        text: &str,
+        speaker: Option<u32>,
+        speed: Option<f32>,
+    ) -> Result<PcmStream> {
+        let (backend, voice) = self.backend_for(voice)?;
+        backend.synthesize_stream(&voice, text, speaker, speed)
+    }
+
+    /// The backend of `voice` and the voice as that backend names it.
+    fn backend_for(&self, voice: &VoiceInfo) -> Result<(&dyn TtsBackend, VoiceInfo)> {
+        let backend = self
+            .backends
+            .iter()
+            .find(|backend| backend.id() == voice.backend)
+            .ok_or_else(|| TtsError::VoiceNotFound(voice.name.clone()))?;
+        let mut local = voice.clone();
+        local.name = voice.local_name().to_string();
+        Ok((backend.as_ref(), local))
+    }
+}
+
+fn join_chunks(text: &str, sentences: &[Sentence], pieces: &[WavAudio]) -> Result<(WavAudio, TimingMap)> {
+    let mut audio: Option<WavAudio> = None;
+    let mut segments = Vec::with_capacity(sentences.len());
+    let mut byte_cursor = 0;
+    let mut char_cursor = 0;
+    for (index, (sentence, piece)) in sentences.iter().zip(pieces).enumerate() {
+        let joined = audio.get_or_insert_with(|| WavAudio::new(piece.sample_rate, piece.channels));
+        let start_ms = joined.duration_ms();
+        joined.append(piece)?;
+        let source = &text[sentence.start..sentence.end];
+        char_cursor += text[byte_cursor..sentence.start].chars().count();
+        let char_start = char_cursor;
+        char_cursor += source.chars().count();
+        byte_cursor = sentence.end;
+        let (voice_start, voice_end) = piece.voiced_range_ms();
+        segments.push(TimedSegment {
+            index,
+            text: sentence.text.clone(),
+            start_ms,
+            end_ms: joined.duration_ms(),
+            char_start,
+            char_end: char_cursor,
+            words: estimate_words(source, char_start, start_ms + voice_start, start_ms + voice_end),
+        });
+    }
+    let audio = audio.context("the synthesizer produced no audio")?;
+    let timings = TimingMap {
+        duration_ms: audio.duration_ms(),
+        segments,
+    };
+    Ok((audio, timings))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::espeak::EspeakConfig;
+    use crate::mock::FakePiper;
+    use std::os::unix::fs::PermissionsExt;
+    use uuid::Uuid;
+
+    #[test]
+    fn routes_voices_to_their_backend() {
+        let root = std::env::temp_dir().join(format!("piperdesk-tts-{}", Uuid::new_v4()));
+        fs::create_dir_all(&root).unwrap();
+        let piper = FakePiper::default().install(&root).unwrap();
+        let mut template = WavAudio::new(22050, 1);
+        template.samples = vec![7; 100];
+        let template_path = root.join("espeak.wav");
+        crate::audio::write_wav(&template_path, &template).unwrap();
+        let espeak = root.join("espeak-ng");
+        let script = format!(
+            "#!/bin/sh\n\
+             if [ \"$1\" = --voices ]; then\n\
+             printf 'Pty Language Age/Gender VoiceName File Other Languages\\n 5  eu  --/M  Basque  eu\\n 5  es  --/M  Spanish  roa/es\\n'\n\
+             exit 0\nfi\n\
+             echo \"$@\" >> {log}\ncat > /dev/null\ncat {template}\n",
+            log = root.join("espeak.log").display(),
+            template = template_path.display(),
+        );
+        fs::write(&espeak, script).unwrap();
+        fs::set_permissions(&espeak, fs::Permissions::from_mode(0o755)).unwrap();
+        let config = AppConfig {
+            piper,
+            espeak: EspeakConfig {
+                enabled: true,
+                binary_path: espeak,
+                ..EspeakConfig::default()
+            },
+            ..AppConfig::default()
+        };
+        let cache = Arc::new(SynthesisCache::open(crate::cache::CacheConfig {
+            enabled: false,
+            ..Default::default()
+        }));
+        let engine = TtsEngine::from_config(&config, cache).unwrap();
+
+        let mut names: Vec<String> = engine.voices().into_iter().map(|voice| voice.name).collect();
+        names.sort();
+        assert_eq!(names, ["es_ES-prueba-medium", "espeak:es", "espeak:eu"]);
+        assert_eq!(engine.resolve_voice(Some("eu")).unwrap().name, "espeak:eu");
+        assert_eq!(
+            engine.resolve_voice(Some("piper:es_ES-prueba-medium")).unwrap().backend,
+            "piper"
+        );
+        assert!(engine.resolve_voice(Some("espeak:es_ES-prueba-medium")).is_err());
+        // Piper is preferred where it has a voice; espeak-ng fills the gaps.
+        assert_eq!(engine.voice_for_language("es-MX").unwrap().name, "es_ES-prueba-medium");
+        let basque = engine.voice_for_language("eu-ES").unwrap();
+        assert_eq!(basque.name, "espeak:eu");
+
+        let clips = async_std::task::block_on(engine.synthesize_clips(&basque, &["Kaixo."], None, Some(2.0))).unwrap();
+        assert_eq!(clips[0].samples, template.samples);
+        let log = fs::read_to_string(root.join("espeak.log")).unwrap();
+        assert_eq!(log.trim(), "-v eu -s 88 --stdout");
+        let _ = fs::remove_dir_all(&root);
+    }
+}
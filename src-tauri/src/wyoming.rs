@@ -0,0 +1,466 @@
+//! Wyoming protocol server, the TCP protocol Home Assistant uses to talk to
+//! speech services. Every event is a JSON header line, optionally followed by
+//! a JSON data section and a binary payload whose lengths the header gives.
+//!
+//! Only text to speech is offered: `describe` is answered with `info` listing
+//! the installed voices, and `synthesize` with `audio-start`, a series of
+//! `audio-chunk` events carrying 16-bit PCM and `audio-stop`.
+
+use crate::audio::encode_pcm;
+use crate::dict::{self, PronunciationDictionary};
+use crate::tts::{PcmStream, TtsEngine, VoiceInfo};
+use crate::SharedState;
+use async_std::io::{prelude::BufReadExt, BufReader, ReadExt, WriteExt};
+use async_std::net::{TcpListener, TcpStream};
+use futures::future::{BoxFuture, FutureExt};
+use futures::{AsyncBufRead, AsyncRead, AsyncWrite, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Protocol version sent in event headers.
+const PROTOCOL_VERSION: &str = "1.5.2";
+const MAX_HEADER_BYTES: u64 = 64 * 1024;
+/// Clients only send text, so anything larger is a broken or hostile peer.
+const MAX_SECTION_BYTES: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct WyomingConfig {
+    pub enabled: bool,
+    /// Address to listen on. Home Assistant on another machine or in a
+    /// container needs `0.0.0.0`; the protocol has no authentication.
+    pub host: IpAddr,
+    pub port: u16,
+    /// Pronunciation dictionary applied to every text, re-read per request.
+    pub dictionary: PathBuf,
+}
+
+impl Default for WyomingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            host: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: 10200,
+            dictionary: dict::default_path(),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum WyomingError {
+    #[error("failed to listen on {0}: {1}")]
+    Listen(SocketAddr, std::io::Error),
+    #[error("Wyoming connection failed: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid Wyoming event: {0}")]
+    InvalidEvent(String),
+}
+
+/// One protocol message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub kind: String,
+    pub data: Map<String, Value>,
+    pub payload: Vec<u8>,
+}
+
+impl Event {
+    pub fn new(kind: &str, data: Value) -> Self {
+        let data = match data {
+            Value::Object(map) => map,
+            _ => Map::new(),
+        };
+        Self {
+            kind: kind.to_string(),
+            data,
+            payload: Vec::new(),
+        }
+    }
+
+    pub fn with_payload(mut self, payload: Vec<u8>) -> Self {
+        self.payload = payload;
+        self
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Header {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    version: Option<String>,
+    /// Data inline in the header, as sent by older clients.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    data: Option<Map<String, Value>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    data_length: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    payload_length: Option<usize>,
+}
+
+/// Reads the next event, or `None` once the peer has closed the connection.
+pub async fn read_event<R>(reader: &mut R) -> Result<Option<Event>, WyomingError>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let mut line = Vec::new();
+    (&mut *reader)
+        .take(MAX_HEADER_BYTES)
+        .read_until(b'\n', &mut line)
+        .await?;
+    if line.is_empty() {
+        return Ok(None);
+    }
+    if line.last() != Some(&b'\n') {
+        return Err(WyomingError::InvalidEvent(
+            "header line is too long or truncated".into(),
+        ));
+    }
+    let header: Header = serde_json::from_slice(&line).map_err(|err| WyomingError::InvalidEvent(err.to_string()))?;
+    let mut data = header.data.unwrap_or_default();
+    if let Some(length) = header.data_length.filter(|&length| length > 0) {
+        let section = read_section(reader, length).await?;
+        let extra: Map<String, Value> =
+            serde_json::from_slice(&section).map_err(|err| WyomingError::InvalidEvent(err.to_string()))?;
+        data.extend(extra);
+    }
+    let payload = match header.payload_length.filter(|&length| length > 0) {
+        Some(length) => read_section(reader, length).await?,
+        None => Vec::new(),
+    };
+    Ok(Some(Event {
+        kind: header.kind,
+        data,
+        payload,
+    }))
+}
+
+async fn read_section<R: AsyncRead + Unpin>(reader: &mut R, length: usize) -> Result<Vec<u8>, WyomingError> {
+    if length > MAX_SECTION_BYTES {
+        return Err(WyomingError::InvalidEvent(format!(
+            "{length} byte section exceeds the limit"
+        )));
+    }
+    let mut section = vec![0; length];
+    reader.read_exact(&mut section).await?;
+    Ok(section)
+}
+
+pub async fn write_event<W: AsyncWrite + Unpin>(writer: &mut W, event: &Event) -> Result<(), WyomingError> {
+    let data = if event.data.is_empty() {
+        Vec::new()
+    } else {
+        serde_json::to_vec(&event.data).map_err(|err| WyomingError::InvalidEvent(err.to_string()))?
+    };
+    let header = Header {
+        kind: event.kind.clone(),
+        version: Some(PROTOCOL_VERSION.to_string()),
+        data: None,
+        data_length: (!data.is_empty()).then_some(data.len()),
+        payload_length: (!event.payload.is_empty()).then_some(event.payload.len()),
+    };
+    let mut bytes = serde_json::to_vec(&header).map_err(|err| WyomingError::InvalidEvent(err.to_string()))?;
+    bytes.push(b'\n');
+    bytes.extend_from_slice(&data);
+    bytes.extend_from_slice(&event.payload);
+    writer.write_all(&bytes).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Attribution {
+    name: &'static str,
+    url: &'static str,
+}
+
+const PIPER_ATTRIBUTION: Attribution = Attribution {
+    name: "rhasspy",
+    url: "https://github.com/rhasspy/piper",
+};
+
+const ESPEAK_ATTRIBUTION: Attribution = Attribution {
+    name: "eSpeak NG",
+    url: "https://github.com/espeak-ng/espeak-ng",
+};
+
+#[derive(Debug, Clone, Serialize)]
+struct TtsProgram {
+    name: &'static str,
+    description: Option<String>,
+    attribution: Attribution,
+    installed: bool,
+    version: Option<String>,
+    voices: Vec<TtsVoice>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct TtsVoice {
+    name: String,
+    description: Option<String>,
+    attribution: Attribution,
+    installed: bool,
+    version: Option<String>,
+    languages: Vec<String>,
+    speakers: Option<Vec<Value>>,
+}
+
+/// `data` of a `synthesize` event.
+#[derive(Debug, Clone, Default, Deserialize)]
+struct Synthesize {
+    text: String,
+    #[serde(default)]
+    voice: Option<VoiceSelection>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+struct VoiceSelection {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    language: Option<String>,
+    /// Piper speaker id of multi-speaker voices, as a string.
+    #[serde(default)]
+    speaker: Option<String>,
+}
+
+/// Binds the server to the configured address and returns the future that
+/// accepts clients until it is dropped or cancelled. Each connection is
+/// served on its own task.
+pub async fn bind<S>(
+    state: S,
+    config: &WyomingConfig,
+) -> Result<BoxFuture<'static, Result<(), WyomingError>>, WyomingError>
+where
+    S: AsRef<SharedState> + Clone + Send + Sync + 'static,
+{
+    let address = SocketAddr::new(config.host, config.port);
+    let listener = TcpListener::bind(address)
+        .await
+        .map_err(|err| WyomingError::Listen(address, err))?;
+    log::info!("Wyoming server listening on tcp://{address}");
+    Ok(async move {
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            let state = state.clone();
+            async_std::task::spawn(async move {
+                if let Err(err) = serve_client(state.as_ref(), stream).await {
+                    log::warn!("Wyoming client {peer}: {err}");
+                }
+            });
+        }
+    }
+    .boxed())
+}
+
+/// Answers the events of one client until it disconnects.
+pub async fn serve_client(state: &SharedState, stream: TcpStream) -> Result<(), WyomingError> {
+    let mut reader = BufReader::new(stream.clone());
+    let mut writer = stream;
+    while let Some(event) = read_event(&mut reader).await? {
+        match event.kind.as_str() {
+            "describe" => write_event(&mut writer, &info(&state.tts)).await?,
+            "synthesize" => {
+                let request = serde_json::from_value::<Synthesize>(Value::Object(event.data));
+                let stream = match request {
+                    Ok(request) => synthesize(state, &request),
+                    Err(err) => Err(anyhow::anyhow!("invalid synthesize event: {err}")),
+                };
+                let result = match stream {
+                    Ok(stream) => write_audio(&mut writer, stream).await?,
+                    Err(err) => Err(err),
+                };
+                if let Err(err) = result {
+                    log::warn!("Wyoming synthesis failed: {err:#}");
+                    let error = serde_json::json!({ "text": format!("{err:#}"), "code": "synthesis_failed" });
+                    write_event(&mut writer, &Event::new("error", error)).await?;
+                }
+            }
+            "ping" => write_event(&mut writer, &Event::new("pong", Value::Object(event.data))).await?,
+            other => log::debug!("ignoring Wyoming event '{other}'"),
+        }
+    }
+    Ok(())
+}
+
+fn info(engine: &TtsEngine) -> Event {
+    let mut voices = engine.voices();
+    voices.sort_by(|a, b| a.name.cmp(&b.name));
+    let program = TtsProgram {
+        name: "piperdesk",
+        description: Some("Piper and espeak-ng voices with the PiperDesk pronunciation dictionary".into()),
+        attribution: PIPER_ATTRIBUTION,
+        installed: true,
+        version: Some(env!("CARGO_PKG_VERSION").into()),
+        voices: voices
+            .into_iter()
+            .map(|voice| TtsVoice {
+                description: voice.description.or_else(|| Some(voice.name.clone())),
+                attribution: match voice.backend.as_str() {
+                    "espeak" => ESPEAK_ATTRIBUTION,
+                    _ => PIPER_ATTRIBUTION,
+                },
+                name: voice.name,
+                installed: true,
+                version: None,
+                languages: voice.language.into_iter().collect(),
+                speakers: None,
+            })
+            .collect(),
+    };
+    Event::new("info", serde_json::json!({ "tts": [program] }))
+}
+
+/// Starts synthesizing the request after applying the pronunciation
+/// dictionary; audio arrives while the synthesizer is still speaking.
+fn synthesize(state: &SharedState, request: &Synthesize) -> anyhow::Result<PcmStream> {
+    let dictionary_path = state.config.lock().wyoming.dictionary.clone();
+    let dictionary = PronunciationDictionary::load(&dictionary_path)?;
+    let text = dictionary.apply(request.text.trim());
+    let selection = request.voice.clone().unwrap_or_default();
+    let voice = choose_voice(&state.tts, &selection)?;
+    let speaker = selection.speaker.and_then(|speaker| speaker.trim().parse().ok());
+    state.tts.synthesize_stream(&voice, &text, speaker, None)
+}
+
+/// Home Assistant sends the voice name it was offered, a language, or
+/// nothing; unknown names fall back to the language and then the default.
+fn choose_voice(engine: &TtsEngine, selection: &VoiceSelection) -> anyhow::Result<VoiceInfo> {
+    if let Some(voice) = selection
+        .name
+        .as_deref()
+        .and_then(|name| engine.resolve_voice(Some(name)).ok())
+    {
+        return Ok(voice);
+    }
+    if let Some(voice) = selection
+        .language
+        .as_deref()
+        .and_then(|language| engine.voice_for_language(language))
+    {
+        return Ok(voice);
+    }
+    engine.resolve_voice(None)
+}
+
+/// Sends `audio-start` once the first audio is ready, an `audio-chunk` for
+/// every piece of PCM as it arrives and `audio-stop`. Synthesis failures are
+/// returned for the caller to report; connection failures end the client.
+async fn write_audio<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    mut stream: PcmStream,
+) -> Result<anyhow::Result<()>, WyomingError> {
+    let first = match stream.next().await {
+        Some(Ok(first)) => first,
+        Some(Err(err)) => return Ok(Err(err)),
+        None => return Ok(Err(anyhow::anyhow!("no audio was synthesized"))),
+    };
+    let format = serde_json::json!({ "rate": first.sample_rate, "width": 2, "channels": first.channels });
+    write_event(writer, &Event::new("audio-start", format.clone())).await?;
+    let mut pcm = encode_pcm(&first);
+    loop {
+        let event = Event::new("audio-chunk", format.clone()).with_payload(pcm);
+        write_event(writer, &event).await?;
+        pcm = match stream.next().await {
+            Some(Ok(audio)) => encode_pcm(&audio),
+            Some(Err(err)) => return Ok(Err(err)),
+            None => break,
+        };
+    }
+    write_event(writer, &Event::new("audio-stop", Value::Null)).await?;
+    Ok(Ok(()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AppConfig;
+    use std::fs;
+
+    #[test]
+    fn reads_inline_and_separate_data() {
+        async_std::task::block_on(async {
+            let mut bytes = Vec::new();
+            let chunk = Event::new("audio-chunk", serde_json::json!({ "rate": 22050 })).with_payload(vec![1, 0, 2, 0]);
+            write_event(&mut bytes, &chunk).await.unwrap();
+            bytes.extend_from_slice(b"{\"type\": \"synthesize\", \"data\": {\"text\": \"Hola\"}}\n");
+            let mut reader = BufReader::new(&bytes[..]);
+            assert_eq!(read_event(&mut reader).await.unwrap(), Some(chunk));
+            let synthesize = read_event(&mut reader).await.unwrap().unwrap();
+            assert_eq!(synthesize.kind, "synthesize");
+            assert_eq!(synthesize.data["text"], "Hola");
+            assert_eq!(read_event(&mut reader).await.unwrap(), None);
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn serves_voices_and_audio_to_a_client() {
+        use crate::mock::FakePiper;
+
+        let root = std::env::temp_dir().join(format!("piperdesk-wyoming-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&root).unwrap();
+        let piper = FakePiper::default().install(&root).unwrap();
+        let dictionary = root.join("dictionary.json");
+        fs::write(&dictionary, r#"{"HA": "jache a"}"#).unwrap();
+
+        let mut config = AppConfig {
+            piper,
+            ..AppConfig::default()
+        };
+        config.espeak.enabled = false;
+        config.cache.enabled = false;
+        config.cache.directory = root.join("cache");
+        config.wyoming.dictionary = dictionary;
+        let state = SharedState::new(config).unwrap();
+
+        async_std::task::block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let address = listener.local_addr().unwrap();
+            let server = async {
+                let (stream, _) = listener.accept().await.unwrap();
+                serve_client(&state, stream).await.unwrap();
+            };
+            let client = async {
+                let stream = TcpStream::connect(address).await.unwrap();
+                let mut reader = BufReader::new(stream.clone());
+                let mut writer = stream.clone();
+                write_event(&mut writer, &Event::new("describe", Value::Null))
+                    .await
+                    .unwrap();
+                let info = read_event(&mut reader).await.unwrap().unwrap();
+                assert_eq!(info.kind, "info");
+                let voice = &info.data["tts"][0]["voices"][0];
+                assert_eq!(voice["name"], FakePiper::VOICE);
+                assert_eq!(voice["languages"], serde_json::json!(["es-ES"]));
+
+                let request = serde_json::json!({ "text": "Hola HA. Adiós.", "voice": { "language": "es" } });
+                write_event(&mut writer, &Event::new("synthesize", request))
+                    .await
+                    .unwrap();
+                let start = read_event(&mut reader).await.unwrap().unwrap();
+                assert_eq!(start.kind, "audio-start");
+                assert_eq!(start.data["rate"], 22050);
+                let mut received = 0;
+                loop {
+                    let event = read_event(&mut reader).await.unwrap().unwrap();
+                    match event.kind.as_str() {
+                        "audio-chunk" => received += event.payload.len(),
+                        "audio-stop" => break,
+                        other => panic!("unexpected event {other}"),
+                    }
+                }
+                // One clip per sentence, two bytes per sample.
+                assert_eq!(received, 2 * FakePiper::clip().samples.len() * 2);
+                stream.shutdown(std::net::Shutdown::Both).unwrap();
+            };
+            futures::join!(server, client);
+        });
+        let log = FakePiper::log(&root);
+        assert!(log.iter().any(|line| line.contains("Hola jache a.")), "{log:?}");
+        let _ = fs::remove_dir_all(&root);
+    }
+}
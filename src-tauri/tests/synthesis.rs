@@ -0,0 +1,633 @@
+//! Queue synthesis and export end to end, with the mock backend in place of
+//! Piper and, on Unix, a fake `piper` executable and a failing ffmpeg.
+
+use async_std::channel::Receiver;
+use async_std::task::block_on;
+use piperdesk::audio::{encode_pcm, read_wav, streaming_wav_header, AudioFormat};
+use piperdesk::cache::{CacheConfig, SynthesisCache};
+use piperdesk::captions::CaptionOptions;
+use piperdesk::mock::{MockBackend, MOCK_SAMPLE_RATE, MOCK_VOICE};
+use piperdesk::queue::{self, ExportOptions, NewQueueItem, QueueError, QueueEvent};
+use piperdesk::retry::{self, FailureKind, RetryConfig};
+use piperdesk::server::{self, ServerConfig};
+use piperdesk::{AppConfig, QueueItem, QueueStatus, SharedState};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+fn scratch_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("piperdesk-it-{}", uuid::Uuid::new_v4()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn config(root: &Path) -> AppConfig {
+    let mut config = AppConfig {
+        export_dir: root.join("export"),
+        cache: CacheConfig {
+            enabled: false,
+            directory: root.join("cache"),
+            ..CacheConfig::default()
+        },
+        retry: RetryConfig {
+            max_attempts: 3,
+            initial_delay_ms: 5,
+            max_delay_ms: 20,
+        },
+        ..AppConfig::default()
+    };
+    config.espeak.enabled = false;
+    config
+}
+
+fn mock_state(mock: &MockBackend, root: &Path) -> SharedState {
+    let config = config(root);
+    let cache = Arc::new(SynthesisCache::open(config.cache.clone()));
+    SharedState::with_engine(config, mock.engine(), cache)
+}
+
+fn enqueue(state: &SharedState, texts: &[&str], format: AudioFormat) -> Vec<QueueItem> {
+    let items = texts
+        .iter()
+        .enumerate()
+        .map(|(index, text)| NewQueueItem {
+            title: format!("item {index}"),
+            text: text.to_string(),
+            voice: None,
+            rate: None,
+            format: Some(format),
+        })
+        .collect();
+    queue::enqueue_text(state, items).unwrap()
+}
+
+fn status(state: &SharedState, item: &QueueItem) -> QueueStatus {
+    queue::get_item(state, item.id).unwrap().status
+}
+
+fn failure(state: &SharedState, item: &QueueItem) -> (FailureKind, u32) {
+    let item = queue::get_item(state, item.id).unwrap();
+    (item.error.expect("item has an error").kind, item.attempts)
+}
+
+/// Synthesizes the next item, and again whenever a retry is due, like
+/// `piperdesk queue run`.
+fn synthesize_with_retries(state: &SharedState) -> Result<QueueItem, String> {
+    loop {
+        let result = block_on(queue::synthesize_next(state));
+        match queue::next_retry_at(state) {
+            Some(at) if result.is_err() => block_on(retry::sleep_until(at)),
+            _ => return result,
+        }
+    }
+}
+
+fn drain(events: &Receiver<QueueEvent>) -> Vec<QueueEvent> {
+    std::iter::from_fn(|| events.try_recv().ok()).collect()
+}
+
+/// Audio files in `dir`, ignoring timing maps and captions.
+fn audio_files(dir: &Path) -> Vec<PathBuf> {
+    fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .map(|entry| entry.unwrap().path())
+                .filter(|path| matches!(path.extension().and_then(|ext| ext.to_str()), Some("wav" | "mp3")))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[test]
+fn synthesizes_the_oldest_pending_item() {
+    let root = scratch_dir();
+    let mock = MockBackend::new();
+    let state = mock_state(&mock, &root);
+    let items = enqueue(&state, &["Primera frase. Segunda frase.", "Otra."], AudioFormat::Wav);
+    let events = state.events.subscribe();
+
+    let done = block_on(queue::synthesize_next(&state)).unwrap();
+    assert_eq!(done.id, items[0].id);
+    assert_eq!(done.voice, MOCK_VOICE);
+    assert_eq!(status(&state, &items[0]), QueueStatus::Completed);
+    assert_eq!(status(&state, &items[1]), QueueStatus::Pending);
+    assert_eq!(mock.requests(), ["Primera frase.", "Segunda frase."]);
+
+    let output = done.output.clone().unwrap();
+    assert!(output.starts_with(root.join("export")));
+    let first = MockBackend::clip("Primera frase.").duration_ms();
+    let second = MockBackend::clip("Segunda frase.").duration_ms();
+    assert_eq!(read_wav(&output).unwrap().duration_ms(), first + second);
+    let timings = queue::timing_map(&state, done.id).unwrap();
+    assert_eq!(timings.segments.len(), 2);
+    assert_eq!(timings.segments[1].start_ms, first);
+    let span = queue::text_span(&state, done.id, first + 10).unwrap().unwrap();
+    assert_eq!(span.sentence_char_start, "Primera frase. ".len());
+
+    let events = drain(&events);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].name(), "queue::completed");
+    assert_eq!(events[0].payload()["id"], done.id.to_string());
+    assert_eq!(events[0].payload()["status"], "completed");
+    let _ = fs::remove_dir_all(&root);
+}
+
+#[test]
+fn failed_synthesis_fails_the_item_and_leaves_no_audio() {
+    let root = scratch_dir();
+    let mock = MockBackend::new();
+    mock.fail_on("rota");
+    let state = mock_state(&mock, &root);
+    let items = enqueue(&state, &["Bien. Esta frase está rota."], AudioFormat::Wav);
+    let events = state.events.subscribe();
+
+    let err = synthesize_with_retries(&state).unwrap_err();
+    assert!(err.contains("injected failure"), "{err}");
+    assert_eq!(status(&state, &items[0]), QueueStatus::Failed);
+    assert_eq!(failure(&state, &items[0]), (FailureKind::ProcessCrash, 3));
+    assert_eq!(mock.requests().len(), 3 * 2);
+    assert!(audio_files(&root.join("export")).is_empty());
+
+    // A retry is announced as a change, the final failure once.
+    let events = drain(&events);
+    let names: Vec<&str> = events.iter().map(QueueEvent::name).collect();
+    assert_eq!(names, ["queue::changed", "queue::changed", "queue::failed"]);
+    assert_eq!(events[2].payload(), serde_json::json!([items[0].id, err]));
+
+    // Retrying by hand starts a new round of automatic retries.
+    queue::retry_item(&state, items[0].id).unwrap();
+    let err = block_on(queue::synthesize_next(&state)).unwrap_err();
+    assert!(err.contains("retrying at"), "{err}");
+    assert_eq!(failure(&state, &items[0]), (FailureKind::ProcessCrash, 1));
+    let _ = fs::remove_dir_all(&root);
+}
+
+#[test]
+fn retries_transient_failures_until_they_succeed() {
+    let root = scratch_dir();
+    let mock = MockBackend::new();
+    mock.fail_next(1);
+    let state = mock_state(&mock, &root);
+    state.config.lock().retry.initial_delay_ms = 200;
+    let items = enqueue(&state, &["Tercera va la vencida.", "Otra."], AudioFormat::Wav);
+
+    // The failed item waits while the rest of the queue goes on.
+    let err = block_on(queue::synthesize_next(&state)).unwrap_err();
+    assert!(err.contains("retrying at"), "{err}");
+    let waiting = queue::get_item(&state, items[0].id).unwrap();
+    assert_eq!(waiting.status, QueueStatus::Pending);
+    assert_eq!(waiting.error.map(|error| error.kind), Some(FailureKind::ProcessCrash));
+    assert_eq!(queue::next_retry_at(&state), waiting.next_retry_at);
+    assert_eq!(block_on(queue::synthesize_next(&state)).unwrap().id, items[1].id);
+    let err = block_on(queue::synthesize_next(&state)).unwrap_err();
+    assert!(err.starts_with("No pending items until"), "{err}");
+
+    mock.fail_next(1);
+    let done = synthesize_with_retries(&state).unwrap();
+    assert_eq!(done.id, items[0].id);
+    assert_eq!(done.status, QueueStatus::Completed);
+    assert_eq!(done.attempts, 3);
+    assert_eq!(done.error, None);
+    assert_eq!(done.next_retry_at, None);
+    assert!(done.last_attempt_at.is_some());
+    assert_eq!(queue::get_item(&state, items[0].id).unwrap().attempts, 3);
+    assert_eq!(mock.requests().len(), 4);
+    let _ = fs::remove_dir_all(&root);
+}
+
+#[test]
+fn due_retries_run_without_being_asked_for() {
+    let root = scratch_dir();
+    let mock = MockBackend::new();
+    mock.fail_next(1);
+    let state = mock_state(&mock, &root);
+    state.config.lock().retry.initial_delay_ms = 100;
+    let items = enqueue(&state, &["Hola.", "Otra."], AudioFormat::Wav);
+    block_on(queue::synthesize_next(&state)).unwrap_err();
+    let events = state.events.subscribe();
+
+    let completed = async {
+        while let Ok(event) = events.recv().await {
+            if let QueueEvent::Completed(item) = event {
+                return *item;
+            }
+        }
+        unreachable!("queue events closed")
+    };
+    let done = block_on(async_std::future::timeout(
+        Duration::from_secs(10),
+        futures::future::select(Box::pin(queue::run_due_retries(&state)), Box::pin(completed)),
+    ))
+    .expect("retry did not run");
+    let futures::future::Either::Right((done, _)) = done else {
+        panic!("retry worker stopped");
+    };
+    assert_eq!(done.id, items[0].id);
+    assert_eq!(done.attempts, 2);
+    assert_eq!(status(&state, &items[0]), QueueStatus::Completed);
+    // Items that never failed are left for synthesize_next.
+    assert_eq!(status(&state, &items[1]), QueueStatus::Pending);
+    let _ = fs::remove_dir_all(&root);
+}
+
+#[test]
+fn items_waiting_for_a_retry_can_be_cancelled() {
+    let root = scratch_dir();
+    let mock = MockBackend::new();
+    mock.fail_next(1);
+    let state = mock_state(&mock, &root);
+    state.config.lock().retry.initial_delay_ms = 60_000;
+    let items = enqueue(&state, &["Hola."], AudioFormat::Wav);
+
+    block_on(queue::synthesize_next(&state)).unwrap_err();
+    assert!(queue::retry_scheduled(&state, items[0].id).is_some());
+    let cancelled = queue::cancel_item(&state, items[0].id).unwrap();
+    assert_eq!(cancelled.status, QueueStatus::Cancelled);
+    assert_eq!(cancelled.next_retry_at, None);
+    assert_eq!(queue::next_retry_at(&state), None);
+    assert_eq!(
+        block_on(queue::synthesize_next(&state)).unwrap_err(),
+        "No pending items"
+    );
+    let _ = fs::remove_dir_all(&root);
+}
+
+#[test]
+fn retries_failed_items_on_request() {
+    let root = scratch_dir();
+    let mock = MockBackend::new();
+    let state = mock_state(&mock, &root);
+    let mut items = enqueue(&state, &["Sin voz."], AudioFormat::Wav);
+    items.extend(
+        queue::enqueue_text(
+            &state,
+            vec![NewQueueItem {
+                title: "otra".into(),
+                text: "Tampoco.".into(),
+                voice: Some("no-existe".into()),
+                rate: None,
+                format: None,
+            }],
+        )
+        .unwrap(),
+    );
+    queue::cancel_item(&state, items[0].id).unwrap();
+
+    // A missing voice will not appear by itself: no retries.
+    let err = block_on(queue::synthesize_next(&state)).unwrap_err();
+    assert!(err.contains("no-existe"), "{err}");
+    assert_eq!(failure(&state, &items[1]), (FailureKind::VoiceMissing, 1));
+    assert!(mock.requests().is_empty());
+
+    assert!(matches!(
+        queue::retry_item(&state, items[0].id),
+        Err(QueueError::NotRetryable(_, QueueStatus::Cancelled))
+    ));
+    let retried = queue::retry_item(&state, items[1].id).unwrap();
+    assert_eq!(retried.status, QueueStatus::Pending);
+    assert_eq!(retried.attempts, 0);
+    assert_eq!(retried.error.map(|error| error.kind), Some(FailureKind::VoiceMissing));
+    block_on(queue::synthesize_next(&state)).unwrap_err();
+    assert_eq!(failure(&state, &items[1]), (FailureKind::VoiceMissing, 1));
+
+    let retried = queue::retry_failed(&state);
+    assert_eq!(retried.len(), 1);
+    assert_eq!(status(&state, &items[1]), QueueStatus::Pending);
+    assert!(queue::retry_failed(&state).is_empty());
+    let _ = fs::remove_dir_all(&root);
+}
+
+#[test]
+fn skips_cancelled_items() {
+    let root = scratch_dir();
+    let mock = MockBackend::new();
+    let state = mock_state(&mock, &root);
+    let items = enqueue(&state, &["Uno.", "Dos."], AudioFormat::Wav);
+    let events = state.events.subscribe();
+
+    assert_eq!(
+        queue::cancel_item(&state, items[0].id).unwrap().status,
+        QueueStatus::Cancelled
+    );
+    assert_eq!(block_on(queue::synthesize_next(&state)).unwrap().id, items[1].id);
+    assert_eq!(
+        block_on(queue::synthesize_next(&state)).unwrap_err(),
+        "No pending items"
+    );
+    assert_eq!(status(&state, &items[0]), QueueStatus::Cancelled);
+    assert_eq!(mock.requests(), ["Dos."]);
+
+    let names: Vec<&str> = drain(&events).iter().map(QueueEvent::name).collect();
+    assert_eq!(names, ["queue::changed", "queue::completed"]);
+    let _ = fs::remove_dir_all(&root);
+}
+
+#[test]
+fn items_being_synthesized_run_to_completion() {
+    let root = scratch_dir();
+    let mock = MockBackend::new();
+    mock.set_delay(Duration::from_millis(100));
+    let state = mock_state(&mock, &root);
+    let items = enqueue(&state, &["Lento."], AudioFormat::Wav);
+
+    let (done, cancelled) = block_on(futures::future::join(queue::synthesize_next(&state), async {
+        async_std::task::sleep(Duration::from_millis(20)).await;
+        queue::cancel_item(&state, items[0].id)
+    }));
+    assert!(matches!(
+        cancelled,
+        Err(QueueError::NotCancellable(id, QueueStatus::Processing)) if id == items[0].id
+    ));
+    assert_eq!(done.unwrap().status, QueueStatus::Completed);
+    let _ = fs::remove_dir_all(&root);
+}
+
+#[test]
+fn exports_audio_and_captions_without_changing_the_status() {
+    let root = scratch_dir();
+    let mock = MockBackend::new();
+    let state = mock_state(&mock, &root);
+    let items = enqueue(&state, &["Hola. ¿Qué tal?"], AudioFormat::Wav);
+    block_on(queue::synthesize_next(&state)).unwrap();
+
+    let directory = root.join("otra");
+    let path = block_on(queue::export_item(
+        &state,
+        ExportOptions {
+            item_id: items[0].id,
+            format: AudioFormat::Wav,
+            speed: Some(1.5),
+            voice: None,
+            directory: Some(directory.clone()),
+            captions: Some(CaptionOptions::default()),
+        },
+    ))
+    .unwrap();
+    assert!(path.starts_with(&directory));
+    assert!(read_wav(&path).unwrap().duration_ms() > 0);
+    assert!(path.with_extension("srt").exists());
+    assert!(path.with_extension("vtt").exists());
+    let stored = queue::get_item(&state, items[0].id).unwrap();
+    assert_eq!(stored.status, QueueStatus::Completed);
+    assert_eq!(stored.output, Some(path));
+
+    let missing = ExportOptions {
+        item_id: uuid::Uuid::new_v4(),
+        format: AudioFormat::Wav,
+        speed: None,
+        voice: None,
+        directory: None,
+        captions: None,
+    };
+    assert_eq!(
+        block_on(queue::export_item(&state, missing)).unwrap_err(),
+        "Item not found"
+    );
+    let _ = fs::remove_dir_all(&root);
+}
+
+#[test]
+fn export_with_an_unknown_voice_fails() {
+    let root = scratch_dir();
+    let mock = MockBackend::new();
+    let state = mock_state(&mock, &root);
+    let items = enqueue(&state, &["Hola."], AudioFormat::Wav);
+    let err = block_on(queue::export_item(
+        &state,
+        ExportOptions {
+            item_id: items[0].id,
+            format: AudioFormat::Wav,
+            speed: None,
+            voice: Some("no-existe".into()),
+            directory: None,
+            captions: None,
+        },
+    ))
+    .unwrap_err();
+    assert!(err.contains("no-existe"), "{err}");
+    assert_eq!(status(&state, &items[0]), QueueStatus::Pending);
+    assert!(mock.requests().is_empty());
+    let _ = fs::remove_dir_all(&root);
+}
+
+#[test]
+fn streams_speech_over_the_openai_api() {
+    let root = scratch_dir();
+    let mock = MockBackend::new();
+    let state = Arc::new(mock_state(&mock, &root));
+    let server_config = ServerConfig {
+        token: "secreto".into(),
+        ..ServerConfig::default()
+    };
+    let app = server::app(state, &server_config);
+    let speech = |format: &str| {
+        let url = tide::http::Url::parse("http://127.0.0.1/v1/audio/speech").unwrap();
+        let mut request = tide::http::Request::new(tide::http::Method::Post, url);
+        request.insert_header("Authorization", "Bearer secreto");
+        request.set_content_type(tide::http::mime::JSON);
+        request.set_body(format!(
+            r#"{{"input": "Primera frase. Segunda.", "voice": "alloy", "response_format": "{format}"}}"#
+        ));
+        request
+    };
+    block_on(async {
+        let pcm = [
+            encode_pcm(&MockBackend::clip("Primera frase.")),
+            encode_pcm(&MockBackend::clip("Segunda.")),
+        ]
+        .concat();
+        let mut response: tide::http::Response = app.respond(speech("pcm")).await.unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.body_bytes().await.unwrap(), pcm);
+
+        let mut response: tide::http::Response = app.respond(speech("wav")).await.unwrap();
+        assert_eq!(response.content_type().unwrap().essence(), "audio/wav");
+        let wav = response.body_bytes().await.unwrap();
+        assert_eq!(wav[..44], streaming_wav_header(MOCK_SAMPLE_RATE, 1)[..]);
+        assert_eq!(wav[44..], pcm[..]);
+
+        mock.fail_on("Primera");
+        let response: tide::http::Response = app.respond(speech("wav")).await.unwrap();
+        assert_eq!(response.status(), 500);
+    });
+    let _ = fs::remove_dir_all(&root);
+}
+
+#[cfg(unix)]
+mod process {
+    use super::*;
+    use futures::TryStreamExt;
+    use piperdesk::mock::FakePiper;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn piper_state(piper: FakePiper, root: &Path) -> SharedState {
+        let mut config = config(root);
+        config.piper = piper.install(root).unwrap();
+        SharedState::new(config).unwrap()
+    }
+
+    #[test]
+    fn synthesizes_through_the_piper_process() {
+        let root = scratch_dir();
+        let state = piper_state(FakePiper::default(), &root);
+        enqueue(&state, &["Hola. Adiós."], AudioFormat::Wav);
+
+        let done = block_on(queue::synthesize_next(&state)).unwrap();
+        let audio = read_wav(&done.output.unwrap()).unwrap();
+        assert_eq!(audio.samples.len(), 2 * FakePiper::clip().samples.len());
+        let log = FakePiper::log(&root);
+        assert_eq!(log.len(), 2);
+        assert!(log[0].contains("\"text\":\"Hola.\""), "{}", log[0]);
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn long_texts_do_not_block_on_piper_logs() {
+        let root = scratch_dir();
+        let state = piper_state(
+            FakePiper {
+                stderr_per_line: 1_024,
+                ..FakePiper::default()
+            },
+            &root,
+        );
+        // Well over a pipe buffer of input and of stderr.
+        let sentence = format!("{}fin.", "palabra ".repeat(50));
+        let text = vec![sentence.as_str(); 300].join(" ");
+        enqueue(&state, &[&text], AudioFormat::Wav);
+
+        let done = block_on(async_std::future::timeout(
+            Duration::from_secs(30),
+            queue::synthesize_next(&state),
+        ))
+        .expect("Piper run deadlocked")
+        .unwrap();
+        assert_eq!(done.status, QueueStatus::Completed);
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn streams_raw_audio_from_the_piper_process() {
+        let root = scratch_dir();
+        let state = piper_state(FakePiper::default(), &root);
+        let voice = state.tts.resolve_voice(None).unwrap();
+        let chunks: Vec<_> = block_on(
+            state
+                .tts
+                .synthesize_stream(&voice, "Hola. Adiós.", None, None)
+                .unwrap()
+                .try_collect(),
+        )
+        .unwrap();
+        let samples: usize = chunks.iter().map(|chunk| chunk.samples.len()).sum();
+        assert_eq!(samples, 2 * FakePiper::clip().samples.len());
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn piper_failures_fail_the_item() {
+        let root = scratch_dir();
+        let state = piper_state(
+            FakePiper {
+                exit_code: Some(3),
+                ..FakePiper::default()
+            },
+            &root,
+        );
+        let items = enqueue(&state, &["Hola."], AudioFormat::Wav);
+        let err = synthesize_with_retries(&state).unwrap_err();
+        assert!(err.contains("fake piper crashed"), "{err}");
+        assert_eq!(status(&state, &items[0]), QueueStatus::Failed);
+        assert_eq!(failure(&state, &items[0]), (FailureKind::ProcessCrash, 3));
+        let _ = fs::remove_dir_all(&root);
+
+        let root = scratch_dir();
+        let state = piper_state(
+            FakePiper {
+                fail_on: Some("Adiós".into()),
+                ..FakePiper::default()
+            },
+            &root,
+        );
+        enqueue(&state, &["Hola. Adiós."], AudioFormat::Wav);
+        let err = synthesize_with_retries(&state).unwrap_err();
+        assert!(err.contains("fake piper failed"), "{err}");
+        assert_eq!(FakePiper::log(&root).len(), 3 * 2);
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn a_missing_piper_binary_is_not_retried() {
+        let root = scratch_dir();
+        let mut config = config(&root);
+        config.piper = FakePiper::default().install(&root).unwrap();
+        config.piper.binary_path = root.join("no-piper");
+        let state = SharedState::new(config).unwrap();
+        let items = enqueue(&state, &["Hola."], AudioFormat::Wav);
+        let err = block_on(queue::synthesize_next(&state)).unwrap_err();
+        assert!(err.contains("failed to spawn Piper"), "{err}");
+        assert_eq!(failure(&state, &items[0]), (FailureKind::BinaryMissing, 1));
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn hung_piper_runs_are_killed_and_retried() {
+        let root = scratch_dir();
+        let mut config = config(&root);
+        config.piper = FakePiper {
+            delay: Duration::from_secs(10),
+            ..FakePiper::default()
+        }
+        .install(&root)
+        .unwrap();
+        config.piper.timeout_secs = 1;
+        let state = SharedState::new(config).unwrap();
+        let items = enqueue(&state, &["Hola."], AudioFormat::Wav);
+
+        let started = std::time::Instant::now();
+        let err = block_on(queue::synthesize_next(&state)).unwrap_err();
+        assert!(started.elapsed() < Duration::from_secs(5), "{:?}", started.elapsed());
+        assert!(err.contains("did not finish within 1 seconds"), "{err}");
+        assert!(err.contains("retrying at"), "{err}");
+        assert_eq!(failure(&state, &items[0]), (FailureKind::Timeout, 1));
+        assert!(queue::retry_scheduled(&state, items[0].id).is_some());
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn mp3_transcoding_failures_fail_the_item() {
+        let root = scratch_dir();
+        let mock = MockBackend::new();
+        let state = mock_state(&mock, &root);
+        let ffmpeg = root.join("ffmpeg");
+        fs::write(&ffmpeg, "#!/bin/sh\necho 'Unknown encoder libmp3lame' >&2\nexit 1\n").unwrap();
+        fs::set_permissions(&ffmpeg, fs::Permissions::from_mode(0o755)).unwrap();
+        state.config.lock().ffmpeg.binary_path = Some(ffmpeg);
+        let items = enqueue(&state, &["Hola."], AudioFormat::Mp3);
+        let events = state.events.subscribe();
+
+        let err = block_on(queue::synthesize_next(&state)).unwrap_err();
+        assert!(err.starts_with("MP3 export failed"), "{err}");
+        assert!(err.contains("Unknown encoder"), "{err}");
+        assert_eq!(status(&state, &items[0]), QueueStatus::Failed);
+        assert_eq!(failure(&state, &items[0]), (FailureKind::Transcode, 1));
+        assert!(audio_files(&root.join("export")).is_empty());
+        assert_eq!(drain(&events)[0].name(), "queue::failed");
+
+        let err = block_on(queue::export_item(
+            &state,
+            ExportOptions {
+                item_id: items[0].id,
+                format: AudioFormat::Mp3,
+                speed: None,
+                voice: None,
+                directory: None,
+                captions: None,
+            },
+        ))
+        .unwrap_err();
+        assert!(err.starts_with("MP3 export failed"), "{err}");
+        let _ = fs::remove_dir_all(&root);
+    }
+}
@@ -5,24 +5,6 @@ from types import SimpleNamespace
 
 import pytest
 
-from scripts import import_epub
-
-
-def create_epub(tmp_path: Path) -> Path:
-    epub_path = tmp_path / 'sample.epub'
-    import zipfile
-
-    with zipfile.ZipFile(epub_path, 'w') as zf:
-        zf.writestr('mimetype', 'application/epub+zip')
-        zf.writestr('content.xhtml', '<html><body><h1>Título</h1><p>Hola mundo</p></body></html>')
-    return epub_path
-
-
-def test_extract_epub_text(tmp_path: Path) -> None:
-    epub_path = create_epub(tmp_path)
-    content = import_epub.extract_epub_text(epub_path)
-    assert 'Hola mundo' in content
-
 
 def test_extract_pdf_text(monkeypatch, tmp_path: Path) -> None:
     pdf_path = tmp_path / 'doc.pdf'
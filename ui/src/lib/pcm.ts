@@ -0,0 +1,57 @@
+import type { PcmAudio } from '$lib/types';
+
+function decode(chunk: PcmAudio, audio: AudioContext) {
+  const bytes = Uint8Array.from(atob(chunk.pcm), (c) => c.charCodeAt(0));
+  const samples = new Int16Array(bytes.buffer, 0, bytes.length >> 1);
+  const frames = samples.length / chunk.channels;
+  const buffer = audio.createBuffer(chunk.channels, frames, chunk.sampleRate);
+  for (let channel = 0; channel < chunk.channels; channel++) {
+    const data = buffer.getChannelData(channel);
+    for (let frame = 0; frame < frames; frame++) {
+      data[frame] = samples[frame * chunk.channels + channel] / 32768;
+    }
+  }
+  return buffer;
+}
+
+/** Plays PCM chunks back to back as they arrive from the backend. */
+export class PcmScheduler {
+  private context: AudioContext | null = null;
+  private playhead = 0;
+  private sources: AudioBufferSourceNode[] = [];
+
+  /** Must be called from a user gesture before audio can be heard. */
+  async resume() {
+    this.context ??= new AudioContext();
+    await this.context.resume();
+  }
+
+  /** Queues `chunk` right after the audio scheduled so far. */
+  play(chunk: PcmAudio) {
+    if (!this.context) {
+      return;
+    }
+    const source = this.context.createBufferSource();
+    source.buffer = decode(chunk, this.context);
+    source.connect(this.context.destination);
+    this.playhead = Math.max(this.playhead, this.context.currentTime);
+    source.start(this.playhead);
+    this.playhead += source.buffer.duration;
+    this.sources.push(source);
+    source.onended = () => {
+      this.sources = this.sources.filter((s) => s !== source);
+    };
+  }
+
+  /** Seconds of scheduled audio still to be heard. */
+  remaining() {
+    return this.context ? Math.max(0, this.playhead - this.context.currentTime) : 0;
+  }
+
+  /** Silences everything scheduled. */
+  stop() {
+    this.sources.forEach((source) => source.stop());
+    this.sources = [];
+    this.playhead = this.context?.currentTime ?? 0;
+  }
+}
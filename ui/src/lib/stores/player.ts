@@ -0,0 +1,95 @@
+import { writable } from 'svelte/store';
+import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
+import type { Bookmark, PcmAudio, PlaybackStatus, PlayerCommand } from '$lib/types';
+import { PcmScheduler } from '$lib/pcm';
+import { lastError, loadQueue } from './queue';
+
+export const playback = writable<PlaybackStatus>({
+  state: 'stopped',
+  itemId: null,
+  positionMs: 0,
+  durationMs: 0,
+  volume: 1,
+  speed: 1
+});
+
+export const bookmarks = writable<Bookmark[]>([]);
+
+const scheduler = new PcmScheduler();
+
+function report(err: unknown) {
+  lastError.set(err instanceof Error ? err.message : String(err));
+}
+
+/** Follows the playback engine; the returned function stops listening. */
+export async function registerPlayerListeners() {
+  playback.set(await invoke<PlaybackStatus>('get_player_status'));
+  await loadBookmarks();
+  const unlisteners = await Promise.all([
+    listen<PcmAudio>('player::audio', (event) => scheduler.play(event.payload)),
+    listen('player::clear', () => scheduler.stop()),
+    listen<PlaybackStatus>('player::state', (event) => {
+      playback.set(event.payload);
+      // Pausing and stopping record where each item was left.
+      if (event.payload.state !== 'playing') {
+        loadQueue();
+      }
+    }),
+    listen<PlaybackStatus>('player::position', (event) => playback.set(event.payload)),
+    listen<[string, string]>('player::failed', (event) => lastError.set(event.payload[1]))
+  ]);
+  return () => unlisteners.forEach((unlisten) => unlisten());
+}
+
+export async function control(command: PlayerCommand) {
+  if (command.action === 'play' || command.action === 'resume') {
+    await scheduler.resume();
+  }
+  try {
+    await invoke('control_player', { command });
+  } catch (err) {
+    report(err);
+  }
+}
+
+export async function loadBookmarks() {
+  bookmarks.set(await invoke<Bookmark[]>('list_bookmarks', {}));
+}
+
+export async function addBookmark(itemId: string, positionMs: number, name?: string, note?: string) {
+  try {
+    await invoke('add_bookmark', { bookmark: { itemId, positionMs, name, note } });
+    await loadBookmarks();
+  } catch (err) {
+    report(err);
+  }
+}
+
+export async function removeBookmark(id: string) {
+  try {
+    await invoke('remove_bookmark', { id });
+    await loadBookmarks();
+  } catch (err) {
+    report(err);
+  }
+}
+
+export async function jumpToBookmark(id: string) {
+  await scheduler.resume();
+  try {
+    await invoke('jump_to_bookmark', { id });
+  } catch (err) {
+    report(err);
+  }
+}
+
+/** Continues a project where it was left. */
+export async function resumeProject(project: string) {
+  await scheduler.resume();
+  try {
+    await invoke('resume_project', { project });
+  } catch (err) {
+    report(err);
+  }
+}
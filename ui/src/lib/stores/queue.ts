@@ -1,7 +1,16 @@
 import { writable } from 'svelte/store';
 import { invoke } from '@tauri-apps/api/core';
 import { listen } from '@tauri-apps/api/event';
-import type { QueueItem, VoiceInfo } from '$lib/types';
+import type {
+  CaptionOptions,
+  FileImportResult,
+  HotFolderEvent,
+  ImportedDocument,
+  ImportFolderOptions,
+  QueueItem,
+  TextSpan,
+  VoiceInfo
+} from '$lib/types';
 
 export const queue = writable<QueueItem[]>([]);
 export const voices = writable<VoiceInfo[]>([]);
@@ -33,11 +42,41 @@ export async function enqueue(title: string, text: string, voice?: string, rate?
   await loadQueue();
 }
 
+export async function enqueueDocument(
+  document: ImportedDocument,
+  options: { project?: string; voice?: string; rate?: number } = {}
+) {
+  const items = await invoke<QueueItem[]>('enqueue_document', { document, options });
+  await loadQueue();
+  return items;
+}
+
+export async function importFolder(path: string, options: ImportFolderOptions = {}) {
+  const report = await invoke<FileImportResult[]>('import_folder', { path, options });
+  await loadQueue();
+  return report;
+}
+
 export async function remove(id: string) {
   await invoke('remove_from_queue', { id });
   await loadQueue();
 }
 
+export async function cancel(id: string) {
+  await invoke('cancel_queue_item', { id });
+  await loadQueue();
+}
+
+export async function retry(id: string) {
+  await invoke('retry_item', { id });
+  await loadQueue();
+}
+
+export async function retryFailed() {
+  await invoke('retry_failed');
+  await loadQueue();
+}
+
 export async function clear() {
   await invoke('clear_queue');
   await loadQueue();
@@ -65,16 +104,28 @@ export async function registerListeners() {
   const unlistenFailed = await listen('queue::failed', async () => {
     await loadQueue();
   });
+  // Also fired when the HTTP API changes the queue.
+  const unlistenChanged = await listen('queue::changed', async () => {
+    await loadQueue();
+  });
+  const unlistenHotFolder = await listen<HotFolderEvent>('hotfolder::processed', async (event) => {
+    if (event.payload.error) {
+      lastError.set(`${event.payload.source}: ${event.payload.error}`);
+    }
+    await loadQueue();
+  });
   return () => {
     unlistenCompleted();
     unlistenFailed();
+    unlistenChanged();
+    unlistenHotFolder();
   };
 }
 
 export async function exportAudio(
   itemId: string,
   format: 'wav' | 'mp3',
-  options: { voice?: string; speed?: number; directory?: string } = {}
+  options: { voice?: string; speed?: number; directory?: string; captions?: CaptionOptions } = {}
 ) {
   await invoke('export_audio', {
     options: {
@@ -82,8 +133,13 @@ export async function exportAudio(
       format,
       voice: options.voice,
       speed: options.speed,
-      directory: options.directory
+      directory: options.directory,
+      captions: options.captions
     }
   });
   await loadQueue();
 }
+
+export async function getTextSpan(itemId: string, positionMs: number) {
+  return invoke<TextSpan | null>('get_text_span', { itemId, positionMs: Math.floor(positionMs) });
+}
@@ -0,0 +1,98 @@
+import { writable } from 'svelte/store';
+import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
+import type { SpeechChunk } from '$lib/types';
+import { PcmScheduler } from '$lib/pcm';
+import { lastError } from './queue';
+
+/** Id of the stream being played, if any. */
+export const speaking = writable<string | null>(null);
+
+const scheduler = new PcmScheduler();
+let current: string | null = null;
+let unlisten: (() => void) | null = null;
+// Events can arrive before `stream_speech` has returned the id.
+let starting = false;
+const early: (() => void)[] = [];
+
+function whenStarted(handler: () => void) {
+  if (starting) {
+    early.push(handler);
+  } else {
+    handler();
+  }
+}
+
+/** Queues each chunk right after the previous one as it arrives. */
+function play(chunk: SpeechChunk) {
+  if (chunk.id === current) {
+    scheduler.play(chunk);
+  }
+}
+
+async function ensureListeners() {
+  if (unlisten) {
+    return;
+  }
+  const unlistenChunk = await listen<SpeechChunk>('speech::chunk', (event) =>
+    whenStarted(() => play(event.payload))
+  );
+  const unlistenFinished = await listen<string>('speech::finished', (event) =>
+    whenStarted(() => finished(event.payload))
+  );
+  const unlistenFailed = await listen<[string, string]>('speech::failed', (event) =>
+    whenStarted(() => failed(...event.payload))
+  );
+  unlisten = () => {
+    unlistenChunk();
+    unlistenFinished();
+    unlistenFailed();
+  };
+}
+
+function finished(id: string) {
+  if (id === current) {
+    // Clear the flag once the scheduled audio has played out.
+    setTimeout(() => {
+      if (current === id) {
+        current = null;
+        speaking.set(null);
+      }
+    }, scheduler.remaining() * 1000);
+  }
+}
+
+function failed(id: string, error: string) {
+  if (id === current) {
+    lastError.set(error);
+    current = null;
+    speaking.set(null);
+  }
+}
+
+/** Starts reading `text` aloud; audio begins with the first sentence. */
+export async function speak(text: string, voice?: string, speed?: number) {
+  await stopSpeaking();
+  await ensureListeners();
+  await scheduler.resume();
+  starting = true;
+  try {
+    current = await invoke<string>('stream_speech', { text, voice, speed });
+    speaking.set(current);
+  } catch (err) {
+    lastError.set(err instanceof Error ? err.message : String(err));
+  } finally {
+    starting = false;
+    early.splice(0).forEach((handler) => handler());
+  }
+}
+
+export async function stopSpeaking() {
+  const id = current;
+  current = null;
+  speaking.set(null);
+  scheduler.stop();
+  if (id) {
+    await invoke('stop_speech', { id });
+  }
+}
@@ -9,6 +9,54 @@ export interface QueueItem {
   format: 'wav' | 'mp3';
   status: QueueStatus;
   output: string | null;
+  timings?: string | null;
+  project?: string | null;
+  index?: number | null;
+  listening?: ListeningState | null;
+  attempts?: number;
+  lastAttemptAt?: string | null;
+  nextRetryAt?: string | null;
+  error?: ItemError | null;
+}
+
+export type FailureKind =
+  | 'voiceMissing'
+  | 'binaryMissing'
+  | 'processCrash'
+  | 'timeout'
+  | 'transcode'
+  | 'io'
+  | 'other';
+
+export interface ItemError {
+  kind: FailureKind;
+  message: string;
+  at: string;
+}
+
+export interface ListeningState {
+  positionMs: number;
+  charOffset: number | null;
+  finished: boolean;
+  updatedAt: string;
+}
+
+export interface ResumePoint {
+  itemId: string;
+  positionMs: number;
+  charOffset: number | null;
+  updatedAt: string;
+}
+
+export interface Bookmark {
+  id: string;
+  itemId: string;
+  project: string | null;
+  name: string;
+  note: string | null;
+  positionMs: number;
+  charOffset: number | null;
+  createdAt: string;
 }
 
 export interface VoiceInfo {
@@ -16,9 +64,173 @@ export interface VoiceInfo {
   description?: string | null;
   language?: string | null;
   quality?: string | null;
-  model_path: string;
+  backend: string;
+  modelPath?: string | null;
+}
+
+export interface TtsCapabilities {
+  ssml: boolean;
+  speakers: boolean;
+  cached: boolean;
+}
+
+export interface TtsBackendInfo {
+  id: string;
+  capabilities: TtsCapabilities;
+  voices: VoiceInfo[];
+}
+
+export interface EspeakConfig {
+  enabled: boolean;
+  binaryPath: string;
+}
+
+export interface HotFolderConfig {
+  enabled: boolean;
+  inbox: string | null;
+  outbox: string | null;
+  voice: string | null;
+  rate: number;
+  format: 'wav' | 'mp3';
+  pollSecs: number;
+}
+
+export interface ServerConfig {
+  enabled: boolean;
+  port: number;
+  token: string;
+  voiceAliases: Record<string, string>;
+}
+
+export interface PcmAudio {
+  sampleRate: number;
+  channels: number;
+  /** Base64 of 16-bit little-endian samples. */
+  pcm: string;
+}
+
+export interface SpeechChunk extends PcmAudio {
+  id: string;
+}
+
+export type PlayerState = 'stopped' | 'playing' | 'paused';
+
+export interface PlaybackStatus {
+  state: PlayerState;
+  itemId: string | null;
+  positionMs: number;
+  durationMs: number;
+  volume: number;
+  speed: number;
+}
+
+export type PlayerCommand =
+  | { action: 'play'; itemId?: string; positionMs?: number }
+  | { action: 'pause' }
+  | { action: 'resume' }
+  | { action: 'stop' }
+  | { action: 'seek'; positionMs: number }
+  | { action: 'next' }
+  | { action: 'previous' }
+  | { action: 'setVolume'; volume: number }
+  | { action: 'setSpeed'; speed: number };
+
+export interface WyomingConfig {
+  enabled: boolean;
+  host: string;
+  port: number;
+  dictionary: string;
 }
 
 export interface AppConfig {
   export_dir: string;
+  hotFolder: HotFolderConfig;
+  server: ServerConfig;
+  wyoming: WyomingConfig;
+  espeak: EspeakConfig;
+}
+
+export interface CaptionOptions {
+  maxCueDurationMs?: number;
+  maxLineLength?: number;
+  maxLines?: number;
+}
+
+export interface TextSpan {
+  segmentIndex: number;
+  wordIndex: number | null;
+  charStart: number;
+  charEnd: number;
+  startMs: number;
+  endMs: number;
+  sentenceCharStart: number;
+  sentenceCharEnd: number;
+}
+
+export interface Chapter {
+  title: string | null;
+  text: string;
+}
+
+export interface DocumentMetadata {
+  title: string | null;
+  author: string | null;
+  language: string | null;
+  cover: { mediaType: string; data: string } | null;
+}
+
+export type TextEncoding =
+  | 'utf-8'
+  | 'utf-16le'
+  | 'utf-16be'
+  | 'windows-1252'
+  | 'iso-8859-1'
+  | 'iso-8859-15';
+
+export interface ImportedDocument {
+  metadata: DocumentMetadata;
+  chapters: Chapter[];
+  warnings: string[];
+  encoding: TextEncoding | null;
+}
+
+export interface SubtitleCue {
+  index: number;
+  startMs: number;
+  endMs: number;
+  text: string;
+}
+
+export interface DubbingOptions {
+  overrun?: 'warn' | 'speedUp';
+  maxSpeedup?: number;
+}
+
+export interface DubbingOutput {
+  path: string;
+  timings: string;
+  durationMs: number;
+  warnings: string[];
+}
+
+export interface ImportFolderOptions {
+  include?: string[];
+  exclude?: string[];
+  voice?: string;
+  rate?: number;
+  format?: 'wav' | 'mp3';
+}
+
+export interface FileImportResult {
+  path: string;
+  queued: number;
+  warnings: string[];
+  error: string | null;
+}
+
+export interface HotFolderEvent {
+  source: string;
+  movedTo: string | null;
+  outputs: string[];
+  error: string | null;
 }